@@ -2,30 +2,96 @@
 #![allow(clippy::needless_return)]
 
 use log::{error, info, warn};
-use std::fs::File;
-use std::io::{self, BufReader};
-use std::path::Path;
-use std::time::Duration;
+use std::collections::VecDeque;
+use std::env;
+use std::fs::{create_dir_all, File};
+use std::io::{self, BufReader, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use windows::Win32::UI::Input::KeyboardAndMouse::VK_C;
 
-use crate::utils::config::ClientConfig;
+use crate::utils::config::{
+    get_cluster_identity_file, get_cluster_ssh_options, ClientConfig, Cluster,
+};
 use crate::utils::constants::DEFAULT_SSH_USERNAME_KEY;
-use crate::utils::{get_console_input_buffer, get_console_title, set_console_title};
-use ssh2_config::{ParseRule, SshConfig};
+use crate::utils::ssh_config::get_proxy_arguments;
+use crate::utils::{
+    expand_environment_variables, get_console_input_buffer, get_console_output_buffer,
+    get_console_title, get_console_window_size, get_input_records_for_text,
+    matches_wildcard_pattern, parse_host_specification, set_console_font_size, set_console_title,
+};
+use ssh2_config::{HostParams, ParseRule, SshConfig};
+use tokio::io::{AsyncRead, AsyncReadExt};
 use tokio::net::windows::named_pipe::NamedPipeClient;
 use tokio::process::{Child, Command};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 use tokio::{io::Interest, net::windows::named_pipe::ClientOptions};
-use windows::Win32::Foundation::GetLastError;
+use windows::Win32::Foundation::{CloseHandle, FALSE};
 use windows::Win32::System::Console::{
-    GenerateConsoleCtrlEvent, WriteConsoleInputW, INPUT_RECORD, INPUT_RECORD_0, KEY_EVENT,
-    KEY_EVENT_RECORD, LEFT_ALT_PRESSED, RIGHT_ALT_PRESSED, SHIFT_PRESSED,
+    GenerateConsoleCtrlEvent, GetConsoleMode, SetConsoleMode, WriteConsoleInputW, CONSOLE_MODE,
+    COORD, ENABLE_VIRTUAL_TERMINAL_PROCESSING, INPUT_RECORD, INPUT_RECORD_0, KEY_EVENT,
+    KEY_EVENT_RECORD, LEFT_ALT_PRESSED, MOUSE_EVENT, RIGHT_ALT_PRESSED, SHIFT_PRESSED,
+    WINDOW_BUFFER_SIZE_EVENT, WINDOW_BUFFER_SIZE_RECORD,
 };
+use windows::Win32::System::Diagnostics::ToolHelp::{
+    CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W, TH32CS_SNAPPROCESS,
+};
+use windows::Win32::System::Threading::{OpenProcess, TerminateProcess, PROCESS_TERMINATE};
 
 use crate::{
-    serde::{deserialization::Deserialize, SERIALIZED_INPUT_RECORD_0_LENGTH},
-    utils::constants::{PIPE_NAME, PKG_NAME},
+    serde::{
+        deserialization::Deserialize, serialize_output_packet, BREAK_PACKET, DISABLED_PACKET,
+        ENABLED_PACKET, HEARTBEAT_TAG, KEEP_ALIVE_PACKET, MAX_OUTPUT_PACKET_LENGTH,
+        SERIALIZED_INPUT_RECORD_0_LENGTH, SHUTDOWN_PACKET,
+    },
+    utils::constants::PKG_NAME,
+    LaunchOptions,
 };
 
+mod onboarding;
+
+/// Number of consecutive failed console input writes after which the
+/// console is considered unusable.
+const MAX_CONSECUTIVE_CONSOLE_INPUT_WRITE_FAILURES: usize = 10;
+/// Interval in which the client signals the daemon that it is still responsive.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+/// Maximum number of output packets waiting to be sent to the daemon,
+/// further output is dropped until the daemon caught up.
+const MAX_PENDING_OUTPUT_PACKETS: usize = 1024;
+/// Time to wait before the first attempt to reconnect.
+const RECONNECT_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// Time after which a connection is considered stable, resetting the reconnect attempts.
+const RECONNECT_STABLE_DURATION: Duration = Duration::from_secs(10);
+/// Maximum time to wait for a message from the daemon before checking the SSH process again.
+const DAEMON_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Interval in which the console window is checked for size changes.
+const CONSOLE_RESIZE_POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// Number of keep-alive intervals without any message from the daemon
+/// after which it is considered gone.
+const MISSED_KEEP_ALIVES_UNTIL_DISCONNECT: u32 = 3;
+
+/// Placeholder in the `initial_command` replaced by the host name.
+const INITIAL_COMMAND_HOST_PLACEHOLDER: &str = "{{HOST}}";
+
+/// Placeholders in the `title_template`.
+const TITLE_PKG_PLACEHOLDER: &str = "{{PKG}}";
+const TITLE_USER_PLACEHOLDER: &str = "{{USER}}";
+const TITLE_HOST_PLACEHOLDER: &str = "{{HOST}}";
+const TITLE_INDEX_PLACEHOLDER: &str = "{{INDEX}}";
+
+/// Flag appended to the console title while the client doesn't receive the broadcasted input.
+const DISABLED_TITLE_FLAG: &str = " [DISABLED]";
+
+/// Whether the daemon currently doesn't broadcast its input to this client.
+static INPUT_DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Consumer of the input records received from the daemon.
+type InputRecordSink = fn(&[INPUT_RECORD]) -> io::Result<()>;
+
 enum ReadWriteResult {
     Success {
         remainder: Vec<u8>,
@@ -34,72 +100,444 @@ enum ReadWriteResult {
     WouldBlock,
     Err,
     Disconnect,
+    ConsoleInputUnusable,
+    /// The daemon requested an orderly shutdown.
+    Shutdown,
 }
 
-fn write_console_input(input_record: INPUT_RECORD_0) {
-    let buffer: [INPUT_RECORD; 1] = [INPUT_RECORD {
-        EventType: KEY_EVENT as u16,
-        Event: input_record,
-    }];
+/// Writes the given input records to the console input buffer in a single call,
+/// so that characters made up of multiple records (e.g. UTF-16 surrogate pairs)
+/// are never split up.
+fn write_console_input(input_records: &[INPUT_RECORD]) -> io::Result<()> {
+    if input_records.is_empty() {
+        return Ok(());
+    }
     let mut nb_of_events_written: u32 = 0;
-    match unsafe {
+    unsafe {
         WriteConsoleInputW(
             get_console_input_buffer(),
-            &buffer,
+            input_records,
             &mut nb_of_events_written,
         )
-    } {
-        Ok(_) => {
-            if nb_of_events_written == 0 {
-                error!("Failed to write console input");
-                error!("{:?}", unsafe { GetLastError() });
+    }?;
+    if nb_of_events_written as usize != input_records.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::WriteZero,
+            format!(
+                "Only {} of {} input records were written",
+                nb_of_events_written,
+                input_records.len()
+            ),
+        ));
+    }
+    return Ok(());
+}
+
+/// Prints a human readable description of all key presses
+/// in the given input records instead of writing them to the console input.
+fn print_input_records(input_records: &[INPUT_RECORD]) -> io::Result<()> {
+    for input_record in input_records {
+        if input_record.EventType == MOUSE_EVENT as u16 {
+            let mouse_event = unsafe { input_record.Event.MouseEvent };
+            println!(
+                "mouse at {},{} (button state 0x{:x}, event flags 0x{:x})",
+                mouse_event.dwMousePosition.X,
+                mouse_event.dwMousePosition.Y,
+                mouse_event.dwButtonState,
+                mouse_event.dwEventFlags
+            );
+            continue;
+        }
+        let key_event = unsafe { input_record.Event.KeyEvent };
+        if !key_event.bKeyDown.as_bool() {
+            continue;
+        }
+        let unicode_char = unsafe { key_event.uChar.UnicodeChar };
+        match char::from_u32(unicode_char as u32) {
+            Some(character) if !character.is_control() => {
+                println!("char `{}`", character);
+            }
+            _ => {
+                println!(
+                    "virtual key 0x{:02x} (char 0x{:x}, control key state 0x{:x})",
+                    key_event.wVirtualKeyCode, unicode_char, key_event.dwControlKeyState
+                );
             }
         }
-        Err(_) => {
-            error!("Failed to write console input");
-            error!("{:?}", unsafe { GetLastError() });
+    }
+    return Ok(());
+}
+
+/// Wraps the given key events into input records.
+fn to_key_input_records(key_events: Vec<INPUT_RECORD_0>) -> Vec<INPUT_RECORD> {
+    return key_events
+        .into_iter()
+        .map(|key_event| {
+            return INPUT_RECORD {
+                EventType: KEY_EVENT as u16,
+                Event: key_event,
+            };
+        })
+        .collect();
+}
+
+/// Returns the text typed to run the `initial_command` on `host`.
+fn get_initial_command_input(initial_command: &str, host: &str) -> String {
+    return format!(
+        "{}\r",
+        initial_command.replace(INITIAL_COMMAND_HOST_PLACEHOLDER, host)
+    );
+}
+
+/// Notifies the SSH process whenever the console window is resized, so it
+/// forwards the new size to the remote pty.
+///
+/// SSH learns about size changes via `WINDOW_BUFFER_SIZE_EVENT`s, which the console
+/// doesn't emit if only the visible window but not its screen buffer changed.
+fn forward_console_resizes() {
+    tokio::spawn(async move {
+        let mut previous_window_size = get_console_window_size().ok();
+        loop {
+            tokio::time::sleep(CONSOLE_RESIZE_POLL_INTERVAL).await;
+            let window_size = match get_console_window_size() {
+                Ok(window_size) => window_size,
+                Err(_) => continue,
+            };
+            if previous_window_size == Some(window_size) {
+                continue;
+            }
+            previous_window_size = Some(window_size);
+            let (columns, rows) = window_size;
+            if let Err(err) = write_console_input(&[INPUT_RECORD {
+                EventType: WINDOW_BUFFER_SIZE_EVENT as u16,
+                Event: INPUT_RECORD_0 {
+                    WindowBufferSizeEvent: WINDOW_BUFFER_SIZE_RECORD {
+                        dwSize: COORD {
+                            X: columns,
+                            Y: rows,
+                        },
+                    },
+                },
+            }]) {
+                warn!("Failed to forward the console size: {}", err);
+            }
         }
-    };
+    });
+}
+
+/// Returns whether the given key event carries the leading half
+/// of a UTF-16 surrogate pair.
+fn is_high_surrogate(key_event: &KEY_EVENT_RECORD) -> bool {
+    return (0xD800..=0xDBFF).contains(&unsafe { key_event.uChar.UnicodeChar });
+}
+
+/// Returns the username used to connect to `host`, taken from the SSH config
+/// if no username was given.
+///
+/// If the SSH config doesn't define a `User` for `host` either, the current Windows user
+/// (`%USERNAME%`) is used with `username_from_whoami`, otherwise the username is empty.
+fn get_username(
+    username: &str,
+    host: &str,
+    config: &ClientConfig,
+    username_from_whoami: bool,
+) -> String {
+    if username == DEFAULT_SSH_USERNAME_KEY {
+        return get_ssh_host_params(host, config)
+            .user
+            .or_else(|| {
+                if !username_from_whoami {
+                    return None;
+                }
+                return env::var("USERNAME").ok();
+            })
+            .unwrap_or_default();
+    }
+    return username.to_owned();
 }
 
 /// Use `username` or load the adequate one from SSH config.
 ///
 /// Returns `<username>@<host>`.
-fn get_username_and_host(username: &str, host: &str, config: &ClientConfig) -> String {
+fn get_username_and_host(
+    username: &str,
+    host: &str,
+    config: &ClientConfig,
+    username_from_whoami: bool,
+) -> String {
+    return format!(
+        "{}@{}",
+        get_username(username, host, config, username_from_whoami),
+        host
+    );
+}
+
+/// Returns the console title for the `title_template`, replacing the
+/// `{{PKG}}`, `{{USER}}`, `{{HOST}}` and `{{INDEX}}` placeholders.
+fn get_title_from_template(
+    title_template: &str,
+    username: &str,
+    host: &str,
+    index: usize,
+) -> String {
+    return title_template
+        .replace(TITLE_PKG_PLACEHOLDER, PKG_NAME)
+        .replace(TITLE_USER_PLACEHOLDER, username)
+        .replace(TITLE_HOST_PLACEHOLDER, host)
+        .replace(TITLE_INDEX_PLACEHOLDER, &(index + 1).to_string());
+}
+
+/// Returns the console title, flagged if the client doesn't receive the broadcasted input.
+fn get_client_console_title(title: &str, input_disabled: bool) -> String {
+    if input_disabled {
+        return format!("{}{}", title, DISABLED_TITLE_FLAG);
+    }
+    return title.to_owned();
+}
+
+/// Returns `<username>@<host>:<port>` as the client would connect to it,
+/// using the port given with the host, via cluster SSH options (`-p`),
+/// via `--port`, the SSH config or `22`.
+pub fn describe_connection(
+    username: &str,
+    host: &str,
+    config: &ClientConfig,
+    clusters: &Vec<Cluster>,
+    username_from_whoami: bool,
+    port: Option<u16>,
+) -> String {
+    let (host_username, hostname, host_port) = parse_host_specification(host);
+    let cluster_ssh_options = get_cluster_ssh_options(host, clusters);
+    let port = host_port
+        .or_else(|| {
+            return cluster_ssh_options
+                .iter()
+                .position(|option| return *option == "-p")
+                .and_then(|index| return cluster_ssh_options.get(index + 1).copied());
+        })
+        .map(|port| return port.to_owned())
+        .or_else(|| {
+            return port
+                .filter(|_| return !specifies_port(hostname, config, &cluster_ssh_options))
+                .map(|port| return port.to_string());
+        })
+        .or_else(|| {
+            return get_ssh_host_params(hostname, config)
+                .port
+                .map(|port| return port.to_string());
+        })
+        .unwrap_or_else(|| return "22".to_owned());
+    return format!(
+        "{}:{}",
+        get_username_and_host(
+            host_username.unwrap_or(username),
+            hostname,
+            config,
+            username_from_whoami
+        ),
+        port
+    );
+}
+
+/// Returns the parameters of the SSH config applying to `host`.
+///
+/// Falls back to the default parameters if the SSH config doesn't exist
+/// or can't be opened, only a malformed SSH config is considered fatal.
+fn get_ssh_host_params(host: &str, config: &ClientConfig) -> HostParams {
     let mut ssh_config = SshConfig::default();
 
     let ssh_config_path = Path::new(config.ssh_config_path.as_str());
 
-    if ssh_config_path.exists() {
-        let mut reader = BufReader::new(
-            File::open(ssh_config_path).expect("Could not open SSH configuration file."),
-        );
-        ssh_config = SshConfig::default()
-            .parse(&mut reader, ParseRule::ALLOW_UNKNOWN_FIELDS)
-            .expect("Failed to parse SSH configuration file");
+    match File::open(ssh_config_path) {
+        Ok(file) => {
+            let mut reader = BufReader::new(file);
+            ssh_config = SshConfig::default()
+                .parse(&mut reader, ParseRule::ALLOW_UNKNOWN_FIELDS)
+                .expect("Failed to parse SSH configuration file");
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            warn!(
+                "SSH config `{}` does not exist, using the default parameters",
+                ssh_config_path.display()
+            );
+        }
+        Err(err) => {
+            warn!(
+                "Failed to open SSH config `{}`, using the default parameters: {}",
+                ssh_config_path.display(),
+                err
+            );
+        }
     }
 
-    let host_specific_params = ssh_config.query(<&str>::clone(&host));
+    return ssh_config.query(<&str>::clone(&host));
+}
 
-    let username: String = if username == DEFAULT_SSH_USERNAME_KEY {
-        // FIXME: find a better default
-        host_specific_params.user.unwrap_or_default()
-    } else {
-        username.to_owned()
+/// Returns the connect timeout for `host`, the `ConnectTimeout` of the SSH config
+/// or else the configured `connect_timeout_secs` (`0` disables it).
+///
+/// Returns `None` if a `ConnectTimeout` is given in the program arguments or
+/// cluster SSH options, which SSH then uses as is.
+fn get_connect_timeout(
+    host: &str,
+    config: &ClientConfig,
+    cluster_ssh_options: &[&str],
+) -> Option<Duration> {
+    let (_, program_arguments) = get_program(host, config);
+    if program_arguments
+        .iter()
+        .map(|argument| return argument.as_str())
+        .chain(cluster_ssh_options.iter().copied())
+        .any(|argument| return argument.to_lowercase().contains("connecttimeout"))
+    {
+        return None;
+    }
+    return get_ssh_host_params(host, config)
+        .connect_timeout
+        .or_else(|| {
+            return config
+                .connect_timeout_secs
+                .filter(|connect_timeout_secs| return *connect_timeout_secs > 0)
+                .map(Duration::from_secs);
+        });
+}
+
+/// Returns the port to pass to SSH via `-p` for `host`, the `host_port` given with the host
+/// or else the `port` given via `--port`.
+///
+/// `--port` doesn't override a port given via the program arguments or cluster SSH options.
+fn get_port(
+    host_port: Option<&str>,
+    port: Option<u16>,
+    host: &str,
+    config: &ClientConfig,
+    cluster_ssh_options: &[&str],
+) -> Option<String> {
+    return host_port
+        .map(|host_port| return host_port.to_owned())
+        .or_else(|| {
+            return port
+                .filter(|_| return !specifies_port(host, config, cluster_ssh_options))
+                .map(|port| return port.to_string());
+        });
+}
+
+/// Whether the program arguments or cluster SSH options used for `host` already
+/// specify the port, as `-p <port>`, `-p<port>` or `Port=<port>` option.
+fn specifies_port(host: &str, config: &ClientConfig, cluster_ssh_options: &[&str]) -> bool {
+    let (_, program_arguments) = get_program(host, config);
+    return program_arguments
+        .iter()
+        .map(|argument| return argument.as_str())
+        .chain(cluster_ssh_options.iter().copied())
+        .any(|argument| {
+            let argument = argument.to_lowercase();
+            return argument == "-p"
+                || argument
+                    .strip_prefix("-p")
+                    .is_some_and(|port| return port.parse::<u16>().is_ok())
+                || argument
+                    .trim_start_matches("-o")
+                    .trim_start()
+                    .starts_with("port=");
+        });
+}
+
+/// Returns the program and arguments used to connect to `host`,
+/// those of the first matching `program_overrides` entry or the default ones.
+fn get_program<'a>(host: &str, config: &'a ClientConfig) -> (&'a str, &'a [String]) {
+    return match config
+        .program_overrides
+        .iter()
+        .find(|program_override| return matches_wildcard_pattern(&program_override.host, host))
+    {
+        Some(program_override) => (&program_override.program, &program_override.arguments),
+        None => (&config.program, &config.arguments),
     };
+}
 
-    return format!("{}@{}", username, host);
+/// Build the list of arguments handed to the SSH program.
+///
+/// Replaces the `username_host_placeholder` and prepends the
+/// `ConnectTimeout` option if a `connect_timeout` is given.
+/// The `command` to run non-interactively is appended as the last argument.
+///
+/// The cluster identity file and SSH options are prepended before all
+/// other arguments, as SSH uses the first value obtained for each option.
+fn get_ssh_arguments(
+    username_host: &str,
+    program_arguments: &[String],
+    config: &ClientConfig,
+    cluster_ssh_options: &[&str],
+    identity_file: Option<&str>,
+    connect_timeout: Option<Duration>,
+    command: Option<&str>,
+) -> Vec<String> {
+    let mut arguments: Vec<String> = Vec::new();
+    if let Some(identity_file) = identity_file {
+        arguments.push("-i".to_owned());
+        arguments.push(identity_file.to_owned());
+    }
+    arguments.extend(
+        cluster_ssh_options
+            .iter()
+            .map(|option| return option.to_string()),
+    );
+    if let Some(connect_timeout) = connect_timeout {
+        arguments.push("-o".to_owned());
+        arguments.push(format!("ConnectTimeout={}", connect_timeout.as_secs()));
+    }
+    arguments.extend(program_arguments.iter().map(|arg| {
+        return arg.replace(config.username_host_placeholder.as_str(), username_host);
+    }));
+    if let Some(command) = command {
+        arguments.push(command.to_owned());
+    }
+    return arguments;
 }
 
 /// Launch the SSH process.
 /// It might overwrite the console title once it launches, so we wait for that
 /// to happen and set the title again.
-async fn launch_ssh_process(username_host: &str, config: &ClientConfig) -> Child {
-    let arguments = config.arguments.clone().into_iter().map(|arg| {
-        return arg.replace(config.username_host_placeholder.as_str(), username_host);
-    });
-    let child = Command::new(&config.program)
+async fn launch_ssh_process(
+    username_host: &str,
+    hostname: &str,
+    config: &ClientConfig,
+    cluster_ssh_options: &[&str],
+    identity_file: Option<&str>,
+    connect_timeout: Option<Duration>,
+    environment: &[String],
+    command: Option<&str>,
+    output_sender: Option<UnboundedSender<Vec<u8>>>,
+) -> Child {
+    let (program, program_arguments) = get_program(hostname, config);
+    let mut arguments = get_ssh_arguments(
+        username_host,
+        program_arguments,
+        config,
+        cluster_ssh_options,
+        identity_file,
+        connect_timeout,
+        command,
+    );
+    let session_log = config
+        .session_log_dir
+        .as_ref()
+        .and_then(|session_log_dir| return open_session_log(session_log_dir, hostname));
+    let mut ssh_command = Command::new(program);
+    let pipe_output = session_log.is_some() || output_sender.is_some();
+    if pipe_output {
+        // The output is piped through the client, so SSH has to be forced to allocate a tty.
+        arguments.insert(0, "-tt".to_owned());
+        ssh_command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        enable_virtual_terminal_processing();
+    }
+    let arguments = arguments.into_iter();
+    let mut child = ssh_command
         .args(arguments.clone())
+        .envs(environment.iter().filter_map(|environment_variable| {
+            return environment_variable.split_once('=');
+        }))
         .spawn()
         .unwrap_or_else(|err| {
             let args: String =
@@ -107,15 +545,208 @@ async fn launch_ssh_process(username_host: &str, config: &ClientConfig) -> Child
             error!("{}", err);
             panic!(
                 "Failed to launch process `{}` with arguments `{}`",
-                config.program, args
+                program, args
             )
         });
+    if pipe_output {
+        let session_log = session_log.map(|session_log| return Arc::new(Mutex::new(session_log)));
+        if let Some(stdout) = child.stdout.take() {
+            tee_session_output(stdout, session_log.clone(), output_sender.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            tee_session_output(stderr, session_log, output_sender);
+        }
+    }
     return child;
 }
 
+/// Returns the path of the log of a session with `host` started at `timestamp`
+/// (e.g. `2024-05-01_12-30-00`) in `session_log_dir`.
+fn get_session_log_path(session_log_dir: &str, host: &str, timestamp: &str) -> PathBuf {
+    // IPv6 addresses contain characters not allowed in file names.
+    let host: String = host
+        .chars()
+        .map(|character| {
+            if character.is_alphanumeric() || "-_.".contains(character) {
+                return character;
+            }
+            return '_';
+        })
+        .collect();
+    return Path::new(session_log_dir).join(format!("{}_{}.log", host, timestamp));
+}
+
+/// Creates the log file of a new session with `host` in `session_log_dir`.
+///
+/// Returns `None` if it can't be created, the session is not logged then.
+fn open_session_log(session_log_dir: &str, host: &str) -> Option<File> {
+    let session_log_path = get_session_log_path(
+        session_log_dir,
+        host,
+        &chrono::offset::Utc::now()
+            .format("%Y-%m-%d_%H-%M-%S")
+            .to_string(),
+    );
+    return create_dir_all(session_log_dir)
+        .and_then(|_| return File::create(&session_log_path))
+        .map_err(|err| {
+            warn!(
+                "Failed to create session log `{}`, not logging the session: {}",
+                session_log_path.display(),
+                err
+            );
+            return err;
+        })
+        .ok();
+}
+
+/// Writes the `output` of the SSH process to the console, appends it to the `session_log`
+/// and passes it to the `output_sender` to stream it to the daemon.
+fn tee_session_output(
+    mut output: impl AsyncRead + Unpin + Send + 'static,
+    session_log: Option<Arc<Mutex<File>>>,
+    output_sender: Option<UnboundedSender<Vec<u8>>>,
+) {
+    tokio::spawn(async move {
+        let mut buf = [0u8; 4096];
+        loop {
+            let number_of_bytes = match output.read(&mut buf).await {
+                Ok(0) | Err(_) => return,
+                Ok(number_of_bytes) => number_of_bytes,
+            };
+            let mut stdout = io::stdout();
+            if let Err(err) = stdout
+                .write_all(&buf[..number_of_bytes])
+                .and_then(|_| return stdout.flush())
+            {
+                warn!("Failed to write the session output to the console: {}", err);
+            }
+            if let Some(session_log) = session_log.as_ref() {
+                if let Err(err) = session_log
+                    .lock()
+                    .unwrap()
+                    .write_all(&buf[..number_of_bytes])
+                {
+                    warn!("Failed to write the session log: {}", err);
+                }
+            }
+            if let Some(output_sender) = output_sender.as_ref() {
+                // The receiver is gone once the client exits.
+                let _ = output_sender.send(buf[..number_of_bytes].to_vec());
+            }
+        }
+    });
+}
+
+/// Lets the console interpret the escape sequences in the output of the SSH process,
+/// which it otherwise writes to the console itself.
+fn enable_virtual_terminal_processing() {
+    let output_buffer = get_console_output_buffer();
+    let mut mode = CONSOLE_MODE(0u32);
+    if let Err(err) = unsafe { GetConsoleMode(output_buffer, &mut mode) }.and_then(|_| {
+        return unsafe { SetConsoleMode(output_buffer, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) };
+    }) {
+        warn!("Failed to enable virtual terminal processing: {}", err);
+    }
+}
+
+/// Appends the bytes received from the daemon to the `internal_buffer` and hands
+/// all complete input records to the `sink`, handling the daemon's control packets.
+fn handle_received_input(
+    received: &[u8],
+    internal_buffer: &mut Vec<u8>,
+    break_sequence: &str,
+    console_input_write_failures: &mut usize,
+    sink: InputRecordSink,
+) -> ReadWriteResult {
+    internal_buffer.extend(received);
+    let iter = internal_buffer.chunks_exact(SERIALIZED_INPUT_RECORD_0_LENGTH);
+    let mut input_records: Vec<INPUT_RECORD> = Vec::new();
+    // Offsets of the deserialized input records in the internal buffer,
+    // `None` for the input records typing the break sequence.
+    let mut input_record_offsets: Vec<Option<usize>> = Vec::new();
+    for (index, serialzied_input_record) in iter.clone().enumerate() {
+        if serialzied_input_record == KEEP_ALIVE_PACKET {
+            // Just a keep alive packet from the daemon, ignore it
+            continue;
+        };
+        if serialzied_input_record == SHUTDOWN_PACKET {
+            // Input broadcasted before the shutdown still reaches the console.
+            if !input_records.is_empty() {
+                if let Err(err) = sink(&input_records) {
+                    error!(
+                        "Failed to write console input before shutting down: {}",
+                        err
+                    );
+                }
+            }
+            return ReadWriteResult::Shutdown;
+        }
+        if serialzied_input_record == DISABLED_PACKET || serialzied_input_record == ENABLED_PACKET {
+            INPUT_DISABLED.store(serialzied_input_record == DISABLED_PACKET, Ordering::SeqCst);
+            continue;
+        }
+        if serialzied_input_record == BREAK_PACKET {
+            let break_input_records =
+                to_key_input_records(get_input_records_for_text(break_sequence));
+            input_record_offsets
+                .resize(input_record_offsets.len() + break_input_records.len(), None);
+            input_records.extend(break_input_records);
+            continue;
+        }
+        input_records.push(INPUT_RECORD::deserialize(
+            &mut serialzied_input_record.to_owned(),
+        ));
+        input_record_offsets.push(Some(index * SERIALIZED_INPUT_RECORD_0_LENGTH));
+    }
+    let mut remainder = iter.remainder().to_vec();
+    // Characters outside of the basic multilingual plane consist of two
+    // UTF-16 code units, hold back the trailing high surrogate key events
+    // until the low surrogate arrived so both are written together.
+    while let (Some(last_input_record), Some(Some(offset))) =
+        (input_records.last(), input_record_offsets.last())
+    {
+        if last_input_record.EventType != KEY_EVENT as u16
+            || !is_high_surrogate(unsafe { &last_input_record.Event.KeyEvent })
+        {
+            break;
+        }
+        remainder = internal_buffer[*offset..].to_vec();
+        input_records.pop();
+        input_record_offsets.pop();
+    }
+    match sink(&input_records) {
+        Ok(_) => {
+            *console_input_write_failures = 0;
+        }
+        Err(err) => {
+            *console_input_write_failures += 1;
+            error!(
+                "Failed to write console input ({}/{}): {}",
+                console_input_write_failures, MAX_CONSECUTIVE_CONSOLE_INPUT_WRITE_FAILURES, err
+            );
+            if *console_input_write_failures >= MAX_CONSECUTIVE_CONSOLE_INPUT_WRITE_FAILURES {
+                return ReadWriteResult::ConsoleInputUnusable;
+            }
+        }
+    }
+    let key_event_records: Vec<KEY_EVENT_RECORD> = input_records
+        .iter()
+        .filter(|input_record| return input_record.EventType == KEY_EVENT as u16)
+        .map(|input_record| return unsafe { input_record.Event.KeyEvent })
+        .collect();
+    return ReadWriteResult::Success {
+        remainder,
+        key_event_records,
+    };
+}
+
 async fn read_write_loop(
     named_pipe_client: &NamedPipeClient,
     internal_buffer: &mut Vec<u8>,
+    break_sequence: &str,
+    console_input_write_failures: &mut usize,
+    sink: InputRecordSink,
 ) -> ReadWriteResult {
     let mut buf: [u8; SERIALIZED_INPUT_RECORD_0_LENGTH * 10] =
         [0; SERIALIZED_INPUT_RECORD_0_LENGTH * 10];
@@ -127,23 +758,13 @@ async fn read_write_loop(
             return ReadWriteResult::Disconnect;
         }
         Ok(n) => {
-            internal_buffer.extend(&mut buf[0..n].iter());
-            let iter = internal_buffer.chunks_exact(SERIALIZED_INPUT_RECORD_0_LENGTH);
-            let mut key_event_records: Vec<KEY_EVENT_RECORD> = Vec::new();
-            for serialzied_input_record in iter.clone() {
-                if serialzied_input_record == [u8::MAX; 18] {
-                    // Just a keep alive packet from the daemon, ignore it
-                    continue;
-                };
-                let input_record =
-                    INPUT_RECORD_0::deserialize(&mut serialzied_input_record.to_owned());
-                write_console_input(input_record);
-                key_event_records.push(unsafe { input_record.KeyEvent });
-            }
-            return ReadWriteResult::Success {
-                remainder: iter.remainder().to_vec(),
-                key_event_records,
-            };
+            return handle_received_input(
+                &buf[0..n],
+                internal_buffer,
+                break_sequence,
+                console_input_write_failures,
+                sink,
+            );
         }
         Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
             return ReadWriteResult::WouldBlock;
@@ -155,21 +776,49 @@ async fn read_write_loop(
     }
 }
 
-async fn run(child: &mut Child) {
+/// Returns the time without any message from the daemon after which it is considered gone.
+fn get_daemon_timeout(keep_alive_interval: Duration) -> Duration {
+    // Very short intervals must not cause a disconnect while merely polling the SSH process.
+    return keep_alive_interval.max(DAEMON_POLL_INTERVAL) * MISSED_KEEP_ALIVES_UNTIL_DISCONNECT;
+}
+
+/// Returns whether the SSH process most likely exited because
+/// the `connect_timeout` was reached.
+///
+/// OpenSSH exits with `255` if it fails to establish a connection.
+fn is_connect_timeout(
+    exit_code: i32,
+    elapsed: Duration,
+    connect_timeout: Option<Duration>,
+) -> bool {
+    return match connect_timeout {
+        Some(connect_timeout) => exit_code == 255 && elapsed >= connect_timeout,
+        None => false,
+    };
+}
+
+fn open_named_pipe_client(pipe_name: &str) -> NamedPipeClient {
     // Many clients trying to open the pipe at the same time can cause
     // a file not found error, so keep trying until we managed to open it
-    let named_pipe_client: NamedPipeClient = loop {
-        match ClientOptions::new().open(PIPE_NAME) {
+    loop {
+        match ClientOptions::new().open(pipe_name) {
             Ok(named_pipe_client) => {
-                break named_pipe_client;
+                return named_pipe_client;
             }
             Err(_) => {
                 continue;
             }
         }
-    };
-    let mut child_error = false;
+    }
+}
+
+/// Prints the input received from the daemon instead of forwarding it to an SSH process.
+///
+/// Used to verify what is being broadcasted without a real SSH target.
+async fn echo(pipe_name: &str, config: &ClientConfig) {
+    let named_pipe_client = open_named_pipe_client(pipe_name);
     let mut internal_buffer: Vec<u8> = Vec::new();
+    let mut console_input_write_failures: usize = 0;
     loop {
         named_pipe_client
             .ready(Interest::READABLE)
@@ -178,13 +827,206 @@ async fn run(child: &mut Child) {
                 error!("{}", err);
                 panic!("Named client pipe is not ready to be read",)
             });
+        match read_write_loop(
+            &named_pipe_client,
+            &mut internal_buffer,
+            &config.break_sequence,
+            &mut console_input_write_failures,
+            print_input_records,
+        )
+        .await
+        {
+            ReadWriteResult::Success { remainder, .. } => {
+                internal_buffer = remainder;
+            }
+            ReadWriteResult::WouldBlock | ReadWriteResult::Err => {
+                // Sleep some time to avoid hogging 100% CPU usage.
+                tokio::time::sleep(Duration::from_nanos(5)).await;
+            }
+            ReadWriteResult::Disconnect
+            | ReadWriteResult::ConsoleInputUnusable
+            | ReadWriteResult::Shutdown => {
+                break;
+            }
+        }
+    }
+}
 
-        match read_write_loop(&named_pipe_client, &mut internal_buffer).await {
+/// Reason the client stopped running the SSH process.
+enum RunResult {
+    /// The client is done, e.g. because the SSH process or the daemon exited.
+    Exited,
+    /// The SSH process failed and is to be relaunched.
+    Reconnect,
+}
+
+/// Returns whether the client exits right away once the SSH process exited with `exit_code`.
+///
+/// An interactive session ends with the exit code of its last command, `0` (successful),
+/// `1` (unsuccessful) or `130` (cancelled with Ctrl + C). A `--command` ends with any exit
+/// code except `255`, SSH's connection failure, which is reported like in an interactive session.
+fn exits_with_ssh_process(exit_code: i32, runs_command: bool) -> bool {
+    return matches!(exit_code, 0 | 1 | 130) || (runs_command && exit_code != 255);
+}
+
+/// Returns whether the SSH process that exited with `exit_code` after `connection_duration`
+/// is to be relaunched, given the number of `reconnect_attempts` made so far.
+///
+/// Only connection failures (OpenSSH exits with `255`) are retried and only with `--reconnect`,
+/// a connection that stayed up for a while is retried regardless of the previous attempts.
+fn should_reconnect(
+    exit_code: i32,
+    reconnect: bool,
+    config: &ClientConfig,
+    reconnect_attempts: u32,
+    connection_duration: Duration,
+) -> bool {
+    return reconnect
+        && exit_code == 255
+        && config.reconnect_attempts > 0
+        && (reconnect_attempts < config.reconnect_attempts
+            || connection_duration >= RECONNECT_STABLE_DURATION);
+}
+
+/// Waits `backoff` before reconnecting while keeping up the heartbeats,
+/// input broadcasted in the meantime is dropped.
+///
+/// Returns `false` if the daemon disconnected, so the client exits instead of reconnecting.
+async fn wait_for_reconnect(named_pipe_client: &NamedPipeClient, backoff: Duration) -> bool {
+    let start = Instant::now();
+    let mut last_heartbeat = start;
+    let mut buffer = [0_u8; 1024];
+    while start.elapsed() < backoff {
+        if last_heartbeat.elapsed() >= HEARTBEAT_INTERVAL {
+            // The content of the heartbeat is irrelevant, a full pipe is fine to skip.
+            let _ = named_pipe_client.try_write(&[HEARTBEAT_TAG]);
+            last_heartbeat = Instant::now();
+        }
+        match named_pipe_client.try_read(&mut buffer) {
+            Ok(0) => return false,
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            Err(_) => return false,
+        }
+    }
+    return true;
+}
+
+/// Sends the output of the SSH process received via the `output_receiver` to the daemon
+/// without waiting for the named pipe to become writable.
+///
+/// Output packets that can't be sent yet remain in `pending_output`.
+/// Returns whether any output was sent, which also serves as heartbeat.
+fn send_session_output(
+    named_pipe_client: &NamedPipeClient,
+    output_receiver: &mut UnboundedReceiver<Vec<u8>>,
+    pending_output: &mut VecDeque<Vec<u8>>,
+) -> bool {
+    while let Ok(output) = output_receiver.try_recv() {
+        for chunk in output.chunks(MAX_OUTPUT_PACKET_LENGTH) {
+            // Drop the output rather than exhausting the memory if the daemon can't keep up.
+            if pending_output.len() < MAX_PENDING_OUTPUT_PACKETS {
+                pending_output.push_back(serialize_output_packet(chunk));
+            }
+        }
+    }
+    let mut sent = false;
+    while let Some(packet) = pending_output.front_mut() {
+        match named_pipe_client.try_write(packet) {
+            Ok(number_of_bytes) if number_of_bytes == packet.len() => {
+                pending_output.pop_front();
+                sent = true;
+            }
+            Ok(number_of_bytes) => {
+                packet.drain(..number_of_bytes);
+                sent = true;
+            }
+            Err(err) => {
+                if err.kind() != io::ErrorKind::WouldBlock {
+                    warn!("Failed to send the session output: {}", err);
+                }
+                break;
+            }
+        }
+    }
+    return sent;
+}
+
+async fn run(
+    child: &mut Child,
+    named_pipe_client: &NamedPipeClient,
+    config: &ClientConfig,
+    connect_timeout: Option<Duration>,
+    reconnect: bool,
+    reconnect_attempts: u32,
+    keep_alive_interval: Duration,
+    runs_command: bool,
+    mut output_receiver: Option<&mut UnboundedReceiver<Vec<u8>>>,
+) -> RunResult {
+    let launch_time = Instant::now();
+    let mut child_error = false;
+    let mut internal_buffer: Vec<u8> = Vec::new();
+    let mut console_input_write_failures: usize = 0;
+    let mut last_heartbeat = Instant::now();
+    // Time any message, real input or keep-alive, was last received from the daemon.
+    let mut last_message = Instant::now();
+    let daemon_timeout = get_daemon_timeout(keep_alive_interval);
+    let mut pending_output: VecDeque<Vec<u8>> = VecDeque::new();
+    loop {
+        if let Some(output_receiver) = output_receiver.as_mut() {
+            if send_session_output(named_pipe_client, output_receiver, &mut pending_output) {
+                last_heartbeat = Instant::now();
+            }
+        }
+        // A heartbeat must not end up within a partially sent output packet.
+        if last_heartbeat.elapsed() >= HEARTBEAT_INTERVAL && pending_output.is_empty() {
+            // The content of the heartbeat is irrelevant, a full pipe is fine to skip.
+            if let Err(err) = named_pipe_client.try_write(&[HEARTBEAT_TAG]) {
+                if err.kind() != io::ErrorKind::WouldBlock {
+                    warn!("Failed to send heartbeat: {}", err);
+                }
+            }
+            last_heartbeat = Instant::now();
+        }
+        let read_write_result = match tokio::time::timeout(
+            DAEMON_POLL_INTERVAL,
+            named_pipe_client.ready(Interest::READABLE),
+        )
+        .await
+        {
+            Ok(ready) => {
+                ready.unwrap_or_else(|err| {
+                    error!("{}", err);
+                    panic!("Named client pipe is not ready to be read",)
+                });
+                read_write_loop(
+                    named_pipe_client,
+                    &mut internal_buffer,
+                    &config.break_sequence,
+                    &mut console_input_write_failures,
+                    write_console_input,
+                )
+                .await
+            }
+            Err(_) if last_message.elapsed() >= daemon_timeout => {
+                warn!(
+                    "Received nothing from the daemon for {:?}, considering it gone",
+                    daemon_timeout
+                );
+                ReadWriteResult::Disconnect
+            }
+            Err(_) => ReadWriteResult::WouldBlock,
+        };
+
+        match read_write_result {
             ReadWriteResult::Success {
                 remainder,
                 key_event_records,
             } => {
                 internal_buffer = remainder;
+                last_message = Instant::now();
                 if child_error {
                     for key_event in key_event_records.into_iter() {
                         if (key_event.dwControlKeyState & LEFT_ALT_PRESSED >= 1
@@ -192,7 +1034,7 @@ async fn run(child: &mut Child) {
                             && key_event.dwControlKeyState & SHIFT_PRESSED >= 1
                             && key_event.wVirtualKeyCode == VK_C.0
                         {
-                            return;
+                            return RunResult::Exited;
                         }
                     }
                 }
@@ -203,24 +1045,53 @@ async fn run(child: &mut Child) {
             }
             ReadWriteResult::Disconnect => {
                 warn!("Encountered disconnect when trying to read from named pipe");
+                if config.detach_on_daemon_loss {
+                    // The window keeps accepting local input, only the broadcast is gone.
+                    println!("\r\nLost connection to the daemon, the session stays usable locally");
+                    match child.wait().await {
+                        Ok(exit_status) => {
+                            info!("Detached application terminated: {}", exit_status)
+                        }
+                        Err(err) => error!("Failed to wait for the detached application: {}", err),
+                    }
+                }
+                break;
+            }
+            ReadWriteResult::ConsoleInputUnusable => {
+                error!("Console input is unusable, exiting");
+                break;
+            }
+            ReadWriteResult::Shutdown => {
+                info!("Received shutdown from the daemon, exiting");
                 break;
             }
         }
         match child.try_wait() {
             Ok(Some(exit_status)) => match exit_status.code().unwrap() {
-                0 | 1 | 130 => {
-                    // 0 -> last command successful
-                    // 1 -> last command unsuccessful
-                    // 130 -> last command cancelled (Ctrl + C)
-                    info!(
-                        "Application terminated, last exit code: {}",
-                        exit_status.code().unwrap()
-                    );
+                exit_code if exits_with_ssh_process(exit_code, runs_command) => {
+                    info!("Application terminated, last exit code: {}", exit_code);
                     break;
                 }
-                _ => {
+                exit_code => {
+                    if should_reconnect(
+                        exit_code,
+                        reconnect,
+                        config,
+                        reconnect_attempts,
+                        launch_time.elapsed(),
+                    ) {
+                        println!("SSH process exited: {exit_status}");
+                        return RunResult::Reconnect;
+                    }
                     if !child_error {
-                        println!("Failed to establish SSH connection: {exit_status}");
+                        if is_connect_timeout(exit_code, launch_time.elapsed(), connect_timeout) {
+                            println!(
+                                "Connection timed out after {} seconds",
+                                connect_timeout.unwrap_or_default().as_secs()
+                            );
+                        } else {
+                            println!("Failed to establish SSH connection: {exit_status}");
+                        }
                         println!("Shift-Alt-C to exit");
                         child_error = true;
                     }
@@ -232,33 +1103,975 @@ async fn run(child: &mut Child) {
             Err(e) => panic!("{}", e),
         }
     }
+    return RunResult::Exited;
+}
+
+/// Returns the ids of all (transitive) child processes of the process with the given id.
+fn get_descendant_process_ids(process_id: u32) -> Vec<u32> {
+    let snapshot = match unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) } {
+        Ok(snapshot) => snapshot,
+        Err(err) => {
+            error!("Failed to create process snapshot: {}", err);
+            return Vec::new();
+        }
+    };
+    // (process id, parent process id)
+    let mut processes: Vec<(u32, u32)> = Vec::new();
+    let mut process_entry = PROCESSENTRY32W {
+        dwSize: mem::size_of::<PROCESSENTRY32W>() as u32,
+        ..Default::default()
+    };
+    let mut result = unsafe { Process32FirstW(snapshot, &mut process_entry) };
+    while result.is_ok() {
+        processes.push((
+            process_entry.th32ProcessID,
+            process_entry.th32ParentProcessID,
+        ));
+        result = unsafe { Process32NextW(snapshot, &mut process_entry) };
+    }
+    let _ = unsafe { CloseHandle(snapshot) };
+
+    let mut descendant_process_ids: Vec<u32> = Vec::new();
+    let mut parent_process_ids: Vec<u32> = vec![process_id];
+    while let Some(parent_process_id) = parent_process_ids.pop() {
+        for (id, parent_id) in processes.iter() {
+            if *parent_id == parent_process_id
+                && *id != process_id
+                && !descendant_process_ids.contains(id)
+            {
+                descendant_process_ids.push(*id);
+                parent_process_ids.push(*id);
+            }
+        }
+    }
+    return descendant_process_ids;
+}
+
+/// Terminates the given child process and all of its descendants.
+fn terminate_process_tree(child: &mut Child) {
+    if let Some(process_id) = child.id() {
+        for descendant_process_id in get_descendant_process_ids(process_id) {
+            info!("Terminating process {}", descendant_process_id);
+            match unsafe { OpenProcess(PROCESS_TERMINATE, FALSE, descendant_process_id) } {
+                Ok(handle) => {
+                    if let Err(err) = unsafe { TerminateProcess(handle, 1) } {
+                        error!(
+                            "Failed to terminate process {}: {}",
+                            descendant_process_id, err
+                        );
+                    }
+                    let _ = unsafe { CloseHandle(handle) };
+                }
+                Err(err) => {
+                    error!("Failed to open process {}: {}", descendant_process_id, err);
+                }
+            }
+        }
+    }
+    info!("Terminating SSH process");
+    if let Err(err) = child.start_kill() {
+        error!("Failed to terminate SSH process: {}", err);
+    }
 }
 
-pub async fn main(host: String, username: String, config: &ClientConfig) {
-    let username_host = get_username_and_host(&username, &host, config);
-    let _username_host = username_host.clone();
+pub async fn main(
+    host: String,
+    username: String,
+    pipe_name: String,
+    config: &ClientConfig,
+    clusters: &Vec<Cluster>,
+    keep_alive_interval: Duration,
+    stream_output: bool,
+    index: usize,
+    options: &LaunchOptions,
+) {
+    let environment = &options.environment;
+    let command = options.command.as_deref();
+    if let Some(font_size) = config.font_size {
+        if let Err(err) = set_console_font_size(font_size) {
+            warn!(
+                "Failed to set the console font size to {}: {}",
+                font_size, err
+            );
+        }
+    }
+    if options.echo_only {
+        set_console_title(format!("{} - echo {}", PKG_NAME, host).as_str());
+        echo(&pipe_name, config).await;
+        return;
+    }
+    // `<user>@` and `:<port>` given with the host take precedence over the defaults.
+    let (host_username, hostname, host_port) = parse_host_specification(&host);
+    let username = get_username(
+        host_username.unwrap_or(&username),
+        hostname,
+        config,
+        options.username_from_whoami,
+    );
+    let username_host = format!("{}@{}", username, hostname);
+    let title = get_title_from_template(&config.title_template, &username, hostname, index);
     tokio::spawn(async move {
         loop {
             // Set the console title (child might overwrite it, so we have to keep checking it)
-            let console_title = format!("{} - {}", PKG_NAME, _username_host);
+            let console_title =
+                get_client_console_title(&title, INPUT_DISABLED.load(Ordering::SeqCst));
             if console_title != get_console_title() {
                 set_console_title(console_title.as_str());
             }
             tokio::time::sleep(Duration::from_millis(5)).await;
         }
     });
+    forward_console_resizes();
 
-    let mut child = launch_ssh_process(&username_host, config).await;
+    let mut cluster_ssh_options = get_cluster_ssh_options(&host, clusters);
+    let port = get_port(
+        host_port,
+        options.port,
+        hostname,
+        config,
+        &cluster_ssh_options,
+    );
+    if let Some(port) = port.as_deref() {
+        // SSH uses the first value obtained for each option.
+        cluster_ssh_options = ["-p", port]
+            .into_iter()
+            .chain(cluster_ssh_options)
+            .collect();
+    }
+    // Appended, so a proxy given via the cluster SSH options takes precedence.
+    let proxy_arguments = get_proxy_arguments(Path::new(&config.ssh_config_path), hostname);
+    cluster_ssh_options.extend(
+        proxy_arguments
+            .iter()
+            .map(|argument| return argument.as_str()),
+    );
+    let identity_file = get_cluster_identity_file(&host, clusters)
+        .map(expand_environment_variables)
+        .filter(|identity_file| {
+            if std::path::Path::new(identity_file).exists() {
+                return true;
+            }
+            // Don't fail, SSH might still authenticate through the agent or its config
+            warn!(
+                "Identity file `{}` of host `{}` does not exist, ignoring it",
+                identity_file, host
+            );
+            return false;
+        });
+    let connect_timeout = get_connect_timeout(hostname, config, &cluster_ssh_options);
+    let mut named_pipe_client: Option<NamedPipeClient> = None;
+    let mut reconnect_attempts: u32 = 0;
+    let mut reconnect_backoff = RECONNECT_INITIAL_BACKOFF;
+    let mut onboarded = false;
+    // Output streamed to the daemon for its monitor pane.
+    let (output_sender, mut output_receiver) = if stream_output {
+        let (output_sender, output_receiver) = mpsc::unbounded_channel();
+        (Some(output_sender), Some(output_receiver))
+    } else {
+        (None, None)
+    };
+    let mut child = loop {
+        let mut child = launch_ssh_process(
+            &username_host,
+            hostname,
+            config,
+            &cluster_ssh_options,
+            identity_file.as_deref(),
+            connect_timeout,
+            environment,
+            command,
+            output_sender.clone(),
+        )
+        .await;
+        let launch_time = Instant::now();
 
-    run(&mut child).await;
+        if let Some(initial_command) = config.initial_command.as_ref() {
+            if let Err(err) = write_console_input(&to_key_input_records(
+                get_input_records_for_text(&get_initial_command_input(initial_command, hostname)),
+            )) {
+                warn!("Failed to send the initial command: {}", err);
+            }
+        }
+        // Onboarding only runs for the first session, a reconnected session
+        // starts out at whatever prompt the SSH program leaves it at.
+        if !onboarded {
+            onboarded = true;
+            let failed_steps = onboarding::run_onboarding(
+                &config.onboarding,
+                &mut onboarding::ClientConsole,
+                onboarding::WAIT_FOR_TEXT_TIMEOUT,
+            )
+            .await;
+            if failed_steps > 0 {
+                warn!(
+                    "{} of {} onboarding steps failed for host `{}`",
+                    failed_steps,
+                    config.onboarding.len(),
+                    hostname
+                );
+            }
+        }
+
+        let named_pipe_client =
+            named_pipe_client.get_or_insert_with(|| return open_named_pipe_client(&pipe_name));
+        match run(
+            &mut child,
+            named_pipe_client,
+            config,
+            connect_timeout,
+            options.reconnect,
+            reconnect_attempts,
+            keep_alive_interval,
+            command.is_some(),
+            output_receiver.as_mut(),
+        )
+        .await
+        {
+            RunResult::Exited => break child,
+            RunResult::Reconnect => {}
+        }
+        if launch_time.elapsed() >= RECONNECT_STABLE_DURATION {
+            reconnect_attempts = 0;
+            reconnect_backoff = RECONNECT_INITIAL_BACKOFF;
+        }
+        reconnect_attempts += 1;
+        println!(
+            "Reconnecting in {} second(s), attempt {} of {}",
+            reconnect_backoff.as_secs(),
+            reconnect_attempts,
+            config.reconnect_attempts
+        );
+        if !wait_for_reconnect(named_pipe_client, reconnect_backoff).await {
+            info!("Lost connection to the daemon while waiting to reconnect, exiting");
+            break child;
+        }
+        reconnect_backoff = (reconnect_backoff * 2)
+            .min(Duration::from_secs(config.reconnect_backoff_cap_secs))
+            .max(RECONNECT_INITIAL_BACKOFF);
+    };
 
     // Make sure the client and all its subprocesses
     // are aware they need to shutdown.
-    unsafe {
-        GenerateConsoleCtrlEvent(0, 0).unwrap_or_else(|err| {
-            error!("{}", err);
-            panic!("Failed to send `ctrl + c` to remaining client windows",)
-        });
+    if let Err(err) = unsafe { GenerateConsoleCtrlEvent(0, 0) } {
+        error!(
+            "Failed to send `ctrl + c` to remaining client processes: {}",
+            err
+        );
+        info!("Falling back to terminating the SSH process tree");
+        terminate_process_tree(&mut child);
     }
     drop(child);
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::serde::serialization::Serialize;
+    use crate::utils::config::ProgramOverride;
+
+    thread_local! {
+        static WRITTEN_UNICODE_CHARS: RefCell<Vec<Vec<u16>>> = const { RefCell::new(Vec::new()) };
+    }
+
+    /// Console input stub recording the characters of each write.
+    fn record_console_input(input_records: &[INPUT_RECORD]) -> io::Result<()> {
+        WRITTEN_UNICODE_CHARS.with(|written| {
+            written.borrow_mut().push(
+                input_records
+                    .iter()
+                    .map(|input_record| {
+                        return unsafe { input_record.Event.KeyEvent.uChar.UnicodeChar };
+                    })
+                    .collect(),
+            );
+        });
+        return Ok(());
+    }
+
+    fn serialize_text(text: &str) -> Vec<u8> {
+        return get_input_records_for_text(text)
+            .iter()
+            .flat_map(|input_record| return input_record.serialize().into_vec())
+            .collect();
+    }
+
+    /// Feeds the given reads to the client as if received from the daemon
+    /// and returns the characters written to the console input per read.
+    fn receive(reads: &[&[u8]]) -> Vec<Vec<u16>> {
+        WRITTEN_UNICODE_CHARS.with(|written| written.borrow_mut().clear());
+        let mut internal_buffer: Vec<u8> = Vec::new();
+        let mut console_input_write_failures: usize = 0;
+        for received in reads {
+            match handle_received_input(
+                received,
+                &mut internal_buffer,
+                "",
+                &mut console_input_write_failures,
+                record_console_input,
+            ) {
+                ReadWriteResult::Success { remainder, .. } => {
+                    internal_buffer = remainder;
+                }
+                _ => panic!("Failed to handle the received input"),
+            }
+        }
+        return WRITTEN_UNICODE_CHARS.with(|written| return written.borrow().clone());
+    }
+
+    fn fail_console_input(_: &[INPUT_RECORD]) -> io::Result<()> {
+        return Err(io::Error::new(
+            io::ErrorKind::WriteZero,
+            "console input is full",
+        ));
+    }
+
+    #[test]
+    fn test_console_input_unusable_after_consecutive_write_failures() {
+        let serialized = serialize_text("a");
+        let mut console_input_write_failures: usize = 0;
+        for _ in 1..MAX_CONSECUTIVE_CONSOLE_INPUT_WRITE_FAILURES {
+            assert!(matches!(
+                handle_received_input(
+                    &serialized,
+                    &mut Vec::new(),
+                    "",
+                    &mut console_input_write_failures,
+                    fail_console_input,
+                ),
+                ReadWriteResult::Success { .. }
+            ));
+        }
+        assert!(matches!(
+            handle_received_input(
+                &serialized,
+                &mut Vec::new(),
+                "",
+                &mut console_input_write_failures,
+                fail_console_input,
+            ),
+            ReadWriteResult::ConsoleInputUnusable
+        ));
+    }
+
+    #[test]
+    fn test_successful_write_resets_console_input_write_failures() {
+        let serialized = serialize_text("a");
+        let mut console_input_write_failures = MAX_CONSECUTIVE_CONSOLE_INPUT_WRITE_FAILURES - 1;
+        handle_received_input(
+            &serialized,
+            &mut Vec::new(),
+            "",
+            &mut console_input_write_failures,
+            record_console_input,
+        );
+        assert_eq!(console_input_write_failures, 0);
+    }
+
+    #[test]
+    fn test_shutdown_packet_stops_the_client() {
+        WRITTEN_UNICODE_CHARS.with(|written| written.borrow_mut().clear());
+        let received = [KEEP_ALIVE_PACKET, SHUTDOWN_PACKET].concat();
+        assert!(matches!(
+            handle_received_input(&received, &mut Vec::new(), "", &mut 0, record_console_input),
+            ReadWriteResult::Shutdown
+        ));
+        assert!(WRITTEN_UNICODE_CHARS.with(|written| return written.borrow().is_empty()));
+    }
+
+    #[test]
+    fn test_input_before_shutdown_packet_is_written() {
+        WRITTEN_UNICODE_CHARS.with(|written| written.borrow_mut().clear());
+        let received = [serialize_text("ab"), SHUTDOWN_PACKET.to_vec()].concat();
+        assert!(matches!(
+            handle_received_input(&received, &mut Vec::new(), "", &mut 0, record_console_input),
+            ReadWriteResult::Shutdown
+        ));
+        assert_eq!(
+            WRITTEN_UNICODE_CHARS.with(|written| return written.borrow().clone()),
+            vec![vec![b'a' as u16, b'a' as u16, b'b' as u16, b'b' as u16]]
+        );
+    }
+
+    #[test]
+    fn test_disabled_and_enabled_packets_flag_the_console_title() {
+        let mut console_input_write_failures: usize = 0;
+        for (packet, input_disabled) in [(DISABLED_PACKET, true), (ENABLED_PACKET, false)] {
+            handle_received_input(
+                &packet,
+                &mut Vec::new(),
+                "",
+                &mut console_input_write_failures,
+                record_console_input,
+            );
+            assert_eq!(INPUT_DISABLED.load(Ordering::SeqCst), input_disabled);
+        }
+        assert_eq!(
+            get_client_console_title("csshw - user@host", true),
+            "csshw - user@host [DISABLED]"
+        );
+        assert_eq!(
+            get_client_console_title("csshw - user@host", false),
+            "csshw - user@host"
+        );
+    }
+
+    #[test]
+    fn test_keep_alive_packets_are_not_written() {
+        WRITTEN_UNICODE_CHARS.with(|written| written.borrow_mut().clear());
+        let received = [KEEP_ALIVE_PACKET, KEEP_ALIVE_PACKET].concat();
+        assert!(matches!(
+            handle_received_input(&received, &mut Vec::new(), "", &mut 0, record_console_input),
+            ReadWriteResult::Success { remainder, key_event_records }
+                if remainder.is_empty() && key_event_records.is_empty()
+        ));
+        assert_eq!(
+            WRITTEN_UNICODE_CHARS.with(|written| return written.borrow().clone()),
+            vec![Vec::<u16>::new()]
+        );
+    }
+
+    #[test]
+    fn test_get_initial_command_input_substitutes_host() {
+        // Enter is typed as carriage return, like in the console.
+        assert_eq!(
+            get_initial_command_input("cd /var/log && tail -f {{HOST}}.log", "web1"),
+            "cd /var/log && tail -f web1.log\r"
+        );
+        assert_eq!(
+            get_initial_command_input("echo {{HOST}} {{HOST}}", "web1"),
+            "echo web1 web1\r"
+        );
+        assert_eq!(get_initial_command_input("uptime", "web1"), "uptime\r");
+    }
+
+    #[test]
+    fn test_get_title_from_template_default() {
+        assert_eq!(
+            get_title_from_template(&ClientConfig::default().title_template, "alice", "web1", 0),
+            format!("{} - alice@web1", PKG_NAME)
+        );
+    }
+
+    #[test]
+    fn test_get_title_from_template_placeholders() {
+        // The index is shown starting at 1.
+        assert_eq!(
+            get_title_from_template("[{{INDEX}}] {{HOST}} ({{USER}})", "alice", "web1", 2),
+            "[3] web1 (alice)"
+        );
+        assert_eq!(
+            get_title_from_template("{{HOST}} {{HOST}}", "alice", "web1", 0),
+            "web1 web1"
+        );
+        assert_eq!(
+            get_title_from_template("production", "alice", "web1", 0),
+            "production"
+        );
+    }
+
+    #[test]
+    fn test_get_daemon_timeout() {
+        assert_eq!(
+            get_daemon_timeout(Duration::from_secs(1)),
+            Duration::from_secs(3)
+        );
+        // Polling the SSH process must not count as missed keep-alives.
+        assert_eq!(
+            get_daemon_timeout(Duration::from_millis(1)),
+            DAEMON_POLL_INTERVAL * MISSED_KEEP_ALIVES_UNTIL_DISCONNECT
+        );
+    }
+
+    #[test]
+    fn test_surrogate_pair_is_written_together() {
+        // U+1F600 is encoded as the surrogate pair 0xD83D 0xDE00,
+        // each typed as key down and key up record.
+        let serialized = serialize_text("\u{1F600}");
+        let high_surrogate_end = 2 * SERIALIZED_INPUT_RECORD_0_LENGTH;
+        for split in [
+            SERIALIZED_INPUT_RECORD_0_LENGTH,
+            high_surrogate_end,
+            high_surrogate_end + SERIALIZED_INPUT_RECORD_0_LENGTH / 2,
+        ] {
+            let written = receive(&[&serialized[..split], &serialized[split..]]);
+            assert_eq!(
+                written,
+                vec![vec![], vec![0xD83D, 0xD83D, 0xDE00, 0xDE00]],
+                "split after {split} bytes"
+            );
+        }
+    }
+
+    #[test]
+    fn test_basic_multilingual_plane_is_written_immediately() {
+        let serialized = serialize_text("a\u{1F600}");
+        let written = receive(&[
+            &serialized[..3 * SERIALIZED_INPUT_RECORD_0_LENGTH],
+            &serialized[3 * SERIALIZED_INPUT_RECORD_0_LENGTH..],
+        ]);
+        assert_eq!(
+            written,
+            vec![
+                vec![u16::from(b'a'), u16::from(b'a')],
+                vec![0xD83D, 0xD83D, 0xDE00, 0xDE00]
+            ]
+        );
+    }
+
+    /// Returns a client config without SSH config, so no host specific parameters apply.
+    fn client_config_without_ssh_config() -> ClientConfig {
+        return ClientConfig {
+            ssh_config_path: env::temp_dir()
+                .join("csshw-test-missing-ssh-config")
+                .to_string_lossy()
+                .into_owned(),
+            ..Default::default()
+        };
+    }
+
+    /// Returns the host parameters of `host` in an SSH config with the given content.
+    fn query_ssh_config(test_name: &str, ssh_config: &str, host: &str) -> HostParams {
+        let ssh_config_path = env::temp_dir().join(format!(
+            "csshw-test-ssh-config-{}-{}",
+            std::process::id(),
+            test_name
+        ));
+        std::fs::write(&ssh_config_path, ssh_config).unwrap();
+        let config = ClientConfig {
+            ssh_config_path: ssh_config_path.to_string_lossy().into_owned(),
+            ..Default::default()
+        };
+        let host_params = get_ssh_host_params(host, &config);
+        std::fs::remove_file(&ssh_config_path).unwrap();
+        return host_params;
+    }
+
+    #[test]
+    fn test_get_ssh_host_params_falls_back_without_ssh_config() {
+        let config = client_config_without_ssh_config();
+        let host_params = get_ssh_host_params("web1", &config);
+        assert_eq!(host_params.user, None);
+        assert_eq!(host_params.port, None);
+        assert_eq!(
+            get_username_and_host("alice", "web1", &config, false),
+            "alice@web1"
+        );
+        assert_eq!(
+            get_username_and_host(DEFAULT_SSH_USERNAME_KEY, "web1", &config, false),
+            "@web1"
+        );
+    }
+
+    #[test]
+    fn test_get_ssh_host_params_of_empty_ssh_config() {
+        let host_params = query_ssh_config("empty", "", "web1");
+        assert_eq!(host_params.user, None);
+        assert_eq!(host_params.port, None);
+    }
+
+    #[test]
+    fn test_get_ssh_host_params_of_ssh_config() {
+        let ssh_config = "Host web1\n    User bob\n    Port 2222\n";
+        let host_params = query_ssh_config("web1", ssh_config, "web1");
+        assert_eq!(host_params.user, Some("bob".to_owned()));
+        assert_eq!(host_params.port, Some(2222));
+        let host_params = query_ssh_config("web2", ssh_config, "web2");
+        assert_eq!(host_params.user, None);
+    }
+
+    #[test]
+    fn test_get_username_precedence() {
+        let ssh_config_path = env::temp_dir().join(format!(
+            "csshw-test-ssh-config-{}-username",
+            std::process::id()
+        ));
+        std::fs::write(&ssh_config_path, "Host web1\n    User bob\n").unwrap();
+        let config = ClientConfig {
+            ssh_config_path: ssh_config_path.to_string_lossy().into_owned(),
+            ..Default::default()
+        };
+        let usernames = [
+            get_username("alice", "web1", &config, true),
+            get_username(DEFAULT_SSH_USERNAME_KEY, "web1", &config, true),
+            get_username(DEFAULT_SSH_USERNAME_KEY, "web2", &config, true),
+            get_username(DEFAULT_SSH_USERNAME_KEY, "web2", &config, false),
+        ];
+        std::fs::remove_file(&ssh_config_path).unwrap();
+        // Explicit username > SSH config `User` > current Windows user > empty.
+        assert_eq!(
+            usernames,
+            [
+                "alice".to_owned(),
+                "bob".to_owned(),
+                env::var("USERNAME").unwrap_or_default(),
+                String::new()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get_connect_timeout_defaults_to_10_seconds() {
+        let mut config = client_config_without_ssh_config();
+        assert_eq!(
+            get_connect_timeout("web1", &config, &[]),
+            Some(Duration::from_secs(10))
+        );
+        config.connect_timeout_secs = Some(0);
+        assert_eq!(get_connect_timeout("web1", &config, &[]), None);
+        config.connect_timeout_secs = None;
+        assert_eq!(get_connect_timeout("web1", &config, &[]), None);
+    }
+
+    #[test]
+    fn test_get_connect_timeout_of_ssh_config() {
+        let ssh_config_path = env::temp_dir().join(format!(
+            "csshw-test-ssh-config-{}-connect-timeout",
+            std::process::id()
+        ));
+        std::fs::write(&ssh_config_path, "Host web1\n    ConnectTimeout 3\n").unwrap();
+        let config = ClientConfig {
+            ssh_config_path: ssh_config_path.to_string_lossy().into_owned(),
+            ..Default::default()
+        };
+        let connect_timeouts = (
+            get_connect_timeout("web1", &config, &[]),
+            get_connect_timeout("web2", &config, &[]),
+        );
+        std::fs::remove_file(&ssh_config_path).unwrap();
+        assert_eq!(
+            connect_timeouts,
+            (Some(Duration::from_secs(3)), Some(Duration::from_secs(10)))
+        );
+    }
+
+    #[test]
+    fn test_get_connect_timeout_keeps_given_option() {
+        let mut config = client_config_without_ssh_config();
+        assert_eq!(
+            get_connect_timeout("web1", &config, &["-o", "ConnectTimeout=5"]),
+            None
+        );
+        config.arguments = vec![
+            "-o".to_owned(),
+            "connecttimeout 5".to_owned(),
+            config.username_host_placeholder.clone(),
+        ];
+        assert_eq!(get_connect_timeout("web1", &config, &[]), None);
+    }
+
+    #[test]
+    fn test_get_ssh_arguments_contains_connect_timeout() {
+        let config = client_config_without_ssh_config();
+        assert_eq!(
+            get_ssh_arguments(
+                "bob@web1",
+                &config.arguments,
+                &config,
+                &["-p", "2222"],
+                Some("C:\\keys\\id_ed25519"),
+                Some(Duration::from_secs(10)),
+                None
+            ),
+            vec![
+                "-i",
+                "C:\\keys\\id_ed25519",
+                "-p",
+                "2222",
+                "-o",
+                "ConnectTimeout=10",
+                "-XY",
+                "bob@web1"
+            ]
+        );
+    }
+
+    /// Returns a client config with the given program overrides.
+    fn client_config_with_program_overrides(program_overrides: &[(&str, &str)]) -> ClientConfig {
+        let mut config = client_config_without_ssh_config();
+        config.program_overrides = program_overrides
+            .iter()
+            .map(|(host, program)| {
+                return ProgramOverride {
+                    host: host.to_string(),
+                    program: program.to_string(),
+                    arguments: vec!["-ssh".to_owned(), config.username_host_placeholder.clone()],
+                };
+            })
+            .collect();
+        return config;
+    }
+
+    #[test]
+    fn test_get_program_uses_matching_override() {
+        let config = client_config_with_program_overrides(&[("router*", "plink")]);
+        let (program, program_arguments) = get_program("router1", &config);
+        assert_eq!(program, "plink");
+        // The placeholder is replaced in the arguments of the override as well.
+        assert_eq!(
+            get_ssh_arguments(
+                "admin@router1",
+                program_arguments,
+                &config,
+                &[],
+                None,
+                None,
+                None
+            ),
+            vec!["-ssh", "admin@router1"]
+        );
+    }
+
+    #[test]
+    fn test_get_program_falls_back_to_default() {
+        let config = client_config_with_program_overrides(&[("router*", "plink")]);
+        let (program, program_arguments) = get_program("web1", &config);
+        assert_eq!(program, "ssh");
+        assert_eq!(program_arguments, config.arguments.as_slice());
+    }
+
+    #[test]
+    fn test_get_program_first_matching_override_takes_precedence() {
+        let config = client_config_with_program_overrides(&[
+            ("router1", "wrapper.cmd"),
+            ("router*", "plink"),
+        ]);
+        assert_eq!(get_program("router1", &config).0, "wrapper.cmd");
+        assert_eq!(get_program("router2", &config).0, "plink");
+    }
+
+    #[test]
+    fn test_is_connect_timeout() {
+        let connect_timeout = Some(Duration::from_secs(10));
+        assert!(is_connect_timeout(
+            255,
+            Duration::from_secs(10),
+            connect_timeout
+        ));
+        assert!(is_connect_timeout(
+            255,
+            Duration::from_secs(11),
+            connect_timeout
+        ));
+        // Failing earlier, e.g. because the host name can't be resolved.
+        assert!(!is_connect_timeout(
+            255,
+            Duration::from_secs(1),
+            connect_timeout
+        ));
+        assert!(!is_connect_timeout(
+            1,
+            Duration::from_secs(11),
+            connect_timeout
+        ));
+        assert!(!is_connect_timeout(255, Duration::from_secs(11), None));
+    }
+
+    #[test]
+    fn test_describe_connection_defaults_to_port_22() {
+        let config = client_config_without_ssh_config();
+        assert_eq!(
+            describe_connection("alice", "web1", &config, &vec![], false, None),
+            "alice@web1:22"
+        );
+    }
+
+    #[test]
+    fn test_describe_connection_port_precedence() {
+        let config = client_config_without_ssh_config();
+        let clusters = vec![Cluster {
+            name: "web".to_owned(),
+            hosts: vec!["web1".to_owned(), "web2:2222".to_owned()],
+            ssh_options: vec!["-p".to_owned(), "2200".to_owned()],
+            ..Default::default()
+        }];
+        assert_eq!(
+            describe_connection("alice", "db1", &config, &clusters, false, Some(2022)),
+            "alice@db1:2022"
+        );
+        // Cluster SSH options take precedence over `--port`.
+        assert_eq!(
+            describe_connection("alice", "web1", &config, &clusters, false, Some(2022)),
+            "alice@web1:2200"
+        );
+        // A port given with the host takes precedence over everything else.
+        assert_eq!(
+            describe_connection(
+                "alice",
+                "bob@web2:2222",
+                &config,
+                &clusters,
+                false,
+                Some(2022)
+            ),
+            "bob@web2:2222"
+        );
+    }
+
+    #[test]
+    fn test_get_ssh_arguments_contains_proxy_jump() {
+        let config = client_config_without_ssh_config();
+        let proxy_arguments = ["-J".to_owned(), "alice@bastion:2222".to_owned()];
+        let cluster_ssh_options: Vec<&str> = proxy_arguments
+            .iter()
+            .map(|argument| return argument.as_str())
+            .collect();
+        assert_eq!(
+            get_ssh_arguments(
+                "bob@web1",
+                &config.arguments,
+                &config,
+                &cluster_ssh_options,
+                None,
+                None,
+                None
+            ),
+            vec!["-J", "alice@bastion:2222", "-XY", "bob@web1"]
+        );
+    }
+
+    #[test]
+    fn test_get_ssh_arguments_appends_command_last() {
+        let config = client_config_without_ssh_config();
+        assert_eq!(
+            get_ssh_arguments(
+                "bob@web1",
+                &config.arguments,
+                &config,
+                &["-p", "2222"],
+                None,
+                Some(Duration::from_secs(10)),
+                Some("uptime && df -h")
+            ),
+            vec![
+                "-p",
+                "2222",
+                "-o",
+                "ConnectTimeout=10",
+                "-XY",
+                "bob@web1",
+                "uptime && df -h"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_exits_with_ssh_process() {
+        for exit_code in [0, 1, 130] {
+            assert!(exits_with_ssh_process(exit_code, false));
+            assert!(exits_with_ssh_process(exit_code, true));
+        }
+        // A failed command closes the client as well.
+        assert!(!exits_with_ssh_process(2, false));
+        assert!(exits_with_ssh_process(2, true));
+        // Connection failures stay open to report the error.
+        assert!(!exits_with_ssh_process(255, false));
+        assert!(!exits_with_ssh_process(255, true));
+    }
+
+    #[test]
+    fn test_get_port() {
+        let config = client_config_without_ssh_config();
+        assert_eq!(
+            get_port(None, Some(2222), "web1", &config, &[]),
+            Some("2222".to_owned())
+        );
+        assert_eq!(get_port(None, None, "web1", &config, &[]), None);
+        // The port given with the host takes precedence over `--port`.
+        assert_eq!(
+            get_port(Some("2200"), Some(2222), "web1", &config, &[]),
+            Some("2200".to_owned())
+        );
+        assert_eq!(
+            get_port(Some("2200"), None, "web1", &config, &[]),
+            Some("2200".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_get_port_keeps_specified_port() {
+        let mut config = client_config_without_ssh_config();
+        assert_eq!(
+            get_port(None, Some(2222), "web1", &config, &["-p", "2201"]),
+            None
+        );
+        config.arguments = vec![
+            "-p2201".to_owned(),
+            config.username_host_placeholder.clone(),
+        ];
+        assert_eq!(get_port(None, Some(2222), "web1", &config, &[]), None);
+    }
+
+    #[test]
+    fn test_specifies_port() {
+        let config = client_config_without_ssh_config();
+        assert!(!specifies_port("web1", &config, &[]));
+        assert!(specifies_port("web1", &config, &["-p", "2201"]));
+        assert!(specifies_port("web1", &config, &["-p2201"]));
+        assert!(specifies_port("web1", &config, &["-o", "Port=2201"]));
+        assert!(specifies_port("web1", &config, &["-oPort=2201"]));
+        // Other options starting with `-p` don't specify the port.
+        assert!(!specifies_port("web1", &config, &["-pv"]));
+    }
+
+    #[test]
+    fn test_get_session_log_path() {
+        assert_eq!(
+            get_session_log_path("C:\\logs", "web1.example.com", "2024-05-01_12-30-00"),
+            Path::new("C:\\logs").join("web1.example.com_2024-05-01_12-30-00.log")
+        );
+    }
+
+    #[test]
+    fn test_get_session_log_path_replaces_invalid_characters() {
+        assert_eq!(
+            get_session_log_path("C:\\logs", "fe80::1%eth0", "2024-05-01_12-30-00"),
+            Path::new("C:\\logs").join("fe80__1_eth0_2024-05-01_12-30-00.log")
+        );
+    }
+
+    #[test]
+    fn test_should_reconnect_only_on_connection_failure() {
+        let config = ClientConfig::default();
+        assert!(should_reconnect(255, true, &config, 0, Duration::ZERO));
+        for exit_code in [0, 1, 2, 127, 130] {
+            assert!(!should_reconnect(
+                exit_code,
+                true,
+                &config,
+                0,
+                Duration::ZERO
+            ));
+        }
+    }
+
+    #[test]
+    fn test_should_reconnect_requires_opt_in() {
+        let config = ClientConfig::default();
+        assert!(!should_reconnect(255, false, &config, 0, Duration::ZERO));
+        let config = ClientConfig {
+            reconnect_attempts: 0,
+            ..Default::default()
+        };
+        assert!(!should_reconnect(255, true, &config, 0, Duration::ZERO));
+    }
+
+    #[test]
+    fn test_should_reconnect_limits_attempts() {
+        let config = ClientConfig {
+            reconnect_attempts: 2,
+            ..Default::default()
+        };
+        assert!(should_reconnect(255, true, &config, 1, Duration::ZERO));
+        assert!(!should_reconnect(255, true, &config, 2, Duration::ZERO));
+        // A connection that stayed up counts as a fresh start.
+        assert!(should_reconnect(
+            255,
+            true,
+            &config,
+            2,
+            RECONNECT_STABLE_DURATION
+        ));
+    }
+}
@@ -0,0 +1,228 @@
+use std::io;
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use windows::Win32::System::Console::{
+    GetConsoleScreenBufferInfo, ReadConsoleOutputCharacterW, CONSOLE_SCREEN_BUFFER_INFO, COORD,
+};
+
+use super::{to_key_input_records, write_console_input};
+use crate::utils::config::OnboardingStep;
+use crate::utils::{get_console_output_buffer, get_input_records_for_text};
+
+/// Maximum time a `wait_for_text` step waits for its text before continuing with the next step.
+pub const WAIT_FOR_TEXT_TIMEOUT: Duration = Duration::from_secs(60);
+/// Interval in which the console is checked for the awaited text.
+const WAIT_FOR_TEXT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Console the onboarding sequence is executed against.
+pub trait OnboardingConsole {
+    /// Types the given text.
+    fn send(&mut self, text: &str) -> io::Result<()>;
+    /// Returns the text of the line the cursor is on.
+    fn read_cursor_line(&self) -> io::Result<String>;
+}
+
+/// The console of the client, shared with the SSH process.
+pub struct ClientConsole;
+
+impl OnboardingConsole for ClientConsole {
+    fn send(&mut self, text: &str) -> io::Result<()> {
+        return write_console_input(&to_key_input_records(get_input_records_for_text(text)));
+    }
+
+    fn read_cursor_line(&self) -> io::Result<String> {
+        let output_buffer = get_console_output_buffer();
+        let mut screen_buffer_info = CONSOLE_SCREEN_BUFFER_INFO::default();
+        unsafe { GetConsoleScreenBufferInfo(output_buffer, &mut screen_buffer_info) }?;
+        let mut line = vec![0_u16; screen_buffer_info.dwSize.X as usize];
+        let mut number_of_chars_read: u32 = 0;
+        unsafe {
+            ReadConsoleOutputCharacterW(
+                output_buffer,
+                &mut line,
+                COORD {
+                    X: 0,
+                    Y: screen_buffer_info.dwCursorPosition.Y,
+                },
+                &mut number_of_chars_read,
+            )
+        }?;
+        return Ok(String::from_utf16_lossy(
+            &line[..number_of_chars_read as usize],
+        ));
+    }
+}
+
+/// Executes the onboarding `steps` one after another.
+///
+/// Failing steps are logged and skipped, so a single unexpected prompt
+/// doesn't prevent the client from receiving the broadcasted input.
+///
+/// Returns the number of failed steps.
+pub async fn run_onboarding(
+    steps: &[OnboardingStep],
+    console: &mut impl OnboardingConsole,
+    wait_for_text_timeout: Duration,
+) -> usize {
+    let mut failed_steps = 0;
+    for step in steps {
+        info!("Onboarding step {:?}", step);
+        let succeeded = match step {
+            OnboardingStep::WaitForText(text) => {
+                wait_for_text(console, text, wait_for_text_timeout).await
+            }
+            OnboardingStep::Send(text) => match console.send(text) {
+                Ok(_) => true,
+                Err(err) => {
+                    warn!("Failed to send `{}`: {}", text, err);
+                    false
+                }
+            },
+            OnboardingStep::SleepMs(milliseconds) => {
+                tokio::time::sleep(Duration::from_millis(*milliseconds)).await;
+                true
+            }
+        };
+        if !succeeded {
+            failed_steps += 1;
+        }
+    }
+    return failed_steps;
+}
+
+/// Waits until the line of the console cursor contains the given `text`.
+///
+/// Returns whether the text appeared before the `timeout`.
+async fn wait_for_text(console: &impl OnboardingConsole, text: &str, timeout: Duration) -> bool {
+    let start = Instant::now();
+    loop {
+        match console.read_cursor_line() {
+            Ok(line) if line.contains(text) => return true,
+            Ok(_) => {}
+            Err(err) => {
+                warn!("Failed to read the console output: {}", err);
+                return false;
+            }
+        }
+        if start.elapsed() >= timeout {
+            warn!("Timed out waiting for `{}`, continuing", text);
+            return false;
+        }
+        tokio::time::sleep(WAIT_FOR_TEXT_POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+
+    /// Console returning the scripted cursor lines one after another and
+    /// recording the sent text.
+    #[derive(Default)]
+    struct FakeConsole {
+        cursor_lines: RefCell<VecDeque<io::Result<String>>>,
+        sent: Vec<String>,
+    }
+
+    impl FakeConsole {
+        fn with_cursor_lines(cursor_lines: Vec<io::Result<String>>) -> Self {
+            return FakeConsole {
+                cursor_lines: RefCell::new(cursor_lines.into()),
+                ..Default::default()
+            };
+        }
+    }
+
+    impl OnboardingConsole for FakeConsole {
+        fn send(&mut self, text: &str) -> io::Result<()> {
+            self.sent.push(text.to_owned());
+            return Ok(());
+        }
+
+        fn read_cursor_line(&self) -> io::Result<String> {
+            return self
+                .cursor_lines
+                .borrow_mut()
+                .pop_front()
+                .unwrap_or_else(|| return Ok(String::new()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_steps_type_the_text_in_order() {
+        let mut console = FakeConsole::default();
+        let steps = [
+            OnboardingStep::Send("sudo -i\r".to_owned()),
+            OnboardingStep::Send("cd /srv\r".to_owned()),
+        ];
+        assert_eq!(
+            run_onboarding(&steps, &mut console, WAIT_FOR_TEXT_TIMEOUT).await,
+            0
+        );
+        assert_eq!(console.sent, ["sudo -i\r", "cd /srv\r"]);
+    }
+
+    #[tokio::test]
+    async fn test_sleep_ms_step_waits() {
+        let start = Instant::now();
+        let steps = [OnboardingStep::SleepMs(50)];
+        assert_eq!(
+            run_onboarding(&steps, &mut FakeConsole::default(), WAIT_FOR_TEXT_TIMEOUT).await,
+            0
+        );
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_text_waits_until_the_text_appears() {
+        let mut console = FakeConsole::with_cursor_lines(vec![
+            Ok("Last login: Mon May  6 10:00:00 2024".to_owned()),
+            Ok("user@web1:~$ ".to_owned()),
+        ]);
+        let steps = [
+            OnboardingStep::WaitForText("$ ".to_owned()),
+            OnboardingStep::Send("sudo -i\r".to_owned()),
+        ];
+        assert_eq!(
+            run_onboarding(&steps, &mut console, WAIT_FOR_TEXT_TIMEOUT).await,
+            0
+        );
+        assert!(console.cursor_lines.borrow().is_empty());
+        assert_eq!(console.sent, ["sudo -i\r"]);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_text_times_out_and_continues() {
+        let mut console = FakeConsole::with_cursor_lines(vec![Ok("Password: ".to_owned())]);
+        let steps = [
+            OnboardingStep::WaitForText("$ ".to_owned()),
+            OnboardingStep::Send("sudo -i\r".to_owned()),
+        ];
+        assert_eq!(
+            run_onboarding(&steps, &mut console, Duration::from_millis(50)).await,
+            1
+        );
+        assert_eq!(console.sent, ["sudo -i\r"]);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_text_stops_on_read_error() {
+        let mut console = FakeConsole::with_cursor_lines(vec![
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "console closed")),
+            Ok("user@web1:~$ ".to_owned()),
+        ]);
+        let steps = [
+            OnboardingStep::WaitForText("$ ".to_owned()),
+            OnboardingStep::Send("sudo -i\r".to_owned()),
+        ];
+        assert_eq!(
+            run_onboarding(&steps, &mut console, WAIT_FOR_TEXT_TIMEOUT).await,
+            1
+        );
+        assert_eq!(console.cursor_lines.borrow().len(), 1);
+        assert_eq!(console.sent, ["sudo -i\r"]);
+    }
+}
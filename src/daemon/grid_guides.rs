@@ -0,0 +1,114 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::error;
+use windows::core::{w, PCWSTR};
+use windows::Win32::Foundation::{COLORREF, HINSTANCE, HWND, LPARAM, LRESULT, WPARAM};
+use windows::Win32::Graphics::Gdi::{CreateSolidBrush, DeleteObject};
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, PeekMessageW, RegisterClassW,
+    UnregisterClassW, MSG, PM_REMOVE, WNDCLASSW, WS_EX_NOACTIVATE, WS_EX_TOOLWINDOW, WS_EX_TOPMOST,
+    WS_EX_TRANSPARENT, WS_POPUP, WS_VISIBLE,
+};
+
+/// Time the grid guides are shown for.
+const GRID_GUIDES_DURATION: Duration = Duration::from_secs(3);
+/// Thickness of the lines outlining a grid cell.
+const GRID_GUIDE_THICKNESS: i32 = 3;
+/// Color (`0x00BBGGRR`) of the grid guides.
+const GRID_GUIDE_COLOR: COLORREF = COLORREF(0x00FFFF00);
+
+unsafe extern "system" fn grid_guide_window_procedure(
+    hwnd: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    return DefWindowProcW(hwnd, message, wparam, lparam);
+}
+
+/// Briefly outlines the given cells (`x`, `y`, `width`, `height`) with thin
+/// top most windows, without affecting any other window.
+pub fn show_grid_guides(cells: Vec<(i32, i32, i32, i32)>) {
+    // The windows belong to the creating thread, which therefore has to
+    // process their messages until they are destroyed.
+    thread::spawn(move || {
+        let instance: HINSTANCE = match unsafe { GetModuleHandleW(None) } {
+            Ok(module) => module.into(),
+            Err(err) => {
+                error!("Failed to get the module handle: {}", err);
+                return;
+            }
+        };
+        let brush = unsafe { CreateSolidBrush(GRID_GUIDE_COLOR) };
+        let class_name = w!("csshw_grid_guide");
+        let window_class = WNDCLASSW {
+            lpfnWndProc: Some(grid_guide_window_procedure),
+            hInstance: instance,
+            hbrBackground: brush,
+            lpszClassName: class_name,
+            ..Default::default()
+        };
+        if unsafe { RegisterClassW(&window_class) } == 0 {
+            error!("Failed to register the grid guide window class");
+            let _ = unsafe { DeleteObject(brush) };
+            return;
+        }
+
+        let mut guides: Vec<HWND> = Vec::new();
+        for (x, y, width, height) in cells {
+            for (line_x, line_y, line_width, line_height) in [
+                (x, y, width, GRID_GUIDE_THICKNESS),
+                (
+                    x,
+                    y + height - GRID_GUIDE_THICKNESS,
+                    width,
+                    GRID_GUIDE_THICKNESS,
+                ),
+                (x, y, GRID_GUIDE_THICKNESS, height),
+                (
+                    x + width - GRID_GUIDE_THICKNESS,
+                    y,
+                    GRID_GUIDE_THICKNESS,
+                    height,
+                ),
+            ] {
+                let guide = unsafe {
+                    CreateWindowExW(
+                        WS_EX_TOPMOST | WS_EX_TOOLWINDOW | WS_EX_NOACTIVATE | WS_EX_TRANSPARENT,
+                        class_name,
+                        PCWSTR::null(),
+                        WS_POPUP | WS_VISIBLE,
+                        line_x,
+                        line_y,
+                        line_width,
+                        line_height,
+                        None,
+                        None,
+                        instance,
+                        None,
+                    )
+                };
+                if guide.0 != 0 {
+                    guides.push(guide);
+                }
+            }
+        }
+
+        let start = Instant::now();
+        let mut message = MSG::default();
+        while start.elapsed() < GRID_GUIDES_DURATION {
+            while unsafe { PeekMessageW(&mut message, HWND(0), 0, 0, PM_REMOVE) }.as_bool() {
+                unsafe { DispatchMessageW(&message) };
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        for guide in guides {
+            let _ = unsafe { DestroyWindow(guide) };
+        }
+        let _ = unsafe { UnregisterClassW(class_name, instance) };
+        let _ = unsafe { DeleteObject(brush) };
+    });
+}
@@ -0,0 +1,33 @@
+use log::{debug, error, warn};
+use tokio::process::Command;
+
+/// Runs the given hook `command` in a detached task.
+///
+/// The first element of `command` is the program, the remaining elements
+/// are its arguments. An empty `command` means the hook is not configured.
+/// The `event` name and `context` are passed as environment variables.
+pub fn run_hook(event: &str, command: &[String], context: Vec<(&str, String)>) {
+    let (program, arguments) = match command.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let mut hook = Command::new(program);
+    hook.args(arguments).env("CSSHW_EVENT", event);
+    for (key, value) in context {
+        hook.env(key, value);
+    }
+    let event = event.to_owned();
+    tokio::spawn(async move {
+        match hook.status().await {
+            Ok(exit_status) if exit_status.success() => {
+                debug!("Hook `{}` finished successfully", event);
+            }
+            Ok(exit_status) => {
+                warn!("Hook `{}` failed: {}", event, exit_status);
+            }
+            Err(err) => {
+                error!("Failed to run hook `{}`: {}", event, err);
+            }
+        }
+    });
+}
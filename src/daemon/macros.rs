@@ -0,0 +1,75 @@
+use std::ffi::OsStr;
+use std::fs::{create_dir_all, read, read_dir, remove_file, write};
+use std::io;
+use std::path::PathBuf;
+
+use crate::serde::SERIALIZED_INPUT_RECORD_0_LENGTH;
+
+/// Directory (relative to the executable) the macros are stored in.
+const MACRO_DIRECTORY: &str = "macros";
+/// File extension of stored macros.
+const MACRO_EXTENSION: &str = "macro";
+
+/// Returns the path of the macro with the given name.
+///
+/// Names are restricted to alphanumeric characters, `-` and `_`,
+/// so a macro can't be stored outside of the macro directory.
+fn get_macro_path(name: &str) -> io::Result<PathBuf> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|character| return character.is_alphanumeric() || "-_".contains(character))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid macro name `{name}`, only use letters, digits, `-` and `_`"),
+        ));
+    }
+    return Ok(PathBuf::from(MACRO_DIRECTORY).join(format!("{name}.{MACRO_EXTENSION}")));
+}
+
+/// Returns the names of all stored macros in alphabetical order.
+pub fn list_macros() -> Vec<String> {
+    let mut names: Vec<String> = match read_dir(MACRO_DIRECTORY) {
+        Ok(entries) => entries
+            .filter_map(|entry| return entry.ok())
+            .map(|entry| return entry.path())
+            .filter(|path| return path.extension() == Some(OsStr::new(MACRO_EXTENSION)))
+            .filter_map(|path| {
+                return path
+                    .file_stem()
+                    .map(|name| return name.to_string_lossy().into_owned());
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    names.sort();
+    return names;
+}
+
+/// Stores the given serialized input records as macro, overwriting an existing one.
+pub fn save_macro(name: &str, serialized_input_records: &[u8]) -> io::Result<()> {
+    let path = get_macro_path(name)?;
+    create_dir_all(MACRO_DIRECTORY)?;
+    return write(path, serialized_input_records);
+}
+
+/// Loads the serialized input records of the given macro.
+pub fn load_macro(name: &str) -> io::Result<Vec<[u8; SERIALIZED_INPUT_RECORD_0_LENGTH]>> {
+    let content = read(get_macro_path(name)?)?;
+    if content.len() % SERIALIZED_INPUT_RECORD_0_LENGTH != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Macro `{name}` is corrupted"),
+        ));
+    }
+    return Ok(content
+        .chunks_exact(SERIALIZED_INPUT_RECORD_0_LENGTH)
+        .map(|serialized_input_record| return serialized_input_record.try_into().unwrap())
+        .collect());
+}
+
+/// Deletes the given macro.
+pub fn delete_macro(name: &str) -> io::Result<()> {
+    return remove_file(get_macro_path(name)?);
+}
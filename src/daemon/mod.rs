@@ -1,28 +1,47 @@
 #![deny(clippy::implicit_return)]
 #![allow(clippy::needless_return)]
 use std::cmp::max;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::os::windows::io::AsRawHandle;
+use std::path::Path;
 use std::{
     ffi::c_void,
-    io, mem,
-    sync::{Arc, Mutex},
-    time::Duration,
+    io::{self, Write},
+    mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
 };
 use std::{thread, time};
 
-use crate::utils::config::DaemonConfig;
+use crate::daemon::hooks::run_hook;
+use crate::utils::ansible::load_ansible_inventory;
+use crate::utils::config::{
+    expand_cidr_clusters, get_config_path, load_layout, resolve_cluster_tags, save_layout,
+    ActiveSet, Cluster, Config, ConfigOpt, DaemonConfig, DaemonPosition, FillDirection, Layout,
+    ProcessPriority,
+};
 use crate::utils::debug::StringRepr;
-use crate::utils::{clear_screen, set_console_color};
+use crate::utils::{clear_screen, natural_cmp, set_console_color};
 use crate::{
-    serde::{serialization::Serialize, SERIALIZED_INPUT_RECORD_0_LENGTH},
+    serde::{
+        deserialize_client_packets, serialization::Serialize, ClientPacket, BREAK_PACKET,
+        DISABLED_PACKET, ENABLED_PACKET, KEEP_ALIVE_PACKET, MAX_OUTPUT_PACKET_LENGTH,
+        SERIALIZED_INPUT_RECORD_0_LENGTH, SHUTDOWN_PACKET,
+    },
     spawn_console_process,
     utils::{
         arrange_console,
         constants::{DEFAULT_SSH_USERNAME_KEY, PIPE_NAME, PKG_NAME},
-        get_console_input_buffer, read_keyboard_input, set_console_border_color, set_console_title,
+        get_console_input_buffer, get_input_records_for_text, get_window_border_color,
+        read_keyboard_input, read_keyboard_or_mouse_input, set_console_border_color,
+        set_console_title, set_window_border_color,
     },
+    LaunchOptions,
 };
-use log::{debug, error, warn};
+use log::{debug, error, info, warn};
 use tokio::sync::broadcast::error::TryRecvError;
 use tokio::{
     net::windows::named_pipe::{NamedPipeServer, PipeMode, ServerOptions},
@@ -33,44 +52,200 @@ use windows::Win32::System::Com::{
     CoCreateInstance, CoInitializeEx, CLSCTX_ALL, COINIT_MULTITHREADED,
 };
 use windows::Win32::System::Console::{
-    CONSOLE_CHARACTER_ATTRIBUTES, INPUT_RECORD_0, LEFT_CTRL_PRESSED, RIGHT_CTRL_PRESSED,
+    CONSOLE_CHARACTER_ATTRIBUTES, INPUT_RECORD_0, KEY_EVENT_RECORD, LEFT_CTRL_PRESSED,
+    RIGHT_CTRL_PRESSED,
 };
+use windows::Win32::System::Pipes::GetNamedPipeClientProcessId;
+use windows::Win32::System::Threading::{GetExitCodeProcess, WaitForSingleObject};
 
+use windows::Win32::Graphics::Dwm::DWMWA_COLOR_DEFAULT;
 use windows::Win32::UI::Accessibility::{CUIAutomation, IUIAutomation};
 use windows::Win32::UI::Input::KeyboardAndMouse::{
-    VIRTUAL_KEY, VK_A, VK_C, VK_E, VK_ESCAPE, VK_H, VK_R, VK_T,
+    GetAsyncKeyState, GetDoubleClickTime, RegisterHotKey, MOD_ALT, MOD_CONTROL, MOD_NOREPEAT,
+    VIRTUAL_KEY, VK_0, VK_1, VK_9, VK_A, VK_B, VK_BACK, VK_C, VK_CAPITAL, VK_CONTROL, VK_D,
+    VK_DELETE, VK_DOWN, VK_E, VK_ESCAPE, VK_G, VK_H, VK_I, VK_L, VK_LBUTTON, VK_LEFT, VK_LWIN,
+    VK_M, VK_MENU, VK_N, VK_O, VK_P, VK_Q, VK_R, VK_RETURN, VK_RIGHT, VK_RWIN, VK_S, VK_SHIFT,
+    VK_SPACE, VK_T, VK_TAB, VK_U, VK_UP, VK_W, VK_Z,
 };
 use windows::Win32::UI::WindowsAndMessaging::GetWindowThreadProcessId;
 use windows::Win32::UI::WindowsAndMessaging::{
-    GetForegroundWindow, GetWindowPlacement, IsWindow, MoveWindow, SetForegroundWindow, ShowWindow,
-    SW_RESTORE, SW_SHOWMINIMIZED, WINDOWPLACEMENT,
+    FlashWindowEx, GetAncestor, GetCursorPos, GetForegroundWindow, GetMessageW, GetWindowPlacement,
+    GetWindowRect, IsIconic, IsWindow, IsWindowVisible, MoveWindow, PostMessageW, SendMessageW,
+    SetForegroundWindow, SetWindowPos, ShowWindow, WindowFromPoint, FLASHWINFO, FLASHW_CAPTION,
+    GA_ROOT, HTCAPTION, HWND_TOP, MSG, SWP_NOACTIVATE, SWP_NOMOVE, SWP_NOSIZE, SWP_NOZORDER,
+    SW_HIDE, SW_RESTORE, SW_SHOW, SW_SHOWMINIMIZED, SW_SHOWMINNOACTIVE, WINDOWPLACEMENT, WM_CLOSE,
+    WM_HOTKEY, WM_NCHITTEST,
 };
 use windows::Win32::{
-    Foundation::{BOOL, COLORREF, FALSE, HWND, LPARAM, TRUE},
+    Foundation::{
+        BOOL, COLORREF, FALSE, HANDLE, HWND, LPARAM, POINT, RECT, STILL_ACTIVE, TRUE,
+        WAIT_OBJECT_0, WPARAM,
+    },
     System::Console::{
-        GetConsoleMode, GetConsoleWindow, SetConsoleMode, CONSOLE_MODE, ENABLE_PROCESSED_INPUT,
+        GetConsoleMode, GetConsoleWindow, SetConsoleMode, CONSOLE_MODE, ENABLE_EXTENDED_FLAGS,
+        ENABLE_MOUSE_INPUT, ENABLE_PROCESSED_INPUT, ENABLE_QUICK_EDIT_MODE, INPUT_RECORD,
+        MOUSE_EVENT,
     },
     UI::WindowsAndMessaging::EnumWindows,
 };
 
+use self::monitor_pane::MonitorPane;
 use self::workspace::WorkspaceArea;
 
+mod grid_guides;
+mod hooks;
+mod macros;
+mod monitor_pane;
+mod probe;
+mod screenshot;
 mod workspace;
 
 const SENDER_CAPACITY: usize = 1024 * 1024;
+/// Number of times a panicked named pipe server routine is restarted before giving up.
+///
+/// A routine that keeps panicking is assumed to fail for good (e.g. due to a broken pipe),
+/// restarting it forever would only flood the logs.
+const MAX_NAMED_PIPE_SERVER_ROUTINE_RESTARTS: usize = 3;
+/// Time without control mode activity after which the daemon console is minimized
+/// if `auto_minimize_daemon` is enabled.
+const AUTO_MINIMIZE_DAEMON_DELAY: Duration = Duration::from_secs(10);
+/// Maximum time to wait for the already broadcasted input to be forwarded
+/// before changing which clients receive the input.
+const BROADCAST_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+/// Id of the global hotkey (Ctrl+Alt+A) toggling the hidden daemon console.
+const REVEAL_DAEMON_HOTKEY_ID: i32 = 1;
+/// Number of times the title bars of an activated active set's members flash.
+const ACTIVE_SET_HIGHLIGHT_FLASHES: u32 = 3;
+/// Border color of clients which didn't send a heartbeat in time.
+const UNRESPONSIVE_CLIENT_BORDER_COLOR: COLORREF = COLORREF(0x00FF00FF);
+/// Border color of the client window selected in control mode (cyan).
+const SELECTED_CLIENT_BORDER_COLOR: COLORREF = COLORREF(0x00FFFF00);
+/// Time the clients are given to exit on their own when quitting all of them.
+const QUIT_ALL_TIMEOUT: Duration = Duration::from_secs(3);
+/// Flag appended to the daemon console title while broadcasting is locked due to inactivity.
+const LOCKED_TITLE_FLAG: &str = " [LOCKED]";
+/// Interval in which the output of the clients is printed to the monitor pane.
+const MONITOR_PANE_PRINT_INTERVAL: Duration = Duration::from_millis(100);
 
+/// Whether a client receives the broadcasted input.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum PipeServerState {
+    Enabled,
+    Disabled,
+}
+
+/// Client console window together with the state of the client's process.
 #[derive(Clone)]
-struct ClientWindow {
+pub struct ClientWindow {
     hostname: String,
     hwnd: HWND,
+    process_id: u32,
+    process_handle: HANDLE,
+    /// Whether the client receives the broadcasted input.
+    ///
+    /// Stored with the client window and looked up by `process_id`, so it
+    /// follows the client when the windows are retiled, sorted or swapped.
+    state: PipeServerState,
+    /// Whether the client is excluded from broadcasting as the last focused client
+    /// while broadcasting to all others.
+    excluded: bool,
+    /// Point in time the client last signaled that it is responsive.
+    last_heartbeat: Instant,
 }
 
 struct Daemon<'a> {
     hosts: Vec<String>,
     username: Option<String>,
+    /// Clusters used to resolve cluster tags when creating new windows.
+    clusters: Vec<Cluster>,
     config: &'a DaemonConfig,
     control_mode_state: ControlModeState,
-    debug: bool,
+    /// Name of the named pipe used to communicate with this daemon's clients.
+    pipe_name: String,
+    /// Options of the session given on the command line.
+    options: LaunchOptions,
+    /// Active sets in order of definition, including those loaded from the layout in use.
+    active_sets: Vec<ActiveSet>,
+    /// Name of the currently activated active set, if any.
+    active_set_name: Option<String>,
+    /// Point in time of the last control mode activity.
+    last_control_mode_activity: Arc<Mutex<Instant>>,
+    /// Point in time of the last broadcasted input.
+    last_broadcast_activity: Arc<Mutex<Instant>>,
+    /// Whether broadcasting is locked due to inactivity.
+    locked: Arc<AtomicBool>,
+    /// Current console title, shared with the idle lock which flags it as locked.
+    title: Arc<Mutex<String>>,
+    /// Point in time of the last key-press in the daemon console.
+    last_key_press: Arc<Mutex<Instant>>,
+    /// Whether input is broadcasted to all clients except the last focused one.
+    broadcast_to_others: bool,
+    /// Whether broadcasting the typed input is paused.
+    frozen: bool,
+    /// Handle of the client window that was focused last, if any.
+    last_focused_client: Arc<Mutex<Option<HWND>>>,
+    /// Name and serialized input records of the macro currently being recorded, if any.
+    recorded_macro: Option<(String, Vec<u8>)>,
+    /// Index (among the existing client windows) of the client zoomed to the
+    /// full workspace area, if any.
+    zoomed_client: Option<usize>,
+    /// Index (among the existing client windows) of the client selected in control mode,
+    /// if a client selection is open.
+    selected_client: Option<usize>,
+    /// Original border color of the client window highlighted as selected.
+    selection_highlight: BorderHighlight,
+    /// What the open client selection is for.
+    client_selection: ClientSelection,
+    /// Output of the clients printed in the daemon console, if the monitor pane is enabled.
+    monitor_pane: Option<Arc<Mutex<MonitorPane>>>,
+}
+
+/// Purpose of a client selection in control mode, in which the arrow and digit keys
+/// move the selection and Enter or Space acts on the selected client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ClientSelection {
+    /// Toggle whether the selected client receives the broadcasted input.
+    EnableDisable,
+    /// Pick two clients one after another and swap their positions,
+    /// `first` is the index of the client picked first, if any.
+    Swap { first: Option<usize> },
+}
+
+/// Keeps track of the border color a highlighted window had before highlighting it,
+/// so it can be restored once the highlight moves on or is removed.
+#[derive(Default)]
+struct BorderHighlight {
+    /// Highlighted window and its border color before highlighting it.
+    highlighted_window: Option<(HWND, COLORREF)>,
+}
+
+impl BorderHighlight {
+    /// Moves the highlight to `hwnd`, whose current border color is `current_color`.
+    ///
+    /// Returns the previously highlighted window along with the border color to restore,
+    /// if the highlight moved away from it.
+    fn highlight(&mut self, hwnd: HWND, current_color: COLORREF) -> Option<(HWND, COLORREF)> {
+        if let Some((highlighted_window, _)) = self.highlighted_window {
+            if highlighted_window == hwnd {
+                // The current color is the highlight color, keep the original one.
+                return None;
+            }
+        }
+        return self.highlighted_window.replace((hwnd, current_color));
+    }
+
+    /// Whether `hwnd` is the highlighted window.
+    fn is_highlighted(&self, hwnd: HWND) -> bool {
+        return self
+            .highlighted_window
+            .is_some_and(|(highlighted_window, _)| return highlighted_window == hwnd);
+    }
+
+    /// Removes the highlight, returning the highlighted window along with
+    /// the border color to restore, if any.
+    fn clear(&mut self) -> Option<(HWND, COLORREF)> {
+        return self.highlighted_window.take();
+    }
 }
 
 #[derive(PartialEq, Debug)]
@@ -82,7 +257,7 @@ enum ControlModeState {
 
 impl Daemon<'_> {
     async fn launch(mut self) {
-        set_console_title(format!("{} daemon", PKG_NAME).as_str());
+        self.update_console_title();
         set_console_color(CONSOLE_CHARACTER_ATTRIBUTES(self.config.console_color));
         set_console_border_color(COLORREF(0x000000FF));
 
@@ -90,10 +265,40 @@ impl Daemon<'_> {
         // https://learn.microsoft.com/en-us/windows/console/ctrl-c-and-ctrl-break-signals
         disable_processed_input_mode();
 
-        let workspace_area =
-            workspace::get_workspace_area(workspace::Scaling::Logical, self.config.height);
+        let workspace_areas = get_daemon_workspace_areas(
+            self.config,
+            self.options.hidden_daemon,
+            self.options.monitor,
+            self.options.split_index,
+            self.options.split_count,
+        );
+
+        self.arrange_daemon_console(&workspace_areas, self.hosts.len());
+
+        if let Some((columns, rows)) = self.options.grid {
+            if columns * rows < self.hosts.len() {
+                warn!(
+                    "Grid {}x{} has fewer cells than the {} hosts, adding rows",
+                    columns,
+                    rows,
+                    self.hosts.len()
+                );
+            }
+        }
 
-        self.arrange_daemon_console(&workspace_area);
+        let workspace_grids = self.determine_workspace_grids(self.hosts.len(), &workspace_areas);
+        for workspace_grid in &workspace_grids {
+            debug!(
+                "Arranging {} clients in a {}x{} grid of {}x{} pixel cells (aspect ratio {:.2})",
+                workspace_grid.number_of_clients,
+                workspace_grid.grid_dimensions.columns,
+                workspace_grid.grid_dimensions.rows,
+                workspace_grid.grid_dimensions.cell_width,
+                workspace_grid.grid_dimensions.cell_height,
+                workspace_grid.workspace_area.width as f64
+                    / workspace_grid.workspace_area.height as f64
+            );
+        }
 
         // Looks like on windows 10 re-arranging the console resets the console output buffer
         set_console_color(CONSOLE_CHARACTER_ATTRIBUTES(self.config.console_color));
@@ -102,9 +307,10 @@ impl Daemon<'_> {
             launch_clients(
                 self.hosts.to_vec(),
                 &self.username,
-                self.debug,
-                &workspace_area,
-                self.config.aspect_ratio_adjustement,
+                &workspace_grids,
+                self.config,
+                &self.pipe_name,
+                &self.options,
             )
             .await,
         ));
@@ -113,44 +319,135 @@ impl Daemon<'_> {
         let _ = unsafe { SetForegroundWindow(GetConsoleWindow()) };
 
         self.print_instructions();
-        self.run(&mut client_console_window_handles, &workspace_area)
+        if self.options.hidden_daemon {
+            hide_daemon_console();
+            register_reveal_daemon_hotkey();
+        }
+        self.run(&mut client_console_window_handles, &workspace_areas)
             .await;
     }
 
     async fn run(
         &mut self,
         client_console_window_handles: &mut Arc<Mutex<BTreeMap<usize, ClientWindow>>>,
-        workspace_area: &workspace::WorkspaceArea,
+        workspace_areas: &[WorkspaceArea],
     ) {
         let (sender, _) =
             broadcast::channel::<[u8; SERIALIZED_INPUT_RECORD_0_LENGTH]>(SENDER_CAPACITY);
 
-        let mut servers = Arc::new(Mutex::new(self.launch_named_pipe_servers(&sender)));
+        let mut servers = Arc::new(Mutex::new(
+            self.launch_named_pipe_servers(&sender, client_console_window_handles),
+        ));
         let mut _server_clone: Arc<Mutex<Vec<JoinHandle<()>>>> = Arc::clone(&servers);
+        let exit_grace = Duration::from_secs(self.config.exit_grace_secs);
+        let _last_key_press = Arc::clone(&self.last_key_press);
 
         // FIXME: somehow we can't detect if the client consoles are being
         // closed from the outside ...
         tokio::spawn(async move {
+            // Whether the exit was cancelled during the grace period and no
+            // client was (re)started since.
+            let mut exit_cancelled = false;
             loop {
                 _server_clone.lock().unwrap().retain(|server| {
                     return !server.is_finished();
                 });
-                if _server_clone.lock().unwrap().is_empty() {
+                if !_server_clone.lock().unwrap().is_empty() {
+                    exit_cancelled = false;
+                } else if !exit_cancelled {
                     // All clients have exited, exit the daemon as well
-                    std::process::exit(0);
+                    if exit_grace.is_zero() {
+                        std::process::exit(0);
+                    }
+                    let grace_start = Instant::now();
+                    println!(
+                        "All clients exited, exiting in {} seconds (press any key to stay open)",
+                        exit_grace.as_secs()
+                    );
+                    while grace_start.elapsed() < exit_grace {
+                        if *_last_key_press.lock().unwrap() > grace_start {
+                            exit_cancelled = true;
+                            break;
+                        }
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                    }
+                    if !exit_cancelled {
+                        std::process::exit(0);
+                    }
+                    println!("Exit cancelled");
                 }
                 tokio::time::sleep(Duration::from_millis(5)).await;
             }
         });
 
         ensure_client_z_order_in_sync_with_daemon(client_console_window_handles.to_owned());
+        track_last_focused_client(
+            Arc::clone(client_console_window_handles),
+            Arc::clone(&self.last_focused_client),
+            self.config.focused_client_border_color.map(COLORREF),
+        );
+
+        if !self.config.on_client_double_clicked.is_empty() {
+            handle_client_title_double_clicks(
+                Arc::clone(client_console_window_handles),
+                self.config.on_client_double_clicked.clone(),
+            );
+        }
+
+        if self.config.auto_minimize_daemon {
+            auto_minimize_daemon_console(Arc::clone(&self.last_control_mode_activity));
+        }
+
+        if self.config.enforce_uniform_client_size {
+            keep_client_window_sizes_uniform(
+                Arc::clone(client_console_window_handles),
+                workspace_areas.to_vec(),
+                self.config.aspect_ratio_adjustement,
+                self.options.grid,
+                self.config.fill_direction,
+                (
+                    self.config.min_console_width,
+                    self.config.min_console_height,
+                ),
+                get_daemon_cell_position(self.config, self.options.hidden_daemon),
+            );
+        }
+
+        if let Some(client_heartbeat_timeout_secs) = self.config.client_heartbeat_timeout_secs {
+            detect_unresponsive_clients(
+                Arc::clone(client_console_window_handles),
+                Duration::from_secs(client_heartbeat_timeout_secs),
+            );
+        }
+
+        if let Some(idle_lock_secs) = self.config.idle_lock_secs {
+            lock_on_idle(
+                Duration::from_secs(idle_lock_secs),
+                Arc::clone(&self.last_broadcast_activity),
+                Arc::clone(&self.locked),
+                Arc::clone(&self.title),
+            );
+        }
+
+        if self.config.forward_mouse_events {
+            enable_mouse_input_mode();
+        }
+
+        if let Some(monitor_pane) = self.monitor_pane.as_ref() {
+            print_monitor_pane(Arc::clone(monitor_pane));
+        }
 
         loop {
+            let input_record = read_keyboard_or_mouse_input(self.config.forward_mouse_events);
+            if input_record.EventType == MOUSE_EVENT as u16 {
+                self.broadcast_mouse_event(&sender, input_record);
+                continue;
+            }
             self.handle_input_record(
                 &sender,
-                read_keyboard_input(),
+                input_record.Event,
                 client_console_window_handles,
-                workspace_area,
+                workspace_areas,
                 &mut servers,
             )
             .await;
@@ -160,10 +457,11 @@ impl Daemon<'_> {
     fn launch_named_pipe_servers(
         &self,
         sender: &Sender<[u8; SERIALIZED_INPUT_RECORD_0_LENGTH]>,
+        client_console_window_handles: &Arc<Mutex<BTreeMap<usize, ClientWindow>>>,
     ) -> Vec<JoinHandle<()>> {
         let mut servers: Vec<JoinHandle<()>> = Vec::new();
         for _ in &self.hosts {
-            self._launch_named_pipe_server(&mut servers, sender);
+            self._launch_named_pipe_server(&mut servers, sender, client_console_window_handles);
         }
         return servers;
     }
@@ -172,18 +470,37 @@ impl Daemon<'_> {
         &self,
         servers: &mut Vec<JoinHandle<()>>,
         sender: &Sender<[u8; SERIALIZED_INPUT_RECORD_0_LENGTH]>,
+        client_console_window_handles: &Arc<Mutex<BTreeMap<usize, ClientWindow>>>,
     ) {
         let named_pipe_server = ServerOptions::new()
             .access_outbound(true)
             .pipe_mode(PipeMode::Message)
-            .create(PIPE_NAME)
+            .create(&self.pipe_name)
             .unwrap_or_else(|err| {
                 error!("{}", err);
                 panic!("Failed to create named pipe server",)
             });
-        let mut receiver = sender.subscribe();
+        let receiver = sender.subscribe();
+        let _sender = sender.clone();
+        let _client_console_window_handles = Arc::clone(client_console_window_handles);
+        let keep_alive_interval = Duration::from_millis(self.config.keep_alive_interval_ms);
+        let unresponsive_client_timeout = self
+            .config
+            .client_heartbeat_timeout_secs
+            .filter(|_| return self.config.remove_unresponsive_clients)
+            .map(Duration::from_secs);
+        let monitor_pane = self.monitor_pane.clone();
         servers.push(tokio::spawn(async move {
-            named_pipe_server_routine(named_pipe_server, &mut receiver).await;
+            supervise_named_pipe_server_routine(
+                named_pipe_server,
+                receiver,
+                _sender,
+                _client_console_window_handles,
+                keep_alive_interval,
+                unresponsive_client_timeout,
+                monitor_pane,
+            )
+            .await;
         }));
     }
 
@@ -192,14 +509,59 @@ impl Daemon<'_> {
         sender: &Sender<[u8; SERIALIZED_INPUT_RECORD_0_LENGTH]>,
         input_record: INPUT_RECORD_0,
         client_console_window_handles: &mut Arc<Mutex<BTreeMap<usize, ClientWindow>>>,
-        workspace_area: &workspace::WorkspaceArea,
+        workspace_areas: &[WorkspaceArea],
         servers: &mut Arc<Mutex<Vec<JoinHandle<()>>>>,
     ) {
+        if unsafe { input_record.KeyEvent }.bKeyDown.as_bool() {
+            *self.last_key_press.lock().unwrap() = Instant::now();
+        }
+        if self.locked.load(Ordering::SeqCst) {
+            let key_event = unsafe { input_record.KeyEvent };
+            if key_event.bKeyDown.as_bool() && key_event.wVirtualKeyCode == VK_RETURN.0 {
+                *self.last_broadcast_activity.lock().unwrap() = Instant::now();
+                self.locked.store(false, Ordering::SeqCst);
+                self.update_console_title();
+                self.print_instructions();
+            }
+            return;
+        }
+        if self.zoomed_client.is_some() && self.control_mode_state == ControlModeState::Active {
+            let key_event = unsafe { input_record.KeyEvent };
+            if key_event.bKeyDown.as_bool() && key_event.wVirtualKeyCode == VK_ESCAPE.0 {
+                self.unzoom_client_windows(
+                    &client_console_window_handles.lock().unwrap(),
+                    workspace_areas,
+                );
+                self.quit_control_mode();
+                return;
+            }
+        }
         if self.control_mode_is_active(input_record) {
+            *self.last_control_mode_activity.lock().unwrap() = Instant::now();
             if self.control_mode_state == ControlModeState::Initiated {
+                if let Some(monitor_pane) = self.monitor_pane.as_ref() {
+                    monitor_pane.lock().unwrap().pause();
+                }
+                restore_daemon_console();
                 clear_screen();
-                println!("Control Mode (Esc to exit)");
-                println!("[c]reate window(s), [r]etile, copy active [h]ostname(s)");
+                run_hook(
+                    "on_control_mode_entered",
+                    &self.config.on_control_mode_entered,
+                    vec![],
+                );
+                println!("Control Mode (Esc to exit, Ctrl-A to send a literal Ctrl-A)");
+                println!(
+                    "[c]reate window(s), [r]etile, s[w]ap window(s), [e]nable/disable clients"
+                );
+                println!(
+                    "reope[n] exited clients, copy active [h]ostname(s), [t]oggle all clients"
+                );
+                println!("[d]efine active set, [1-9] switch active set, [0] enable all clients");
+                println!("broadcast to all [o]thers except the last focused client, show [g]rid");
+                println!("re[l]oad clusters, send [b]reak, [p]rint screenshot, [s]ort windows");
+                println!("[i]nput a line of text to all enabled clients, [q]uit all clients");
+                println!("start/stop recording a [m]acro, r[u]n, list or delete macros");
+                println!("[z]oom into a single client, [Space] freeze/unfreeze broadcasting");
                 self.control_mode_state = ControlModeState::Active;
                 return;
             }
@@ -208,23 +570,96 @@ impl Daemon<'_> {
                 return;
             }
             match VIRTUAL_KEY(key_event.wVirtualKeyCode) {
+                _ if self.selected_client.is_some() => {
+                    self.handle_client_selection_key(
+                        key_event.wVirtualKeyCode,
+                        &mut client_console_window_handles.lock().unwrap(),
+                    );
+                    if self.selected_client.is_none() {
+                        self.quit_control_mode();
+                    }
+                }
                 VK_R => {
+                    let foreground_window = unsafe { GetForegroundWindow() };
+                    self.restore_zoomed_client_windows(
+                        &client_console_window_handles.lock().unwrap(),
+                    );
                     self.rearrange_client_windows(
                         &client_console_window_handles.lock().unwrap(),
-                        workspace_area,
+                        workspace_areas,
+                    );
+                    self.arrange_daemon_console(
+                        workspace_areas,
+                        get_valid_client_windows(&client_console_window_handles.lock().unwrap())
+                            .len(),
                     );
-                    self.arrange_daemon_console(workspace_area);
+                    restore_foreground_window(foreground_window);
                 }
                 VK_E => {
-                    // TODO: Select windows
+                    // Input typed before toggling is delivered based on the previous state.
+                    wait_for_broadcast_drained(sender).await;
+                    // Stay in control mode to select and toggle clients via the keyboard.
+                    self.open_client_selection(
+                        ClientSelection::EnableDisable,
+                        &client_console_window_handles.lock().unwrap(),
+                    );
+                    if self.selected_client.is_none() {
+                        self.quit_control_mode();
+                    }
+                }
+                VK_Z => {
+                    if self.zoomed_client.is_some() {
+                        self.unzoom_client_windows(
+                            &client_console_window_handles.lock().unwrap(),
+                            workspace_areas,
+                        );
+                        self.quit_control_mode();
+                    } else {
+                        // Stay in control mode to cycle the zoomed client via the arrow keys.
+                        let last_focused_client = *self.last_focused_client.lock().unwrap();
+                        let index = get_valid_client_windows(
+                            &client_console_window_handles.lock().unwrap(),
+                        )
+                        .iter()
+                        .position(|client_window| {
+                            return Some(client_window.hwnd) == last_focused_client;
+                        })
+                        .unwrap_or(0);
+                        self.zoom_client_window(
+                            index,
+                            &client_console_window_handles.lock().unwrap(),
+                            workspace_areas,
+                        );
+                    }
+                }
+                VK_LEFT | VK_RIGHT if self.zoomed_client.is_some() => {
+                    let number_of_clients =
+                        get_valid_client_windows(&client_console_window_handles.lock().unwrap())
+                            .len();
+                    if let Some(zoomed_client) = self.zoomed_client {
+                        let index = get_next_zoomed_client(
+                            zoomed_client,
+                            number_of_clients,
+                            key_event.wVirtualKeyCode == VK_RIGHT.0,
+                        );
+                        self.zoom_client_window(
+                            index,
+                            &client_console_window_handles.lock().unwrap(),
+                            workspace_areas,
+                        );
+                    }
                 }
                 VK_T => {
-                    // TODO: trigger input on selected windows
+                    // Input typed before toggling is delivered based on the previous state.
+                    wait_for_broadcast_drained(sender).await;
+                    toggle_all_clients(&mut client_console_window_handles.lock().unwrap());
+                    self.active_set_name = None;
+                    self.quit_control_mode();
                 }
                 VK_C => {
                     clear_screen();
                     // TODO: make ESC abort
-                    println!("Hostname(s): (leave empty to abort)");
+                    println!("Hostname(s) or cluster tag(s): (leave empty to abort)");
                     disable_processed_input_mode(); // As it was disabled before, this enables it again
                     let mut hostnames = String::new();
                     match io::stdin().read_line(&mut hostnames) {
@@ -232,44 +667,76 @@ impl Daemon<'_> {
                             // Empty input (only newline '\n')
                         }
                         Ok(_) => {
-                            let new_clients = launch_clients(
-                                hostnames
-                                    .split(' ')
-                                    .map(|x| return x.trim().to_owned())
-                                    .collect(),
-                                &self.username,
-                                self.debug,
-                                workspace_area,
-                                self.config.aspect_ratio_adjustement,
+                            self.add_clients(
+                                resolve_cluster_tags(
+                                    hostnames.split_whitespace().collect(),
+                                    &self.clusters,
+                                )
+                                .into_iter()
+                                .map(|host| return host.to_owned())
+                                .collect(),
+                                sender,
+                                client_console_window_handles,
+                                workspace_areas,
+                                servers,
                             )
                             .await;
-                            let number_of_existing_client_console_window_handles =
-                                client_console_window_handles.lock().unwrap().len();
-                            for (index, client_window) in new_clients {
-                                client_console_window_handles.lock().unwrap().insert(
-                                    number_of_existing_client_console_window_handles + index + 1,
-                                    client_window,
-                                );
-                                self._launch_named_pipe_server(
-                                    &mut servers.lock().unwrap(),
-                                    sender,
-                                );
-                            }
                         }
                         Err(error) => {
                             error!("{error}");
                         }
                     }
                     disable_processed_input_mode();
+                    self.restore_zoomed_client_windows(
+                        &client_console_window_handles.lock().unwrap(),
+                    );
                     self.rearrange_client_windows(
                         &client_console_window_handles.lock().unwrap(),
-                        workspace_area,
+                        workspace_areas,
+                    );
+                    self.arrange_daemon_console(
+                        workspace_areas,
+                        get_valid_client_windows(&client_console_window_handles.lock().unwrap())
+                            .len(),
                     );
-                    self.arrange_daemon_console(workspace_area);
                     // Focus the daemon console again.
                     let _ = unsafe { SetForegroundWindow(GetConsoleWindow()) };
                     self.quit_control_mode();
                 }
+                VK_N => {
+                    let exited_hosts = get_exited_hosts(
+                        &self.hosts,
+                        &get_open_client_hostnames(&client_console_window_handles.lock().unwrap()),
+                    );
+                    if !exited_hosts.is_empty() {
+                        info!("Reopening the clients of {}", exited_hosts.join(" "));
+                        self.add_clients(
+                            exited_hosts,
+                            sender,
+                            client_console_window_handles,
+                            workspace_areas,
+                            servers,
+                        )
+                        .await;
+                        self.restore_zoomed_client_windows(
+                            &client_console_window_handles.lock().unwrap(),
+                        );
+                        self.rearrange_client_windows(
+                            &client_console_window_handles.lock().unwrap(),
+                            workspace_areas,
+                        );
+                        self.arrange_daemon_console(
+                            workspace_areas,
+                            get_valid_client_windows(
+                                &client_console_window_handles.lock().unwrap(),
+                            )
+                            .len(),
+                        );
+                        // Focus the daemon console again.
+                        let _ = unsafe { SetForegroundWindow(GetConsoleWindow()) };
+                    }
+                    self.quit_control_mode();
+                }
                 VK_H => {
                     let mut active_hostnames: Vec<String> = vec![];
                     for handle in client_console_window_handles.lock().unwrap().values() {
@@ -280,10 +747,143 @@ impl Daemon<'_> {
                     cli_clipboard::set_contents(active_hostnames.join(" ")).unwrap();
                     self.quit_control_mode();
                 }
+                VK_S => {
+                    sort_client_windows(&mut client_console_window_handles.lock().unwrap());
+                    self.rearrange_client_windows(
+                        &client_console_window_handles.lock().unwrap(),
+                        workspace_areas,
+                    );
+                    self.quit_control_mode();
+                }
+                VK_W => {
+                    // Stay in control mode to pick the clients to swap via the keyboard.
+                    self.open_client_selection(
+                        ClientSelection::Swap { first: None },
+                        &client_console_window_handles.lock().unwrap(),
+                    );
+                    if self.selected_client.is_none() {
+                        self.quit_control_mode();
+                    }
+                }
+                VK_L => {
+                    self.reload_clusters();
+                    self.quit_control_mode();
+                }
+                VK_P => {
+                    let valid_handles: Vec<HWND> = client_console_window_handles
+                        .lock()
+                        .unwrap()
+                        .values()
+                        .map(|client_window| return client_window.hwnd)
+                        .filter(|handle| return unsafe { IsWindow(*handle) }.as_bool())
+                        .collect();
+                    match screenshot::save_screenshot(
+                        &valid_handles,
+                        &WorkspaceArea::bounding(workspace_areas),
+                        &self.config.screenshot_directory,
+                    ) {
+                        Ok(path) => {
+                            info!("Saved screenshot to `{}`", path.display());
+                        }
+                        Err(err) => {
+                            error!("Failed to save screenshot: {}", err);
+                        }
+                    }
+                    self.quit_control_mode();
+                }
+                VK_B => {
+                    // The clients replace the break packet by their configured break sequence.
+                    if let Err(err) = sender.send(BREAK_PACKET) {
+                        error!("Failed to broadcast break: {}", err);
+                    }
+                    self.quit_control_mode();
+                }
+                VK_I => {
+                    self.broadcast_text(sender).await;
+                    self.quit_control_mode();
+                }
+                VK_Q => {
+                    quit_all(sender, &client_console_window_handles.lock().unwrap());
+                }
+                VK_D => {
+                    self.define_active_set(&client_console_window_handles.lock().unwrap());
+                    // Focus the daemon console again.
+                    let _ = unsafe { SetForegroundWindow(GetConsoleWindow()) };
+                    self.quit_control_mode();
+                }
+                VK_G => {
+                    self.show_grid_guides(
+                        &client_console_window_handles.lock().unwrap(),
+                        workspace_areas,
+                    );
+                    self.quit_control_mode();
+                }
+                VK_O => {
+                    self.broadcast_to_others = !self.broadcast_to_others;
+                    if !self.broadcast_to_others {
+                        for client_window in
+                            client_console_window_handles.lock().unwrap().values_mut()
+                        {
+                            client_window.excluded = false;
+                        }
+                    }
+                    self.update_console_title();
+                    self.quit_control_mode();
+                }
+                VK_SPACE => {
+                    self.frozen = !self.frozen;
+                    self.update_console_title();
+                    self.quit_control_mode();
+                }
+                VK_M => {
+                    self.toggle_macro_recording();
+                    self.update_console_title();
+                    self.quit_control_mode();
+                }
+                VK_U => {
+                    self.run_macro(sender);
+                    // Focus the daemon console again.
+                    let _ = unsafe { SetForegroundWindow(GetConsoleWindow()) };
+                    self.quit_control_mode();
+                }
+                VK_0 => {
+                    for client_window in client_console_window_handles.lock().unwrap().values_mut()
+                    {
+                        client_window.state = PipeServerState::Enabled;
+                    }
+                    self.active_set_name = None;
+                    self.quit_control_mode();
+                }
+                VIRTUAL_KEY(key_code) if (VK_1.0..=VK_9.0).contains(&key_code) => {
+                    self.switch_active_set(
+                        (key_code - VK_1.0) as usize,
+                        &mut client_console_window_handles.lock().unwrap(),
+                    );
+                    self.quit_control_mode();
+                }
                 _ => {}
             }
             return;
         }
+        if self.frozen {
+            return;
+        }
+        let key_event = unsafe { input_record.KeyEvent };
+        if self
+            .config
+            .swallowed_keys
+            .iter()
+            .any(|key_combination| return key_combination.matches(&key_event))
+        {
+            debug!("Swallowed key {:#x}", key_event.wVirtualKeyCode);
+            return;
+        }
+        if self.broadcast_to_others {
+            let last_focused_client = *self.last_focused_client.lock().unwrap();
+            for client_window in client_console_window_handles.lock().unwrap().values_mut() {
+                client_window.excluded = Some(client_window.hwnd) == last_focused_client;
+            }
+        }
         let _error_handler = |err| {
             error!("{}", err);
             panic!(
@@ -291,38 +891,63 @@ impl Daemon<'_> {
                 input_record.string_repr()
             )
         };
-        match sender.send(
+        let serialized_input_record: [u8; SERIALIZED_INPUT_RECORD_0_LENGTH] =
             input_record.serialize().as_mut_vec()[..]
                 .try_into()
-                .unwrap_or_else(_error_handler),
-        ) {
-            Ok(_) => {}
+                .unwrap_or_else(_error_handler);
+        match sender.send(serialized_input_record) {
+            Ok(_) => {
+                *self.last_broadcast_activity.lock().unwrap() = Instant::now();
+                if let Some((_, recorded_input_records)) = self.recorded_macro.as_mut() {
+                    recorded_input_records.extend_from_slice(&serialized_input_record);
+                }
+                if self.options.echo {
+                    echo_key_event(&key_event);
+                }
+            }
             Err(_) => {
                 thread::sleep(time::Duration::from_nanos(1));
             }
         }
     }
 
+    /// Broadcasts the given mouse event unless the daemon is locked, frozen or in control mode.
+    fn broadcast_mouse_event(
+        &self,
+        sender: &Sender<[u8; SERIALIZED_INPUT_RECORD_0_LENGTH]>,
+        input_record: INPUT_RECORD,
+    ) {
+        if self.locked.load(Ordering::SeqCst)
+            || self.frozen
+            || self.control_mode_state != ControlModeState::Inactive
+        {
+            return;
+        }
+        let serialized_input_record: [u8; SERIALIZED_INPUT_RECORD_0_LENGTH] =
+            input_record.serialize().as_mut_vec()[..]
+                .try_into()
+                .unwrap();
+        if sender.send(serialized_input_record).is_ok() {
+            *self.last_broadcast_activity.lock().unwrap() = Instant::now();
+        }
+    }
+
     fn control_mode_is_active(&mut self, input_record: INPUT_RECORD_0) -> bool {
         let key_event = unsafe { input_record.KeyEvent };
-        if self.control_mode_state == ControlModeState::Active {
-            if key_event.wVirtualKeyCode == VK_ESCAPE.0 {
+        let next_control_mode_state =
+            get_next_control_mode_state(&self.control_mode_state, &key_event);
+        if next_control_mode_state == ControlModeState::Inactive {
+            if self.control_mode_state == ControlModeState::Active {
                 self.quit_control_mode();
-                return false;
             }
-            return true;
-        }
-        if (key_event.dwControlKeyState & LEFT_CTRL_PRESSED >= 1
-            || key_event.dwControlKeyState & RIGHT_CTRL_PRESSED >= 1)
-            && key_event.wVirtualKeyCode == VK_A.0
-        {
-            self.control_mode_state = ControlModeState::Initiated;
-            return true;
+            return false;
         }
-        return false;
+        self.control_mode_state = next_control_mode_state;
+        return true;
     }
 
     fn quit_control_mode(&mut self) {
+        self.close_client_selection();
         self.print_instructions();
         self.control_mode_state = ControlModeState::Inactive;
     }
@@ -330,387 +955,3654 @@ impl Daemon<'_> {
     fn print_instructions(&self) {
         clear_screen();
         println!("Input to terminal: (Ctrl-A to enter control mode)");
-    }
-
-    fn rearrange_client_windows(
-        &self,
-        client_console_window_handles: &BTreeMap<usize, ClientWindow>,
-        workspace_area: &workspace::WorkspaceArea,
-    ) {
-        let mut valid_handles: Vec<HWND> = Vec::new();
-        for handle in client_console_window_handles.values() {
-            if unsafe { IsWindow(handle.hwnd).as_bool() } {
-                valid_handles.push(handle.hwnd);
-            }
+        if let Some(active_set_name) = &self.active_set_name {
+            println!("Active set: {}", active_set_name);
         }
-        for (index, handle) in valid_handles.iter().enumerate() {
-            arrage_client_window(
-                handle,
-                workspace_area,
-                index,
-                valid_handles.len(),
-                self.config.aspect_ratio_adjustement,
-            )
+        if self.broadcast_to_others {
+            println!("Broadcasting to all clients except the last focused one");
+        }
+        if self.zoomed_client.is_some() {
+            println!("Zoomed into a single client (Ctrl-A z to restore)");
+        }
+        if self.frozen {
+            println!("Broadcasting is frozen (Ctrl-A Space to resume)");
+        }
+        if let Some((name, _)) = &self.recorded_macro {
+            println!("Recording macro: {} (Ctrl-A m to stop)", name);
+        }
+        // The output received while in control mode is printed below the instructions.
+        if let Some(monitor_pane) = self.monitor_pane.as_ref() {
+            monitor_pane.lock().unwrap().resume();
         }
     }
 
-    fn arrange_daemon_console(&self, workspace_area: &WorkspaceArea) {
-        let (x, y, width, height) = get_console_rect(
-            0,
-            workspace_area.height,
-            workspace_area.width,
-            self.config.height,
-            workspace_area,
-        );
-        arrange_console(x, y, width, height);
+    fn console_title(&self) -> String {
+        let mut title = format!("{} daemon", PKG_NAME);
+        if self.broadcast_to_others {
+            title.push_str(" [OTHERS]");
+        }
+        if self.recorded_macro.is_some() {
+            title.push_str(" [REC]");
+        }
+        if self.frozen {
+            title.push_str(" [FROZEN]");
+        }
+        if self.locked.load(Ordering::SeqCst) {
+            title.push_str(LOCKED_TITLE_FLAG);
+        }
+        return title;
     }
-}
 
-fn arrage_client_window(
-    handle: &HWND,
-    workspace_area: &workspace::WorkspaceArea,
-    index: usize,
-    number_of_consoles: usize,
-    aspect_ratio_adjustment: f64,
-) {
-    let (x, y, width, height) = determine_client_spatial_attributes(
-        index as i32,
-        number_of_consoles as i32,
-        workspace_area,
-        aspect_ratio_adjustment,
-    );
-    unsafe {
-        MoveWindow(*handle, x, y, width, height, true).unwrap_or_else(|err| {
-            error!("{}", err);
-            panic!("Failed to move window",)
-        });
+    /// Sets the console title to reflect the current state.
+    fn update_console_title(&self) {
+        let title = self.console_title();
+        set_console_title(&title);
+        *self.title.lock().unwrap() = title;
     }
-}
 
-fn ensure_client_z_order_in_sync_with_daemon(
-    client_console_window_handles: Arc<Mutex<BTreeMap<usize, ClientWindow>>>,
-) {
-    tokio::spawn(async move {
-        let daemon_handle = unsafe { GetConsoleWindow() };
-        let mut previous_foreground_window = unsafe { GetForegroundWindow() };
-        loop {
-            tokio::time::sleep(Duration::from_millis(1)).await;
-            let foreground_window = unsafe { GetForegroundWindow() };
-            if previous_foreground_window == foreground_window {
-                continue;
+    /// Prompts for a line of text and types it, followed by Enter,
+    /// into all enabled clients.
+    ///
+    /// Long lines are typed in chunks of `broadcast_chunk_size` characters
+    /// with a pause in between.
+    async fn broadcast_text(&self, sender: &Sender<[u8; SERIALIZED_INPUT_RECORD_0_LENGTH]>) {
+        clear_screen();
+        println!("Text to send: (Enter to send, Esc to abort)");
+        let text = match read_line_or_escape() {
+            Some(text) => text,
+            None => return,
+        };
+        let chunks = split_into_chunks(&format!("{text}\r"), self.config.broadcast_chunk_size);
+        for (index, chunk) in chunks.iter().enumerate() {
+            if index > 0 {
+                tokio::time::sleep(Duration::from_millis(self.config.broadcast_chunk_pause_ms))
+                    .await;
             }
-            if foreground_window == daemon_handle
-                && !client_console_window_handles
-                    .lock()
-                    .unwrap()
-                    .values()
-                    .any(|client_handle| {
-                        return client_handle.hwnd == previous_foreground_window
-                            || client_handle.hwnd == daemon_handle;
-                    })
-            {
-                defer_windows(
-                    &client_console_window_handles.lock().unwrap(),
-                    &daemon_handle,
-                );
+            for input_record in get_input_records_for_text(chunk) {
+                if let Err(err) = sender.send(
+                    input_record.serialize().as_mut_vec()[..]
+                        .try_into()
+                        .unwrap(),
+                ) {
+                    error!("Failed to broadcast text: {}", err);
+                    return;
+                }
             }
-            previous_foreground_window = foreground_window;
         }
-    });
-}
+        *self.last_broadcast_activity.lock().unwrap() = Instant::now();
+    }
 
-fn defer_windows(
-    client_console_window_handles: &BTreeMap<usize, ClientWindow>,
+    /// Stops and saves the macro currently being recorded, or prompts for
+    /// a name and starts recording the broadcasted input as new macro.
+    fn toggle_macro_recording(&mut self) {
+        if let Some((name, recorded_input_records)) = self.recorded_macro.take() {
+            match macros::save_macro(&name, &recorded_input_records) {
+                Ok(_) => info!("Saved macro `{}`", name),
+                Err(err) => error!("Failed to save macro `{}`: {}", name, err),
+            }
+            return;
+        }
+        clear_screen();
+        println!("Name of the macro to record: (leave empty to abort)");
+        let name = match read_line() {
+            Some(input) => input.trim().to_owned(),
+            None => return,
+        };
+        if name.is_empty() {
+            return;
+        }
+        self.recorded_macro = Some((name, Vec::new()));
+    }
+
+    /// Lists the saved macros and prompts for one to replay to all enabled
+    /// clients, or to delete when prefixed with `-`.
+    fn run_macro(&self, sender: &Sender<[u8; SERIALIZED_INPUT_RECORD_0_LENGTH]>) {
+        clear_screen();
+        for name in macros::list_macros() {
+            println!("{}", name);
+        }
+        println!("Macro to run: (prefix with `-` to delete, leave empty to abort)");
+        let input = match read_line() {
+            Some(input) => input.trim().to_owned(),
+            None => return,
+        };
+        if input.is_empty() {
+            return;
+        }
+        if let Some(name) = input.strip_prefix('-') {
+            match macros::delete_macro(name) {
+                Ok(_) => info!("Deleted macro `{}`", name),
+                Err(err) => error!("Failed to delete macro `{}`: {}", name, err),
+            }
+            return;
+        }
+        match macros::load_macro(&input) {
+            Ok(serialized_input_records) => {
+                for serialized_input_record in serialized_input_records {
+                    if let Err(err) = sender.send(serialized_input_record) {
+                        error!("Failed to broadcast macro `{}`: {}", input, err);
+                        return;
+                    }
+                }
+                *self.last_broadcast_activity.lock().unwrap() = Instant::now();
+            }
+            Err(err) => error!("Failed to load macro `{}`: {}", input, err),
+        }
+    }
+
+    /// Reloads the clusters from the configuration file, so they can be used
+    /// when creating new windows without restarting.
+    fn reload_clusters(&mut self) {
+        match confy::load_path::<ConfigOpt>(get_config_path(self.options.profile.as_deref())) {
+            Ok(config_on_disk) => {
+                let config: Config = config_on_disk.into();
+                let mut clusters = config.clusters;
+                if let Some(ansible_inventory) = self.options.ansible_inventory.as_ref() {
+                    match load_ansible_inventory(Path::new(ansible_inventory)) {
+                        Ok(inventory_clusters) => clusters.extend(inventory_clusters),
+                        Err(err) => {
+                            error!("Failed to reload Ansible inventory: {}", err);
+                        }
+                    }
+                }
+                expand_cidr_clusters(&mut clusters);
+                info!("Reloaded {} cluster(s)", clusters.len());
+                self.clusters = clusters;
+            }
+            Err(err) => {
+                error!("Failed to reload clusters: {}", err);
+            }
+        }
+    }
+
+    /// Prompts for a name and a list of clients and stores them as active set.
+    ///
+    /// An existing active set with the same name is overwritten.
+    fn define_active_set(&mut self, client_console_window_handles: &BTreeMap<usize, ClientWindow>) {
+        clear_screen();
+        let valid_indices = print_client_list(client_console_window_handles);
+        for (number, active_set) in self.active_sets.iter().enumerate() {
+            println!(
+                "Active set {}: {} ({})",
+                number + 1,
+                active_set.name,
+                active_set.hostnames.join(" ")
+            );
+        }
+        println!("Active set to define: (e.g. `web 1 2 3`, leave empty to abort)");
+        let input = match read_line() {
+            Some(input) => input,
+            None => return,
+        };
+        let mut parts = input.split_whitespace();
+        let name = match parts.next() {
+            Some(name) => name.to_owned(),
+            None => return,
+        };
+        let hostnames: Vec<String> = parse_client_numbers(parts, &valid_indices)
+            .iter()
+            .map(|index| return client_console_window_handles[index].hostname.clone())
+            .collect();
+        if hostnames.is_empty() {
+            warn!("No valid clients selected for active set `{}`", name);
+            return;
+        }
+        match self
+            .active_sets
+            .iter_mut()
+            .find(|active_set| return active_set.name == name)
+        {
+            Some(active_set) => active_set.hostnames = hostnames,
+            None => self.active_sets.push(ActiveSet { name, hostnames }),
+        }
+        self.save_active_sets();
+    }
+
+    /// Saves the active sets to the layout in use, so they are available
+    /// the next time the layout is used.
+    fn save_active_sets(&self) {
+        self.update_layout(|layout| layout.active_sets = self.active_sets.clone());
+    }
+
+    /// Applies `update` to the layout in use and saves it, if a layout is in use.
+    fn update_layout<F: FnOnce(&mut Layout)>(&self, update: F) {
+        let layout_name = match self.options.layout.as_ref() {
+            Some(layout_name) => layout_name,
+            None => return,
+        };
+        let mut layout = match load_layout(layout_name) {
+            Ok(layout) => layout,
+            Err(err) => {
+                error!("Failed to update the layout: {}", err);
+                return;
+            }
+        };
+        update(&mut layout);
+        if let Err(err) = save_layout(layout_name, &layout) {
+            error!("Failed to save layout `{}`: {}", layout_name, err);
+        }
+    }
+
+    /// Enables all clients that are part of the active set at position `index`
+    /// and disables all others.
+    fn switch_active_set(
+        &mut self,
+        index: usize,
+        client_console_window_handles: &mut BTreeMap<usize, ClientWindow>,
+    ) {
+        let active_set = match self.active_sets.get(index) {
+            Some(active_set) => active_set,
+            None => return,
+        };
+        for client_window in client_console_window_handles.values_mut() {
+            client_window.state = if active_set.hostnames.contains(&client_window.hostname) {
+                PipeServerState::Enabled
+            } else {
+                PipeServerState::Disabled
+            };
+        }
+        self.active_set_name = Some(active_set.name.clone());
+        if self.config.highlight_active_set {
+            highlight_client_windows(
+                &client_console_window_handles
+                    .values()
+                    .filter(|client_window| {
+                        return client_window.state == PipeServerState::Enabled;
+                    })
+                    .map(|client_window| return client_window.hwnd)
+                    .collect::<Vec<HWND>>(),
+            );
+        }
+    }
+
+    /// Launches a client for each of the given hosts and lets them join the broadcast.
+    async fn add_clients(
+        &self,
+        hosts: Vec<String>,
+        sender: &Sender<[u8; SERIALIZED_INPUT_RECORD_0_LENGTH]>,
+        client_console_window_handles: &Arc<Mutex<BTreeMap<usize, ClientWindow>>>,
+        workspace_areas: &[WorkspaceArea],
+        servers: &Arc<Mutex<Vec<JoinHandle<()>>>>,
+    ) {
+        let workspace_grids = self.determine_workspace_grids(hosts.len(), workspace_areas);
+        let new_clients = launch_clients(
+            hosts,
+            &self.username,
+            &workspace_grids,
+            self.config,
+            &self.pipe_name,
+            &self.options,
+        )
+        .await;
+        let next_index = get_next_client_index(&client_console_window_handles.lock().unwrap());
+        for (index, client_window) in new_clients {
+            client_console_window_handles
+                .lock()
+                .unwrap()
+                .insert(next_index + index, client_window);
+            self._launch_named_pipe_server(
+                &mut servers.lock().unwrap(),
+                sender,
+                client_console_window_handles,
+            );
+        }
+        return;
+    }
+
+    /// Arranges the client window at `index` (among the existing ones, wrapping around)
+    /// across the full workspace area and minimizes all other client windows.
+    fn zoom_client_window(
+        &mut self,
+        index: usize,
+        client_console_window_handles: &BTreeMap<usize, ClientWindow>,
+        workspace_areas: &[WorkspaceArea],
+    ) {
+        let client_windows = get_valid_client_windows(client_console_window_handles);
+        if client_windows.is_empty() {
+            self.zoomed_client = None;
+            return;
+        }
+        let index = index % client_windows.len();
+        for (client_index, client_window) in client_windows.iter().enumerate() {
+            if client_index != index {
+                let _ = unsafe { ShowWindow(client_window.hwnd, SW_SHOWMINNOACTIVE) };
+            }
+        }
+        let zoomed_client_window = client_windows[index];
+        if unsafe { IsIconic(zoomed_client_window.hwnd) }.as_bool() {
+            let _ = unsafe { ShowWindow(zoomed_client_window.hwnd, SW_RESTORE) };
+        }
+        // The zoomed client fills the workspace area it is placed in.
+        let workspace_area = get_workspace_grid(
+            index as i32,
+            &self.determine_workspace_grids(client_windows.len(), workspace_areas),
+        )
+        .workspace_area;
+        arrage_client_window(
+            &zoomed_client_window.hwnd,
+            determine_cell_spatial_attributes(
+                0,
+                &get_zoomed_grid_dimensions(&workspace_area, self.config),
+                &workspace_area,
+            ),
+        );
+        self.zoomed_client = Some(index);
+        // Focus the daemon console again.
+        let _ = unsafe { SetForegroundWindow(GetConsoleWindow()) };
+        clear_screen();
+        println!(
+            "Zoomed into {} ({}/{}), [Left]/[Right] to cycle, [z] or Esc to restore",
+            zoomed_client_window.hostname,
+            index + 1,
+            client_windows.len()
+        );
+    }
+
+    /// Opens a client selection for the given purpose, selecting the client that was
+    /// focused last.
+    fn open_client_selection(
+        &mut self,
+        client_selection: ClientSelection,
+        client_console_window_handles: &BTreeMap<usize, ClientWindow>,
+    ) {
+        self.client_selection = client_selection;
+        let last_focused_client = *self.last_focused_client.lock().unwrap();
+        let index = get_valid_client_windows(client_console_window_handles)
+            .iter()
+            .position(|client_window| return Some(client_window.hwnd) == last_focused_client)
+            .unwrap_or(0);
+        self.select_client(index, client_console_window_handles);
+    }
+
+    /// Handles a key pressed while a client selection is open: the arrow and digit keys
+    /// move the selection, Enter or Space acts on the selected client according to
+    /// the purpose of the selection.
+    ///
+    /// Closes the selection once it served its purpose.
+    fn handle_client_selection_key(
+        &mut self,
+        key_code: u16,
+        client_console_window_handles: &mut BTreeMap<usize, ClientWindow>,
+    ) {
+        let selected_client = match self.selected_client {
+            Some(selected_client) => selected_client,
+            None => return,
+        };
+        let number_of_clients = get_valid_client_windows(client_console_window_handles).len();
+        if let Some(index) = get_client_selection(selected_client, number_of_clients, key_code) {
+            self.select_client(index, client_console_window_handles);
+            return;
+        }
+        if key_code != VK_RETURN.0 && key_code != VK_SPACE.0 {
+            return;
+        }
+        let hwnd =
+            match get_valid_client_windows(client_console_window_handles).get(selected_client) {
+                Some(client_window) => client_window.hwnd,
+                None => return,
+            };
+        if let ClientSelection::Swap { first } = self.client_selection {
+            match first {
+                None => {
+                    self.client_selection = ClientSelection::Swap {
+                        first: Some(selected_client),
+                    };
+                    self.print_client_selection(client_console_window_handles);
+                }
+                Some(first) => {
+                    self.swap_client_windows(first, hwnd, client_console_window_handles);
+                    self.close_client_selection();
+                }
+            }
+            return;
+        }
+        if let Some(client_window) = client_console_window_handles
+            .values_mut()
+            .find(|client_window| return client_window.hwnd == hwnd)
+        {
+            client_window.state = match client_window.state {
+                PipeServerState::Enabled => PipeServerState::Disabled,
+                PipeServerState::Disabled => PipeServerState::Enabled,
+            };
+        }
+        self.active_set_name = None;
+        self.print_client_selection(client_console_window_handles);
+    }
+
+    /// Swaps the positions of the client at index `first` (among the existing ones) and
+    /// the client with the window `second` and records the swap in the layout in use.
+    fn swap_client_windows(
+        &self,
+        first: usize,
+        second: HWND,
+        client_console_window_handles: &mut BTreeMap<usize, ClientWindow>,
+    ) {
+        let first = match get_valid_client_windows(client_console_window_handles).get(first) {
+            Some(client_window) => client_window.hwnd,
+            None => return,
+        };
+        if let Some(hostnames) =
+            swap_client_window_order(client_console_window_handles, first, second)
+        {
+            swap_window_positions(&first, &second);
+            self.update_layout(|layout| layout.swaps.push(hostnames));
+        }
+    }
+
+    /// Selects the client at `index` (among the existing ones) in the open client selection
+    /// and highlights its window border, restoring the border of the previously selected one.
+    fn select_client(
+        &mut self,
+        index: usize,
+        client_console_window_handles: &BTreeMap<usize, ClientWindow>,
+    ) {
+        let hwnd = match get_valid_client_windows(client_console_window_handles).get(index) {
+            Some(client_window) => client_window.hwnd,
+            None => return,
+        };
+        self.selected_client = Some(index);
+        if let Some((previous_hwnd, original_color)) = self
+            .selection_highlight
+            .highlight(hwnd, get_window_border_color(hwnd))
+        {
+            restore_window_border_color(previous_hwnd, original_color);
+        }
+        set_window_border_color(hwnd, SELECTED_CLIENT_BORDER_COLOR);
+        self.print_client_selection(client_console_window_handles);
+    }
+
+    /// Closes the client selection, if it is open, restoring the border
+    /// of the selected client.
+    fn close_client_selection(&mut self) {
+        self.selected_client = None;
+        if let Some((hwnd, original_color)) = self.selection_highlight.clear() {
+            restore_window_border_color(hwnd, original_color);
+        }
+    }
+
+    fn print_client_selection(
+        &self,
+        client_console_window_handles: &BTreeMap<usize, ClientWindow>,
+    ) {
+        clear_screen();
+        let (title, action, first) = match self.client_selection {
+            ClientSelection::EnableDisable => ("Enable/disable", "toggle", None),
+            ClientSelection::Swap { first: None } => ("Swap", "pick the first client", None),
+            ClientSelection::Swap { first } => ("Swap", "swap with the client marked *", first),
+        };
+        println!(
+            "{}: [Left]/[Right] or [0-9] to select, [Enter] to {}, Esc to exit",
+            title, action
+        );
+        for (index, client_window) in get_valid_client_windows(client_console_window_handles)
+            .iter()
+            .enumerate()
+        {
+            let selection_marker = if Some(index) == self.selected_client {
+                ">"
+            } else if Some(index) == first {
+                "*"
+            } else {
+                " "
+            };
+            let state = match client_window.state {
+                PipeServerState::Enabled => "enabled",
+                PipeServerState::Disabled => "disabled",
+            };
+            println!(
+                "{} {}: {} ({})",
+                selection_marker, index, client_window.hostname, state
+            );
+        }
+    }
+
+    /// Restores the client windows minimized while zooming, if a client is zoomed.
+    fn restore_zoomed_client_windows(
+        &mut self,
+        client_console_window_handles: &BTreeMap<usize, ClientWindow>,
+    ) {
+        if self.zoomed_client.take().is_none() {
+            return;
+        }
+        for client_window in get_valid_client_windows(client_console_window_handles) {
+            if unsafe { IsIconic(client_window.hwnd) }.as_bool() {
+                let _ = unsafe { ShowWindow(client_window.hwnd, SW_RESTORE) };
+            }
+        }
+    }
+
+    /// Leaves the zoom and arranges the client windows in the grid again.
+    fn unzoom_client_windows(
+        &mut self,
+        client_console_window_handles: &BTreeMap<usize, ClientWindow>,
+        workspace_areas: &[WorkspaceArea],
+    ) {
+        self.restore_zoomed_client_windows(client_console_window_handles);
+        self.rearrange_client_windows(client_console_window_handles, workspace_areas);
+        // Focus the daemon console again.
+        let _ = unsafe { SetForegroundWindow(GetConsoleWindow()) };
+    }
+
+    fn rearrange_client_windows(
+        &self,
+        client_console_window_handles: &BTreeMap<usize, ClientWindow>,
+        workspace_areas: &[WorkspaceArea],
+    ) {
+        let mut valid_handles: Vec<HWND> = Vec::new();
+        for handle in client_console_window_handles.values() {
+            if unsafe { IsWindow(handle.hwnd).as_bool() } {
+                valid_handles.push(handle.hwnd);
+            }
+        }
+        let workspace_grids = self.determine_workspace_grids(valid_handles.len(), workspace_areas);
+        for (index, handle) in valid_handles.iter().enumerate() {
+            arrage_client_window(
+                handle,
+                determine_client_spatial_attributes(index as i32, &workspace_grids),
+            )
+        }
+    }
+
+    /// Briefly outlines the grid cells the client windows are arranged in.
+    fn show_grid_guides(
+        &self,
+        client_console_window_handles: &BTreeMap<usize, ClientWindow>,
+        workspace_areas: &[WorkspaceArea],
+    ) {
+        let number_of_consoles = client_console_window_handles
+            .values()
+            .filter(|client_window| return unsafe { IsWindow(client_window.hwnd) }.as_bool())
+            .count() as i32;
+        let workspace_grids =
+            self.determine_workspace_grids(number_of_consoles as usize, workspace_areas);
+        let cells = (0..number_of_consoles)
+            .map(|index| return determine_client_spatial_attributes(index, &workspace_grids))
+            .collect();
+        grid_guides::show_grid_guides(cells);
+    }
+
+    /// Distributes the `number_of_clients` across the `workspace_areas` and determines
+    /// the grid of each of them.
+    fn determine_workspace_grids(
+        &self,
+        number_of_clients: usize,
+        workspace_areas: &[WorkspaceArea],
+    ) -> Vec<WorkspaceGrid> {
+        return determine_workspace_grids(
+            number_of_clients as i32,
+            workspace_areas,
+            self.config.aspect_ratio_adjustement,
+            self.options.grid,
+            self.config.fill_direction,
+            (
+                self.config.min_console_width,
+                self.config.min_console_height,
+            ),
+            get_daemon_cell_position(self.config, self.options.hidden_daemon),
+        );
+    }
+
+    fn arrange_daemon_console(&self, workspace_areas: &[WorkspaceArea], number_of_clients: usize) {
+        let (x, y, width, height) = get_daemon_console_rect(
+            number_of_clients,
+            workspace_areas,
+            self.config,
+            self.options.grid,
+            self.options.hidden_daemon,
+        );
+        arrange_console(x, y, width, height);
+    }
+}
+
+/// Asks all clients to shut down, closes the windows of those that didn't
+/// exit within [`QUIT_ALL_TIMEOUT`] and exits the daemon.
+fn quit_all(
+    sender: &Sender<[u8; SERIALIZED_INPUT_RECORD_0_LENGTH]>,
+    client_console_window_handles: &BTreeMap<usize, ClientWindow>,
+) {
+    clear_screen();
+    println!("Quitting all clients");
+    if let Err(err) = sender.send(SHUTDOWN_PACKET) {
+        error!("Failed to broadcast shutdown: {}", err);
+    }
+    let start = Instant::now();
+    for client_window in client_console_window_handles.values() {
+        let remaining_time = QUIT_ALL_TIMEOUT.saturating_sub(start.elapsed());
+        if unsafe {
+            WaitForSingleObject(
+                client_window.process_handle,
+                remaining_time.as_millis() as u32,
+            )
+        } == WAIT_OBJECT_0
+        {
+            continue;
+        }
+        warn!(
+            "Client `{}` (pid {}) didn't exit in time, closing its window",
+            client_window.hostname, client_window.process_id
+        );
+        if let Err(err) =
+            unsafe { PostMessageW(client_window.hwnd, WM_CLOSE, WPARAM(0), LPARAM(0)) }
+        {
+            error!(
+                "Failed to close the window of client `{}`: {}",
+                client_window.hostname, err
+            );
+        }
+    }
+    std::process::exit(0);
+}
+
+/// Returns the workspace areas available to the client windows of a daemon,
+/// the first one being the area of the daemon console.
+fn get_daemon_workspace_areas(
+    config: &DaemonConfig,
+    hidden_daemon: bool,
+    monitor: Option<usize>,
+    split_index: usize,
+    split_count: usize,
+) -> Vec<WorkspaceArea> {
+    // A hidden daemon console doesn't take any space away from the clients.
+    let daemon_console_height = if hidden_daemon { 0 } else { config.height };
+    return workspace::get_workspace_area(
+        workspace::Scaling::Logical,
+        daemon_console_height,
+        config.daemon_position,
+        monitor,
+    )
+    .into_iter()
+    .map(|workspace_area| return workspace_area.split(split_index, split_count))
+    .collect();
+}
+
+/// Returns the position and size (`x`, `y`, `width`, `height`) each of the
+/// `number_of_hosts` client windows of a daemon would be arranged at,
+/// without launching anything.
+pub fn plan_client_windows(
+    number_of_hosts: usize,
+    config: &DaemonConfig,
+    hidden_daemon: bool,
+    monitor: Option<usize>,
+    grid: Option<(usize, usize)>,
+    split_index: usize,
+    split_count: usize,
+) -> Vec<(i32, i32, i32, i32)> {
+    let workspace_areas =
+        get_daemon_workspace_areas(config, hidden_daemon, monitor, split_index, split_count);
+    return get_client_window_rects(
+        number_of_hosts,
+        &workspace_areas,
+        config,
+        grid,
+        hidden_daemon,
+    );
+}
+
+/// Returns the position and size (`x`, `y`, `width`, `height`) of each of the
+/// `number_of_hosts` client windows distributed across the given workspace areas.
+fn get_client_window_rects(
+    number_of_hosts: usize,
+    workspace_areas: &[WorkspaceArea],
+    config: &DaemonConfig,
+    grid: Option<(usize, usize)>,
+    hidden_daemon: bool,
+) -> Vec<(i32, i32, i32, i32)> {
+    let workspace_grids = determine_workspace_grids(
+        number_of_hosts as i32,
+        workspace_areas,
+        config.aspect_ratio_adjustement,
+        grid,
+        config.fill_direction,
+        (config.min_console_width, config.min_console_height),
+        get_daemon_cell_position(config, hidden_daemon),
+    );
+    return (0..number_of_hosts)
+        .map(|index| {
+            return determine_client_spatial_attributes(index as i32, &workspace_grids);
+        })
+        .collect();
+}
+
+/// Returns the position of the grid cell the daemon console takes,
+/// `None` if it is placed along the workspace edge or hidden.
+fn get_daemon_cell_position(config: &DaemonConfig, hidden_daemon: bool) -> Option<DaemonPosition> {
+    if hidden_daemon || !config.daemon_position.is_grid_cell() {
+        return None;
+    }
+    return Some(config.daemon_position);
+}
+
+/// Returns the position and size (`x`, `y`, `width`, `height`) of the daemon console
+/// in the first of the given workspace areas, next to the `number_of_clients` client
+/// windows distributed across them.
+fn get_daemon_console_rect(
+    number_of_clients: usize,
+    workspace_areas: &[WorkspaceArea],
+    config: &DaemonConfig,
+    grid: Option<(usize, usize)>,
+    hidden_daemon: bool,
+) -> (i32, i32, i32, i32) {
+    let workspace_area = &workspace_areas[0];
+    // A hidden daemon console overlaps the clients while it is revealed.
+    let y = match (config.daemon_position, hidden_daemon) {
+        (DaemonPosition::Top, true) => 0,
+        (DaemonPosition::Top, false) => -config.height,
+        (DaemonPosition::Bottom, true) => workspace_area.height - config.height,
+        (DaemonPosition::Bottom, false) => workspace_area.height,
+        (daemon_position, _) => {
+            // The cell is determined as if it was reserved, even for a hidden daemon console.
+            let workspace_grid = determine_workspace_grids(
+                number_of_clients as i32,
+                workspace_areas,
+                config.aspect_ratio_adjustement,
+                grid,
+                config.fill_direction,
+                (config.min_console_width, config.min_console_height),
+                Some(daemon_position),
+            )[0];
+            return determine_cell_spatial_attributes(
+                get_daemon_cell(daemon_position, &workspace_grid.grid_dimensions),
+                &workspace_grid.grid_dimensions,
+                &workspace_grid.workspace_area,
+            );
+        }
+    };
+    return get_console_rect(0, y, workspace_area.width, config.height, workspace_area);
+}
+
+/// Moves the given client window to the given position and size
+/// (`x`, `y`, `width`, `height`).
+fn arrage_client_window(handle: &HWND, (x, y, width, height): (i32, i32, i32, i32)) {
+    unsafe {
+        MoveWindow(*handle, x, y, width, height, true).unwrap_or_else(|err| {
+            error!("{}", err);
+            panic!("Failed to move window",)
+        });
+    }
+}
+
+/// Returns the index of the client selected in a client selection after pressing
+/// the key with the given virtual key code, `None` if the key doesn't move the selection.
+///
+/// The arrow keys select the previous or next client, wrapping around, the digit keys
+/// select the client with that index, unless there are not as many clients.
+fn get_client_selection(
+    selected_client: usize,
+    number_of_clients: usize,
+    key_code: u16,
+) -> Option<usize> {
+    if number_of_clients == 0 {
+        return None;
+    }
+    return match VIRTUAL_KEY(key_code) {
+        VK_LEFT | VK_UP => Some((selected_client + number_of_clients - 1) % number_of_clients),
+        VK_RIGHT | VK_DOWN => Some((selected_client + 1) % number_of_clients),
+        VIRTUAL_KEY(key_code) if (VK_0.0..=VK_9.0).contains(&key_code) => {
+            let index = (key_code - VK_0.0) as usize;
+            if index < number_of_clients {
+                Some(index)
+            } else {
+                Some(selected_client)
+            }
+        }
+        _ => None,
+    };
+}
+
+/// Restores the border color of the given window, unless it no longer exists.
+fn restore_window_border_color(hwnd: HWND, color: COLORREF) {
+    if unsafe { IsWindow(hwnd) }.as_bool() {
+        set_window_border_color(hwnd, color);
+    }
+}
+
+/// Returns the client windows that still exist, in order of their arrangement.
+fn get_valid_client_windows(
+    client_console_window_handles: &BTreeMap<usize, ClientWindow>,
+) -> Vec<&ClientWindow> {
+    return client_console_window_handles
+        .values()
+        .filter(|client_window| return unsafe { IsWindow(client_window.hwnd) }.as_bool())
+        .collect();
+}
+
+/// Brings the window that had the foreground before rearranging back to the front,
+/// or the daemon console if that window no longer exists.
+fn restore_foreground_window(previous_foreground_window: HWND) {
+    if let Some(window) = get_window_to_refocus(
+        previous_foreground_window,
+        unsafe { GetForegroundWindow() },
+        unsafe { IsWindow(previous_foreground_window) }.as_bool(),
+        unsafe { GetConsoleWindow() },
+    ) {
+        let _ = unsafe { SetForegroundWindow(window) };
+    }
+}
+
+/// Returns the window to bring to the front after rearranging, `None` if the
+/// `previous_foreground_window` still is the `foreground_window`.
+fn get_window_to_refocus(
+    previous_foreground_window: HWND,
+    foreground_window: HWND,
+    previous_foreground_window_exists: bool,
+    daemon_console_window: HWND,
+) -> Option<HWND> {
+    if foreground_window == previous_foreground_window {
+        return None;
+    }
+    if previous_foreground_window_exists {
+        return Some(previous_foreground_window);
+    }
+    return Some(daemon_console_window);
+}
+
+/// Returns the control mode state after the given key event was read in `control_mode_state`.
+///
+/// Ctrl+A initiates control mode and Escape leaves it. Pressing Ctrl+A again leaves
+/// control mode as well, so the key event is broadcasted as a literal Ctrl+A.
+/// Its key release is broadcasted once control mode is left.
+fn get_next_control_mode_state(
+    control_mode_state: &ControlModeState,
+    key_event: &KEY_EVENT_RECORD,
+) -> ControlModeState {
+    let is_ctrl_a = key_event.bKeyDown.as_bool()
+        && (key_event.dwControlKeyState & LEFT_CTRL_PRESSED >= 1
+            || key_event.dwControlKeyState & RIGHT_CTRL_PRESSED >= 1)
+        && key_event.wVirtualKeyCode == VK_A.0;
+    if *control_mode_state == ControlModeState::Active {
+        if key_event.wVirtualKeyCode == VK_ESCAPE.0 || is_ctrl_a {
+            return ControlModeState::Inactive;
+        }
+        return ControlModeState::Active;
+    }
+    if is_ctrl_a {
+        return ControlModeState::Initiated;
+    }
+    return ControlModeState::Inactive;
+}
+
+/// Returns the hostnames of the client windows that are still open.
+fn get_open_client_hostnames(
+    client_console_window_handles: &BTreeMap<usize, ClientWindow>,
+) -> Vec<&str> {
+    return client_console_window_handles
+        .values()
+        .filter(|client_window| return unsafe { IsWindow(client_window.hwnd) }.as_bool())
+        .map(|client_window| return client_window.hostname.as_str())
+        .collect();
+}
+
+/// Returns the `hosts` the daemon was started with that no longer have an open client
+/// window, e.g. because the client crashed. A host given multiple times needs as many windows.
+fn get_exited_hosts(hosts: &[String], open_client_hostnames: &[&str]) -> Vec<String> {
+    let mut exited_hosts = hosts.to_vec();
+    for open_client_hostname in open_client_hostnames {
+        if let Some(position) = exited_hosts
+            .iter()
+            .position(|host| return host == open_client_hostname)
+        {
+            exited_hosts.remove(position);
+        }
+    }
+    return exited_hosts;
+}
+
+/// Returns the index after the highest existing one, so appending client windows
+/// never replaces an existing client window (and its enabled state).
+fn get_next_client_index(client_console_window_handles: &BTreeMap<usize, ClientWindow>) -> usize {
+    return client_console_window_handles
+        .last_key_value()
+        .map_or(0, |(index, _)| return index + 1);
+}
+
+/// Sorts the client windows by hostname in natural order,
+/// so they are arranged accordingly on the next retile.
+fn sort_client_windows(client_console_window_handles: &mut BTreeMap<usize, ClientWindow>) {
+    let mut client_windows: Vec<ClientWindow> = mem::take(client_console_window_handles)
+        .into_values()
+        .collect();
+    client_windows.sort_by(|a, b| return natural_cmp(&a.hostname, &b.hostname));
+    client_console_window_handles.extend(client_windows.into_iter().enumerate());
+}
+
+/// Swaps the client windows `first` and `second` in `client_console_window_handles`,
+/// so the new order persists across retiles.
+///
+/// Returns the hostnames of the swapped clients, `None` if either window is unknown
+/// or both are the same.
+fn swap_client_window_order(
+    client_console_window_handles: &mut BTreeMap<usize, ClientWindow>,
+    first: HWND,
+    second: HWND,
+) -> Option<(String, String)> {
+    let find_index = |hwnd: HWND| {
+        return client_console_window_handles
+            .iter()
+            .find(|(_, client_window)| return client_window.hwnd == hwnd)
+            .map(|(index, _)| return *index);
+    };
+    let (first_index, second_index) = match (find_index(first), find_index(second)) {
+        (Some(first_index), Some(second_index)) if first_index != second_index => {
+            (first_index, second_index)
+        }
+        _ => return None,
+    };
+    let first_client_window = client_console_window_handles.remove(&first_index)?;
+    let second_client_window = client_console_window_handles.remove(&second_index)?;
+    let hostnames = (
+        first_client_window.hostname.clone(),
+        second_client_window.hostname.clone(),
+    );
+    client_console_window_handles.insert(first_index, second_client_window);
+    client_console_window_handles.insert(second_index, first_client_window);
+    return Some(hostnames);
+}
+
+/// Prints the numbered list of client windows that still exist.
+///
+/// Returns the indices of the listed client windows, the client window
+/// listed with number `n` is found at position `n - 1`.
+fn print_client_list(client_console_window_handles: &BTreeMap<usize, ClientWindow>) -> Vec<usize> {
+    let valid_indices: Vec<usize> = client_console_window_handles
+        .iter()
+        .filter(|(_, client_window)| return unsafe { IsWindow(client_window.hwnd).as_bool() })
+        .map(|(index, _)| return *index)
+        .collect();
+    for (number, index) in valid_indices.iter().enumerate() {
+        println!(
+            "{}: {}",
+            number + 1,
+            client_console_window_handles[index].hostname
+        );
+    }
+    return valid_indices;
+}
+
+/// Maps client numbers as listed by [`print_client_list`] to the client indices,
+/// ignoring anything that is not a valid client number.
+fn parse_client_numbers<'a>(
+    numbers: impl Iterator<Item = &'a str>,
+    valid_indices: &[usize],
+) -> Vec<usize> {
+    return numbers
+        .filter_map(|number| return number.parse::<usize>().ok())
+        .filter_map(|number| return valid_indices.get(number.checked_sub(1)?).copied())
+        .collect();
+}
+
+/// Splits `text` into chunks of at most `chunk_size` characters, `0` keeps it in one piece.
+fn split_into_chunks(text: &str, chunk_size: usize) -> Vec<String> {
+    if chunk_size == 0 {
+        return vec![text.to_owned()];
+    }
+    let chars: Vec<char> = text.chars().collect();
+    return chars
+        .chunks(chunk_size)
+        .map(|chunk| return chunk.iter().collect())
+        .collect();
+}
+
+/// Reads a line from the daemon console with processed input temporarily enabled.
+///
+/// Returns `None` if reading failed.
+fn read_line() -> Option<String> {
+    disable_processed_input_mode(); // As it was disabled before, this enables it again
+    let mut input = String::new();
+    let read_result = io::stdin().read_line(&mut input);
+    disable_processed_input_mode();
+    match read_result {
+        Ok(_) => return Some(input),
+        Err(error) => {
+            error!("{error}");
+            return None;
+        }
+    }
+}
+
+/// Reads a line from the daemon console key by key, so the input can be aborted.
+///
+/// Returns `None` if Escape was pressed.
+fn read_line_or_escape() -> Option<String> {
+    let mut line = String::new();
+    loop {
+        let key_event = unsafe { read_keyboard_input().KeyEvent };
+        if !key_event.bKeyDown.as_bool() {
+            continue;
+        }
+        match VIRTUAL_KEY(key_event.wVirtualKeyCode) {
+            VK_ESCAPE => return None,
+            VK_RETURN => {
+                println!();
+                return Some(line);
+            }
+            VK_BACK => {
+                if line.pop().is_some() {
+                    print!("\u{8} \u{8}");
+                }
+            }
+            _ => {
+                if let Some(character) =
+                    char::from_u32(unsafe { key_event.uChar.UnicodeChar } as u32)
+                        .filter(|character| return !character.is_control())
+                {
+                    line.push(character);
+                    print!("{character}");
+                }
+            }
+        }
+        let _ = io::stdout().flush();
+    }
+}
+
+/// Moves each of the given windows to the position and size of the other one.
+fn swap_window_positions(first: &HWND, second: &HWND) {
+    let mut first_rect = RECT::default();
+    let mut second_rect = RECT::default();
+    unsafe {
+        GetWindowRect(*first, &mut first_rect).unwrap();
+        GetWindowRect(*second, &mut second_rect).unwrap();
+    }
+    for (handle, rect) in [(first, second_rect), (second, first_rect)] {
+        unsafe {
+            SetWindowPos(
+                *handle,
+                HWND::default(),
+                rect.left,
+                rect.top,
+                rect.right - rect.left,
+                rect.bottom - rect.top,
+                SWP_NOZORDER | SWP_NOACTIVATE,
+            )
+            .unwrap_or_else(|err| {
+                error!("{}", err);
+                panic!("Failed to move window",)
+            });
+        }
+    }
+}
+
+/// Moves client windows back into their grid cell whenever they are resized,
+/// so all clients keep the same size.
+fn keep_client_window_sizes_uniform(
+    client_console_window_handles: Arc<Mutex<BTreeMap<usize, ClientWindow>>>,
+    workspace_areas: Vec<WorkspaceArea>,
+    aspect_ratio_adjustment: f64,
+    grid: Option<(usize, usize)>,
+    fill_direction: FillDirection,
+    min_console_size: (i32, i32),
+    daemon_cell_position: Option<DaemonPosition>,
+) {
+    tokio::spawn(async move {
+        let mut previous_sizes: HashMap<isize, (i32, i32)> = HashMap::new();
+        loop {
+            tokio::time::sleep(Duration::from_millis(250)).await;
+            let valid_handles: Vec<HWND> = client_console_window_handles
+                .lock()
+                .unwrap()
+                .values()
+                .map(|client_window| return client_window.hwnd)
+                .filter(|handle| return unsafe { IsWindow(*handle) }.as_bool())
+                .collect();
+            for (index, handle) in valid_handles.iter().enumerate() {
+                if unsafe { IsIconic(*handle) }.as_bool() {
+                    continue;
+                }
+                let size = match get_window_size(handle) {
+                    Some(size) => size,
+                    None => continue,
+                };
+                match previous_sizes.insert(handle.0, size) {
+                    Some(previous_size) if previous_size != size => {
+                        debug!(
+                            "Client window {:?} was resized, re-applying grid size",
+                            handle
+                        );
+                        arrage_client_window(
+                            handle,
+                            determine_client_spatial_attributes(
+                                index as i32,
+                                &determine_workspace_grids(
+                                    valid_handles.len() as i32,
+                                    &workspace_areas,
+                                    aspect_ratio_adjustment,
+                                    grid,
+                                    fill_direction,
+                                    min_console_size,
+                                    daemon_cell_position,
+                                ),
+                            ),
+                        );
+                        if let Some(size) = get_window_size(handle) {
+                            previous_sizes.insert(handle.0, size);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    });
+}
+
+/// Returns the width and height of the given window.
+fn get_window_size(handle: &HWND) -> Option<(i32, i32)> {
+    let mut rect = RECT::default();
+    unsafe { GetWindowRect(*handle, &mut rect) }.ok()?;
+    return Some((rect.right - rect.left, rect.bottom - rect.top));
+}
+
+/// Minimizes the daemon console once no control mode activity happened
+/// for [`AUTO_MINIMIZE_DAEMON_DELAY`].
+///
+/// The console is minimized without being deactivated, so it keeps receiving
+/// key-strokes as long as no other window is activated.
+fn auto_minimize_daemon_console(last_control_mode_activity: Arc<Mutex<Instant>>) {
+    tokio::spawn(async move {
+        let daemon_handle = unsafe { GetConsoleWindow() };
+        // Only minimize once per period of inactivity, so a manually
+        // restored daemon console isn't minimized again right away.
+        let mut minimized_activity: Option<Instant> = None;
+        loop {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let last_activity = *last_control_mode_activity.lock().unwrap();
+            if minimized_activity == Some(last_activity)
+                || last_activity.elapsed() < AUTO_MINIMIZE_DAEMON_DELAY
+            {
+                continue;
+            }
+            if !unsafe { IsIconic(daemon_handle) }.as_bool() {
+                let _ = unsafe { ShowWindow(daemon_handle, SW_SHOWMINNOACTIVE) };
+            }
+            minimized_activity = Some(last_activity);
+        }
+    });
+}
+
+/// Locks broadcasting once no input was broadcasted for `idle_lock_timeout`.
+///
+/// The lock is lifted by the daemon once Enter is pressed.
+fn lock_on_idle(
+    idle_lock_timeout: Duration,
+    last_broadcast_activity: Arc<Mutex<Instant>>,
+    locked: Arc<AtomicBool>,
+    title: Arc<Mutex<String>>,
+) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            if locked.load(Ordering::SeqCst)
+                || last_broadcast_activity.lock().unwrap().elapsed() < idle_lock_timeout
+            {
+                continue;
+            }
+            locked.store(true, Ordering::SeqCst);
+            info!(
+                "Locked after {} seconds of inactivity",
+                idle_lock_timeout.as_secs()
+            );
+            // The title is only updated by the daemon while it isn't locked.
+            set_console_title(&format!("{}{LOCKED_TITLE_FLAG}", title.lock().unwrap()));
+            clear_screen();
+            println!("Locked due to inactivity, press Enter to resume broadcasting");
+        }
+    });
+}
+
+/// Hides the daemon console.
+fn hide_daemon_console() {
+    let _ = unsafe { ShowWindow(GetConsoleWindow(), SW_HIDE) };
+}
+
+/// Registers the global Ctrl+Alt+A hotkey, which reveals and focuses the hidden daemon console
+/// or hides it again if it is visible.
+fn register_reveal_daemon_hotkey() {
+    // The hotkey messages are posted to the message queue of the registering thread,
+    // which therefore has to run its own message loop.
+    thread::spawn(|| {
+        if let Err(err) = unsafe {
+            RegisterHotKey(
+                HWND(0),
+                REVEAL_DAEMON_HOTKEY_ID,
+                MOD_CONTROL | MOD_ALT | MOD_NOREPEAT,
+                VK_A.0 as u32,
+            )
+        } {
+            error!(
+                "Failed to register the hotkey revealing the daemon: {}",
+                err
+            );
+            return;
+        }
+        let daemon_handle = unsafe { GetConsoleWindow() };
+        let mut message = MSG::default();
+        while unsafe { GetMessageW(&mut message, HWND(0), 0, 0) }.as_bool() {
+            if message.message != WM_HOTKEY {
+                continue;
+            }
+            if unsafe { IsWindowVisible(daemon_handle) }.as_bool() {
+                hide_daemon_console();
+            } else {
+                let _ = unsafe { ShowWindow(daemon_handle, SW_SHOW) };
+                let _ = unsafe { SetForegroundWindow(daemon_handle) };
+            }
+        }
+    });
+}
+
+/// Shows the given broadcasted key event in the daemon console.
+///
+/// Control keys are shown symbolically, e.g. `<Tab>` or `<Ctrl+C>`.
+fn echo_key_event(key_event: &KEY_EVENT_RECORD) {
+    if !key_event.bKeyDown.as_bool() {
+        return;
+    }
+    let unicode_char = unsafe { key_event.uChar.UnicodeChar };
+    let is_ctrl_pressed =
+        key_event.dwControlKeyState & (LEFT_CTRL_PRESSED | RIGHT_CTRL_PRESSED) != 0;
+    let text = match char::from_u32(unicode_char as u32) {
+        Some('\r') => "\n".to_owned(),
+        Some(character) if !character.is_control() && character != '\0' => character.to_string(),
+        // Leading half of a surrogate pair, shown once
+        None if (0xD800..=0xDBFF).contains(&unicode_char) => '\u{FFFD}'.to_string(),
+        None => return,
+        _ => match VIRTUAL_KEY(key_event.wVirtualKeyCode) {
+            VK_SHIFT | VK_CONTROL | VK_MENU | VK_LWIN | VK_RWIN | VK_CAPITAL => return,
+            VK_BACK => "<BS>".to_owned(),
+            VK_TAB => "<Tab>".to_owned(),
+            VK_ESCAPE => "<Esc>".to_owned(),
+            VK_DELETE => "<Del>".to_owned(),
+            VK_UP => "<Up>".to_owned(),
+            VK_DOWN => "<Down>".to_owned(),
+            VK_LEFT => "<Left>".to_owned(),
+            VK_RIGHT => "<Right>".to_owned(),
+            VIRTUAL_KEY(key_code) if is_ctrl_pressed && (VK_A.0..=VK_Z.0).contains(&key_code) => {
+                format!("<Ctrl+{}>", key_code as u8 as char)
+            }
+            VIRTUAL_KEY(key_code) => format!("<0x{:02x}>", key_code),
+        },
+    };
+    print!("{}", text);
+    let _ = io::stdout().flush();
+}
+
+/// Raises the given client windows and flashes their title bars.
+///
+/// The daemon console keeps the focus, so key-strokes keep being broadcasted.
+fn highlight_client_windows(client_windows: &[HWND]) {
+    for client_window in client_windows {
+        let _ = unsafe {
+            SetWindowPos(
+                *client_window,
+                HWND_TOP,
+                0,
+                0,
+                0,
+                0,
+                SWP_NOMOVE | SWP_NOSIZE | SWP_NOACTIVATE,
+            )
+        };
+        let flash_info = FLASHWINFO {
+            cbSize: mem::size_of::<FLASHWINFO>() as u32,
+            hwnd: *client_window,
+            dwFlags: FLASHW_CAPTION,
+            uCount: ACTIVE_SET_HIGHLIGHT_FLASHES,
+            dwTimeout: 0,
+        };
+        unsafe { FlashWindowEx(&flash_info) };
+    }
+}
+
+/// Restores the daemon console if it is minimized.
+fn restore_daemon_console() {
+    let daemon_handle = unsafe { GetConsoleWindow() };
+    if unsafe { IsIconic(daemon_handle) }.as_bool() {
+        let _ = unsafe { ShowWindow(daemon_handle, SW_RESTORE) };
+    }
+}
+
+fn ensure_client_z_order_in_sync_with_daemon(
+    client_console_window_handles: Arc<Mutex<BTreeMap<usize, ClientWindow>>>,
+) {
+    tokio::spawn(async move {
+        let daemon_handle = unsafe { GetConsoleWindow() };
+        let mut previous_foreground_window = unsafe { GetForegroundWindow() };
+        loop {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            let foreground_window = unsafe { GetForegroundWindow() };
+            if previous_foreground_window == foreground_window {
+                continue;
+            }
+            if foreground_window == daemon_handle
+                && !client_console_window_handles
+                    .lock()
+                    .unwrap()
+                    .values()
+                    .any(|client_handle| {
+                        return client_handle.hwnd == previous_foreground_window
+                            || client_handle.hwnd == daemon_handle;
+                    })
+            {
+                defer_windows(
+                    &client_console_window_handles.lock().unwrap(),
+                    &daemon_handle,
+                );
+            }
+            previous_foreground_window = foreground_window;
+        }
+    });
+}
+
+/// Keeps track of the client window that was focused last,
+/// so it can be excluded when broadcasting to all others.
+///
+/// With a `highlight_color` the border of that client is highlighted,
+/// restoring its previous border color once another client is focused.
+fn track_last_focused_client(
+    client_console_window_handles: Arc<Mutex<BTreeMap<usize, ClientWindow>>>,
+    last_focused_client: Arc<Mutex<Option<HWND>>>,
+    highlight_color: Option<COLORREF>,
+) {
+    tokio::spawn(async move {
+        let mut border_highlight = BorderHighlight::default();
+        loop {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            let foreground_window = unsafe { GetForegroundWindow() };
+            if !client_console_window_handles
+                .lock()
+                .unwrap()
+                .values()
+                .any(|client_window| return client_window.hwnd == foreground_window)
+            {
+                continue;
+            }
+            *last_focused_client.lock().unwrap() = Some(foreground_window);
+            let highlight_color = match highlight_color {
+                Some(highlight_color) => highlight_color,
+                None => continue,
+            };
+            if border_highlight.is_highlighted(foreground_window) {
+                continue;
+            }
+            if let Some((hwnd, original_color)) = border_highlight.highlight(
+                foreground_window,
+                get_window_border_color(foreground_window),
+            ) {
+                restore_window_border_color(hwnd, original_color);
+            }
+            set_window_border_color(foreground_window, highlight_color);
+        }
+    });
+}
+
+/// Runs the `on_client_double_clicked` hook whenever the title bar of a
+/// client window is double-clicked.
+///
+/// The client windows belong to other processes, so the left mouse button
+/// is polled instead of receiving the window's mouse messages.
+fn handle_client_title_double_clicks(
+    client_console_window_handles: Arc<Mutex<BTreeMap<usize, ClientWindow>>>,
+    command: Vec<String>,
+) {
+    tokio::spawn(async move {
+        let double_click_time = Duration::from_millis(unsafe { GetDoubleClickTime() } as u64);
+        let mut was_pressed = false;
+        let mut last_click: Option<(HWND, Instant)> = None;
+        loop {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            // The most significant bit is set while the button is down.
+            let is_pressed = unsafe { GetAsyncKeyState(VK_LBUTTON.0 as i32) } < 0;
+            let clicked = is_pressed && !was_pressed;
+            was_pressed = is_pressed;
+            if !clicked {
+                continue;
+            }
+            let mut cursor_position = POINT::default();
+            if unsafe { GetCursorPos(&mut cursor_position) }.is_err() {
+                continue;
+            }
+            let window = unsafe { GetAncestor(WindowFromPoint(cursor_position), GA_ROOT) };
+            let hostname = client_console_window_handles
+                .lock()
+                .unwrap()
+                .values()
+                .find(|client_window| return client_window.hwnd == window)
+                .map(|client_window| return client_window.hostname.clone());
+            let hit_test_position = LPARAM(
+                ((cursor_position.y as u16 as isize) << 16) | cursor_position.x as u16 as isize,
+            );
+            let hostname = match hostname {
+                Some(hostname)
+                    if unsafe {
+                        SendMessageW(window, WM_NCHITTEST, WPARAM(0), hit_test_position)
+                    }
+                    .0 == HTCAPTION as isize =>
+                {
+                    hostname
+                }
+                _ => {
+                    last_click = None;
+                    continue;
+                }
+            };
+            match last_click {
+                Some((last_window, last_click_time))
+                    if last_window == window && last_click_time.elapsed() <= double_click_time =>
+                {
+                    let mut hook_command = command.clone();
+                    hook_command.push(hostname.clone());
+                    run_hook(
+                        "on_client_double_clicked",
+                        &hook_command,
+                        vec![("CSSHW_HOST", hostname)],
+                    );
+                    last_click = None;
+                }
+                _ => {
+                    last_click = Some((window, Instant::now()));
+                }
+            }
+        }
+    });
+}
+
+fn defer_windows(
+    client_console_window_handles: &BTreeMap<usize, ClientWindow>,
     daemon_handle: &HWND,
 ) {
-    unsafe { CoInitializeEx(None, COINIT_MULTITHREADED).unwrap() };
-    for handle in client_console_window_handles
-        .values()
-        .chain([&ClientWindow {
-            hostname: "root".to_owned(),
-            hwnd: *daemon_handle,
-        }])
-    {
-        // First restore if window is minimized
-        let mut placement: WINDOWPLACEMENT = WINDOWPLACEMENT {
-            length: mem::size_of::<WINDOWPLACEMENT>() as u32,
+    unsafe { CoInitializeEx(None, COINIT_MULTITHREADED).unwrap() };
+    for handle in client_console_window_handles
+        .values()
+        .chain([&ClientWindow {
+            hostname: "root".to_owned(),
+            hwnd: *daemon_handle,
+            process_id: std::process::id(),
+            process_handle: HANDLE::default(),
+            state: PipeServerState::Enabled,
+            excluded: false,
+            last_heartbeat: Instant::now(),
+        }])
+    {
+        // First restore if window is minimized
+        let mut placement: WINDOWPLACEMENT = WINDOWPLACEMENT {
+            length: mem::size_of::<WINDOWPLACEMENT>() as u32,
+            ..Default::default()
+        };
+        match unsafe { GetWindowPlacement(handle.hwnd, &mut placement) } {
+            Ok(_) => {}
+            Err(_) => {
+                continue;
+            }
+        }
+        if placement.showCmd == SW_SHOWMINIMIZED.0.try_into().unwrap() {
+            let _ = unsafe { ShowWindow(handle.hwnd, SW_RESTORE) };
+        }
+        // Then bring it to front using UI automation
+        let automation: IUIAutomation =
+            unsafe { CoCreateInstance(&CUIAutomation, None, CLSCTX_ALL) }.unwrap();
+        if let Ok(window) = unsafe { automation.ElementFromHandle(handle.hwnd) } {
+            unsafe { window.SetFocus() }.unwrap();
+        }
+    }
+}
+
+/// Dimensions of the grid the client windows are arranged in.
+#[derive(Clone, Copy, Debug)]
+struct GridDimensions {
+    columns: i32,
+    rows: i32,
+    /// Size of a cell, the last row (or column when filling column-major)
+    /// of an automatic grid is stretched to use the remaining space.
+    cell_width: i32,
+    cell_height: i32,
+    number_of_consoles: i32,
+    /// Whether the number of columns and rows was given, keeping all cells equally sized.
+    fixed: bool,
+    fill_direction: FillDirection,
+    /// Index of the cell taken by the daemon console, which the clients skip.
+    daemon_cell: Option<i32>,
+}
+
+/// Cells smaller than the `min_console_size` (`width`, `height`, `0` disables the minimum)
+/// reduce the number of columns (or rows when filling column-major), the additional rows
+/// (or columns) then extend beyond the workspace area.
+///
+/// A `daemon_cell_position` reserves one more cell for the daemon console.
+fn determine_grid_dimensions(
+    number_of_clients: i32,
+    workspace_area: &workspace::WorkspaceArea,
+    aspect_ratio_adjustment: f64,
+    grid: Option<(usize, usize)>,
+    fill_direction: FillDirection,
+    min_console_size: (i32, i32),
+    daemon_cell_position: Option<DaemonPosition>,
+) -> GridDimensions {
+    let number_of_consoles = number_of_clients + daemon_cell_position.map_or(0, |_| return 1);
+    let aspect_ratio = workspace_area.width as f64 / workspace_area.height as f64;
+
+    let grid_columns = match grid {
+        Some((columns, _)) => max(columns as i32, 1),
+        None => max(
+            ((number_of_consoles as f64).sqrt() * (aspect_ratio + aspect_ratio_adjustment)) as i32,
+            1,
+        ),
+    };
+    // Rows are added to a fixed grid if there are more consoles than cells.
+    let grid_rows = max(
+        (number_of_consoles as f64 / grid_columns as f64).ceil() as i32,
+        grid.map_or(1, |(_, rows)| return max(rows as i32, 1)),
+    );
+
+    // Filling column-major adds columns to a fixed grid instead,
+    // the rows of the automatic grid might not need all columns.
+    let (grid_columns, grid_rows) = match fill_direction {
+        FillDirection::RowMajor => (grid_columns, grid_rows),
+        FillDirection::ColumnMajor => {
+            let grid_rows = grid.map_or(grid_rows, |(_, rows)| return max(rows as i32, 1));
+            (
+                max(
+                    (number_of_consoles as f64 / grid_rows as f64).ceil() as i32,
+                    grid.map_or(1, |(columns, _)| return max(columns as i32, 1)),
+                ),
+                grid_rows,
+            )
+        }
+    };
+
+    let (min_console_width, min_console_height) = min_console_size;
+    let (grid_columns, grid_rows) = match fill_direction {
+        FillDirection::RowMajor if min_console_width > 0 => {
+            let grid_columns = grid_columns.min(max(workspace_area.width / min_console_width, 1));
+            (
+                grid_columns,
+                max(
+                    grid_rows,
+                    (number_of_consoles as f64 / grid_columns as f64).ceil() as i32,
+                ),
+            )
+        }
+        FillDirection::ColumnMajor if min_console_height > 0 => {
+            let grid_rows = grid_rows.min(max(workspace_area.height / min_console_height, 1));
+            (
+                max(
+                    grid_columns,
+                    (number_of_consoles as f64 / grid_rows as f64).ceil() as i32,
+                ),
+                grid_rows,
+            )
+        }
+        _ => (grid_columns, grid_rows),
+    };
+
+    let mut grid_dimensions = GridDimensions {
+        columns: grid_columns,
+        rows: grid_rows,
+        cell_width: max(workspace_area.width / grid_columns, min_console_width),
+        cell_height: max(workspace_area.height / grid_rows, min_console_height),
+        number_of_consoles,
+        fixed: grid.is_some(),
+        fill_direction,
+        daemon_cell: None,
+    };
+    grid_dimensions.daemon_cell = daemon_cell_position
+        .map(|daemon_position| return get_daemon_cell(daemon_position, &grid_dimensions));
+    return grid_dimensions;
+}
+
+/// Returns the index of the grid cell taken by the daemon console at the given position.
+fn get_daemon_cell(daemon_position: DaemonPosition, grid_dimensions: &GridDimensions) -> i32 {
+    return match daemon_position {
+        DaemonPosition::BottomLeft => match grid_dimensions.fill_direction {
+            FillDirection::RowMajor => (grid_dimensions.rows - 1) * grid_dimensions.columns,
+            FillDirection::ColumnMajor => grid_dimensions.rows - 1,
+        },
+        // The last cell of an automatic grid is stretched to the bottom right corner.
+        DaemonPosition::BottomRight if grid_dimensions.fixed => {
+            grid_dimensions.columns * grid_dimensions.rows - 1
+        }
+        DaemonPosition::BottomRight => grid_dimensions.number_of_consoles - 1,
+        DaemonPosition::Top | DaemonPosition::Bottom | DaemonPosition::TopLeft => 0,
+    };
+}
+
+/// Grid the clients placed in one of the workspace areas are arranged in.
+#[derive(Clone, Copy, Debug)]
+struct WorkspaceGrid {
+    workspace_area: WorkspaceArea,
+    grid_dimensions: GridDimensions,
+    /// Index of the first client placed in the workspace area.
+    first_client: i32,
+    number_of_clients: i32,
+}
+
+/// Returns the number of clients placed in each of the `workspace_areas`, proportional
+/// to their size, so a single workspace area takes all clients.
+fn distribute_clients(number_of_clients: i32, workspace_areas: &[WorkspaceArea]) -> Vec<i32> {
+    let sizes: Vec<i64> = workspace_areas
+        .iter()
+        .map(|workspace_area| {
+            return max(workspace_area.width, 0) as i64 * max(workspace_area.height, 0) as i64;
+        })
+        .collect();
+    let total_size = max(sizes.iter().sum::<i64>(), 1);
+    // Rounding the cumulative shares keeps the sum equal to the number of clients.
+    let mut cumulative_size = 0;
+    let mut distributed_clients = 0;
+    return sizes
+        .iter()
+        .enumerate()
+        .map(|(index, size)| {
+            cumulative_size += size;
+            let clients = if index == sizes.len() - 1 {
+                number_of_clients
+            } else {
+                ((number_of_clients as i64 * cumulative_size + total_size / 2) / total_size) as i32
+            };
+            let area_clients = clients - distributed_clients;
+            distributed_clients = clients;
+            return area_clients;
+        })
+        .collect();
+}
+
+/// Distributes the `number_of_clients` across the `workspace_areas` and determines
+/// the grid of each of them, see [`determine_grid_dimensions`].
+///
+/// Only the grid of the first workspace area, the one of the daemon console,
+/// reserves a cell at the `daemon_cell_position`.
+fn determine_workspace_grids(
+    number_of_clients: i32,
+    workspace_areas: &[WorkspaceArea],
+    aspect_ratio_adjustment: f64,
+    grid: Option<(usize, usize)>,
+    fill_direction: FillDirection,
+    min_console_size: (i32, i32),
+    daemon_cell_position: Option<DaemonPosition>,
+) -> Vec<WorkspaceGrid> {
+    let mut first_client = 0;
+    return workspace_areas
+        .iter()
+        .zip(distribute_clients(number_of_clients, workspace_areas))
+        .enumerate()
+        .map(|(index, (workspace_area, number_of_clients))| {
+            let workspace_grid = WorkspaceGrid {
+                workspace_area: *workspace_area,
+                grid_dimensions: determine_grid_dimensions(
+                    number_of_clients,
+                    workspace_area,
+                    aspect_ratio_adjustment,
+                    grid,
+                    fill_direction,
+                    min_console_size,
+                    daemon_cell_position.filter(|_| return index == 0),
+                ),
+                first_client,
+                number_of_clients,
+            };
+            first_client += number_of_clients;
+            return workspace_grid;
+        })
+        .collect();
+}
+
+/// Returns the grid the client at `index` is placed in, clients beyond the
+/// distributed ones continue the grid of the last workspace area.
+fn get_workspace_grid(index: i32, workspace_grids: &[WorkspaceGrid]) -> &WorkspaceGrid {
+    return workspace_grids
+        .iter()
+        .find(|workspace_grid| {
+            return index < workspace_grid.first_client + workspace_grid.number_of_clients;
+        })
+        .or(workspace_grids.last())
+        .expect("There is at least one workspace area");
+}
+
+/// Returns the dimensions of a grid with a single console filling the whole workspace area,
+/// regardless of the minimum console size.
+fn get_zoomed_grid_dimensions(
+    workspace_area: &workspace::WorkspaceArea,
+    config: &DaemonConfig,
+) -> GridDimensions {
+    return determine_grid_dimensions(
+        1,
+        workspace_area,
+        config.aspect_ratio_adjustement,
+        None,
+        config.fill_direction,
+        (0, 0),
+        None,
+    );
+}
+
+/// Returns the index of the client zoomed after cycling forward (or backward) from
+/// `zoomed_client`, wrapping around at both ends of the `number_of_clients`.
+fn get_next_zoomed_client(zoomed_client: usize, number_of_clients: usize, forward: bool) -> usize {
+    let number_of_clients = number_of_clients.max(1);
+    if forward {
+        return (zoomed_client + 1) % number_of_clients;
+    }
+    return (zoomed_client + number_of_clients - 1) % number_of_clients;
+}
+
+fn determine_client_spatial_attributes(
+    index: i32,
+    workspace_grids: &[WorkspaceGrid],
+) -> (i32, i32, i32, i32) {
+    let workspace_grid = get_workspace_grid(index, workspace_grids);
+    let index = index - workspace_grid.first_client;
+    let cell = match workspace_grid.grid_dimensions.daemon_cell {
+        Some(daemon_cell) if index >= daemon_cell => index + 1,
+        _ => index,
+    };
+    return determine_cell_spatial_attributes(
+        cell,
+        &workspace_grid.grid_dimensions,
+        &workspace_grid.workspace_area,
+    );
+}
+
+fn determine_cell_spatial_attributes(
+    cell: i32,
+    grid_dimensions: &GridDimensions,
+    workspace_area: &workspace::WorkspaceArea,
+) -> (i32, i32, i32, i32) {
+    let (grid_column_index, grid_row_index) = match grid_dimensions.fill_direction {
+        FillDirection::RowMajor => (
+            cell % grid_dimensions.columns,
+            cell / grid_dimensions.columns,
+        ),
+        FillDirection::ColumnMajor => (cell / grid_dimensions.rows, cell % grid_dimensions.rows),
+    };
+
+    // Fixed grids keep all cells equally sized, the last row (or column when
+    // filling column-major) isn't stretched.
+    let mut console_width = grid_dimensions.cell_width;
+    let mut console_height = grid_dimensions.cell_height;
+    if !grid_dimensions.fixed {
+        match grid_dimensions.fill_direction {
+            FillDirection::RowMajor => {
+                let last_row_console_count =
+                    grid_dimensions.number_of_consoles % grid_dimensions.columns;
+                if grid_row_index == grid_dimensions.rows - 1 && last_row_console_count != 0 {
+                    console_width = workspace_area.width / last_row_console_count;
+                }
+            }
+            FillDirection::ColumnMajor => {
+                let last_column_console_count =
+                    grid_dimensions.number_of_consoles % grid_dimensions.rows;
+                if grid_column_index == grid_dimensions.columns - 1
+                    && last_column_console_count != 0
+                {
+                    console_height = workspace_area.height / last_column_console_count;
+                }
+            }
+        }
+    }
+
+    let x = grid_column_index * console_width;
+    let y = grid_row_index * console_height;
+
+    return get_console_rect(x, y, console_width, console_height, workspace_area);
+}
+
+fn get_console_rect(
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    workspace_area: &workspace::WorkspaceArea,
+) -> (i32, i32, i32, i32) {
+    return (
+        workspace_area.x + x,
+        workspace_area.y + y,
+        width + workspace_area.x_fixed_frame + workspace_area.x_size_frame * 2,
+        height + workspace_area.y_size_frame * 2,
+    );
+}
+
+fn launch_client_console(
+    host: &str,
+    username: Option<String>,
+    index: usize,
+    client_window_rect: (i32, i32, i32, i32),
+    pipe_name: &str,
+    priority: ProcessPriority,
+    options: &LaunchOptions,
+) -> ClientWindow {
+    // The first argument must be `--` to ensure all following arguments are treated
+    // as positional arguments and not as options if they start with `-`.
+    let mut client_args: Vec<&str> = Vec::new();
+    if options.debug {
+        client_args.push("-d");
+        client_args.extend(vec!["--log-format", options.log_format.as_arg()]);
+    }
+    if let Some(profile) = options.profile.as_ref() {
+        client_args.extend(vec!["--profile", profile]);
+    }
+    if let Some(ansible_inventory) = options.ansible_inventory.as_ref() {
+        client_args.extend(vec!["--ansible-inventory", ansible_inventory]);
+    }
+    let default_username = DEFAULT_SSH_USERNAME_KEY.to_string();
+    let index_arg = index.to_string();
+    client_args.extend(vec!["client", "--pipe-name", pipe_name]);
+    client_args.extend(vec!["--index", index_arg.as_str()]);
+    if options.echo_only {
+        client_args.push("--echo-only");
+    }
+    for environment_variable in &options.environment {
+        client_args.extend(vec!["--env", environment_variable]);
+    }
+    if let Some(command) = options.command.as_ref() {
+        client_args.extend(vec!["--command", command]);
+    }
+    if options.username_from_whoami {
+        client_args.push("--username-from-whoami");
+    }
+    let port_arg = options.port.map(|port| return port.to_string());
+    if let Some(port_arg) = port_arg.as_ref() {
+        client_args.extend(vec!["--port", port_arg.as_str()]);
+    }
+    if options.reconnect {
+        client_args.push("--reconnect");
+    }
+    client_args.extend(vec![
+        "--",
+        host,
+        username.as_ref().unwrap_or(&default_username),
+    ]);
+    let process_information = spawn_console_process(
+        &format!("{PKG_NAME}.exe"),
+        client_args,
+        priority.priority_class(),
+    );
+    let process_id = process_information.dwProcessId;
+    let mut client_window_handle: Option<HWND> = None;
+    loop {
+        enumerate_windows(|handle| {
+            let mut window_process_id: u32 = 0;
+            unsafe { GetWindowThreadProcessId(handle, Some(&mut window_process_id)) };
+            if process_id == window_process_id {
+                client_window_handle = Some(handle);
+            }
+            return true;
+        });
+        if client_window_handle.is_some() {
+            break;
+        }
+    }
+    arrage_client_window(&client_window_handle.unwrap(), client_window_rect);
+    return ClientWindow {
+        hostname: host.to_owned(),
+        hwnd: client_window_handle.unwrap(),
+        process_id,
+        process_handle: process_information.hProcess,
+        state: PipeServerState::Enabled,
+        excluded: false,
+        last_heartbeat: Instant::now(),
+    };
+}
+
+/// Probes the latency of the given client's host and colors
+/// the client window border accordingly.
+fn color_client_border_by_latency(client_window: ClientWindow) {
+    tokio::spawn(async move {
+        let latency = probe::probe_latency(&client_window.hostname).await;
+        debug!(
+            "Latency of host `{}`: {:?}",
+            client_window.hostname, latency
+        );
+        set_window_border_color(client_window.hwnd, probe::get_latency_color(latency));
+    });
+}
+
+/// Waits for the given client process to exit and runs the
+/// `on_client_exited` hook afterwards.
+fn watch_client_exit(client_window: ClientWindow, on_client_exited: Vec<String>) {
+    if on_client_exited.is_empty() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut exit_code: u32 = STILL_ACTIVE.0 as u32;
+        while exit_code == STILL_ACTIVE.0 as u32 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            if let Err(err) =
+                unsafe { GetExitCodeProcess(client_window.process_handle, &mut exit_code) }
+            {
+                error!(
+                    "Failed to retrieve exit code of client `{}`: {}",
+                    client_window.hostname, err
+                );
+                return;
+            }
+        }
+        run_hook(
+            "on_client_exited",
+            &on_client_exited,
+            vec![
+                ("CSSHW_HOST", client_window.hostname),
+                ("CSSHW_PID", client_window.process_id.to_string()),
+                ("CSSHW_EXIT_CODE", exit_code.to_string()),
+            ],
+        );
+    });
+}
+
+/// Returns the process id of the client connected to the given named pipe server.
+fn get_named_pipe_client_process_id(server: &NamedPipeServer) -> u32 {
+    let mut process_id: u32 = 0;
+    if let Err(err) = unsafe {
+        GetNamedPipeClientProcessId(HANDLE(server.as_raw_handle() as isize), &mut process_id)
+    } {
+        error!("Failed to retrieve named pipe client process id: {}", err);
+    }
+    return process_id;
+}
+
+/// Enables all clients if any of them is disabled, otherwise disables all clients.
+fn toggle_all_clients(client_console_window_handles: &mut BTreeMap<usize, ClientWindow>) {
+    let state = if client_console_window_handles
+        .values()
+        .any(|client_window| return client_window.state == PipeServerState::Disabled)
+    {
+        PipeServerState::Enabled
+    } else {
+        PipeServerState::Disabled
+    };
+    for client_window in client_console_window_handles.values_mut() {
+        client_window.state = state;
+    }
+}
+
+/// Waits until the named pipe servers forwarded all input broadcasted so far,
+/// giving up after [`BROADCAST_DRAIN_TIMEOUT`] in case a client doesn't keep up.
+async fn wait_for_broadcast_drained(sender: &Sender<[u8; SERIALIZED_INPUT_RECORD_0_LENGTH]>) {
+    let start = Instant::now();
+    loop {
+        if sender.is_empty() || start.elapsed() >= BROADCAST_DRAIN_TIMEOUT {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+}
+
+/// Returns whether the client with the given process id should receive the broadcasted input.
+///
+/// Unknown clients are considered enabled.
+fn is_client_enabled(
+    client_console_window_handles: &Arc<Mutex<BTreeMap<usize, ClientWindow>>>,
+    process_id: u32,
+) -> bool {
+    return !client_console_window_handles
+        .lock()
+        .unwrap()
+        .values()
+        .any(|client_window| {
+            return client_window.process_id == process_id
+                && (client_window.state == PipeServerState::Disabled || client_window.excluded);
+        });
+}
+
+/// Returns the hostname of the client with the given process id.
+fn get_client_hostname(
+    client_console_window_handles: &BTreeMap<usize, ClientWindow>,
+    process_id: u32,
+) -> Option<String> {
+    return client_console_window_handles
+        .values()
+        .find(|client_window| return client_window.process_id == process_id)
+        .map(|client_window| return client_window.hostname.clone());
+}
+
+/// Reads all pending packets sent by the client.
+///
+/// An incomplete packet remains in the `buffer` until the rest of it was received.
+fn receive_client_packets(server: &NamedPipeServer, buffer: &mut Vec<u8>) -> Vec<ClientPacket> {
+    let mut buf = [0; MAX_OUTPUT_PACKET_LENGTH * 4];
+    while let Ok(number_of_bytes @ 1..) = server.try_read(&mut buf) {
+        buffer.extend(&buf[..number_of_bytes]);
+    }
+    return deserialize_client_packets(buffer);
+}
+
+/// Passes the output contained in the `client_packets` of the client with the given
+/// process id to the `monitor_pane`.
+fn push_client_output(
+    monitor_pane: &Mutex<MonitorPane>,
+    client_console_window_handles: &Arc<Mutex<BTreeMap<usize, ClientWindow>>>,
+    process_id: u32,
+    client_packets: Vec<ClientPacket>,
+) {
+    let mut hostname: Option<String> = None;
+    for client_packet in client_packets {
+        if let ClientPacket::Output(output) = client_packet {
+            let hostname = hostname.get_or_insert_with(|| {
+                return get_client_hostname(
+                    &client_console_window_handles.lock().unwrap(),
+                    process_id,
+                )
+                .unwrap_or_default();
+            });
+            monitor_pane.lock().unwrap().push_output(hostname, &output);
+        }
+    }
+}
+
+/// Prints the lines collected by the `monitor_pane` to the daemon console.
+fn print_monitor_pane(monitor_pane: Arc<Mutex<MonitorPane>>) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(MONITOR_PANE_PRINT_INTERVAL).await;
+            let lines = monitor_pane.lock().unwrap().take_lines();
+            for line in lines {
+                println!("{}", line);
+            }
+        }
+    });
+}
+
+/// Records a heartbeat of the client with the given process id.
+fn record_heartbeat(
+    client_console_window_handles: &Arc<Mutex<BTreeMap<usize, ClientWindow>>>,
+    process_id: u32,
+) {
+    for client_window in client_console_window_handles.lock().unwrap().values_mut() {
+        if client_window.process_id == process_id {
+            client_window.last_heartbeat = Instant::now();
+        }
+    }
+}
+
+/// Removes the client with the given process id if it didn't send a heartbeat
+/// for `heartbeat_timeout`.
+///
+/// Returns whether the client was removed.
+fn remove_unresponsive_client(
+    client_console_window_handles: &Arc<Mutex<BTreeMap<usize, ClientWindow>>>,
+    process_id: u32,
+    heartbeat_timeout: Duration,
+) -> bool {
+    let mut client_console_window_handles = client_console_window_handles.lock().unwrap();
+    let index = match client_console_window_handles
+        .iter()
+        .find(|(_, client_window)| {
+            return client_window.process_id == process_id
+                && client_window.last_heartbeat.elapsed() >= heartbeat_timeout;
+        })
+        .map(|(index, _)| return *index)
+    {
+        Some(index) => index,
+        None => return false,
+    };
+    if let Some(client_window) = client_console_window_handles.remove(&index) {
+        warn!(
+            "Removing client `{}` (pid {}), it sent no heartbeat for {} seconds",
+            client_window.hostname,
+            process_id,
+            heartbeat_timeout.as_secs()
+        );
+    }
+    return true;
+}
+
+/// Highlights the border of clients that didn't send a heartbeat for `heartbeat_timeout`
+/// and resets it once they are responsive again.
+fn detect_unresponsive_clients(
+    client_console_window_handles: Arc<Mutex<BTreeMap<usize, ClientWindow>>>,
+    heartbeat_timeout: Duration,
+) {
+    tokio::spawn(async move {
+        let mut unresponsive_process_ids: HashSet<u32> = HashSet::new();
+        loop {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            for client_window in client_console_window_handles.lock().unwrap().values() {
+                if !unsafe { IsWindow(client_window.hwnd) }.as_bool() {
+                    continue;
+                }
+                let unresponsive = client_window.last_heartbeat.elapsed() >= heartbeat_timeout;
+                if unresponsive && unresponsive_process_ids.insert(client_window.process_id) {
+                    warn!(
+                        "Client `{}` sent no heartbeat for {} seconds",
+                        client_window.hostname,
+                        heartbeat_timeout.as_secs()
+                    );
+                    set_window_border_color(client_window.hwnd, UNRESPONSIVE_CLIENT_BORDER_COLOR);
+                } else if !unresponsive
+                    && unresponsive_process_ids.remove(&client_window.process_id)
+                {
+                    info!("Client `{}` is responsive again", client_window.hostname);
+                    set_window_border_color(client_window.hwnd, COLORREF(DWMWA_COLOR_DEFAULT));
+                }
+            }
+        }
+    });
+}
+
+/// Waits for a client to connect to the named pipe server and runs the
+/// [`named_pipe_server_routine`] for it.
+///
+/// Should the routine panic, it is restarted on the same connection (with a fresh
+/// receiver), so a single failure doesn't silently stop input to the client.
+async fn supervise_named_pipe_server_routine(
+    server: NamedPipeServer,
+    receiver: Receiver<[u8; SERIALIZED_INPUT_RECORD_0_LENGTH]>,
+    sender: Sender<[u8; SERIALIZED_INPUT_RECORD_0_LENGTH]>,
+    client_console_window_handles: Arc<Mutex<BTreeMap<usize, ClientWindow>>>,
+    keep_alive_interval: Duration,
+    unresponsive_client_timeout: Option<Duration>,
+    monitor_pane: Option<Arc<Mutex<MonitorPane>>>,
+) {
+    // wait for a client to connect
+    server.connect().await.unwrap_or_else(|err| {
+        error!("{}", err);
+        panic!("Timeded out waiting for clients to connect to named pipe server",)
+    });
+    let client_process_id = get_named_pipe_client_process_id(&server);
+    // The client may connect long after its window was created, e.g. while ssh was
+    // prompting for a password, count the connection as its first heartbeat.
+    record_heartbeat(&client_console_window_handles, client_process_id);
+    let server = Arc::new(server);
+    let mut receiver = Some(receiver);
+    restart_on_panic(client_process_id, || {
+        let mut _receiver = receiver.take().unwrap_or_else(|| return sender.subscribe());
+        let _server = Arc::clone(&server);
+        let _client_console_window_handles = Arc::clone(&client_console_window_handles);
+        let _monitor_pane = monitor_pane.clone();
+        return tokio::spawn(async move {
+            named_pipe_server_routine(
+                &_server,
+                &mut _receiver,
+                _client_console_window_handles,
+                client_process_id,
+                keep_alive_interval,
+                unresponsive_client_timeout,
+                _monitor_pane,
+            )
+            .await;
+        });
+    })
+    .await;
+}
+
+/// Awaits the routine spawned by `spawn_routine` for the given client, spawning it anew
+/// up to `MAX_NAMED_PIPE_SERVER_ROUTINE_RESTARTS` times if it panicked.
+///
+/// Returns the number of restarts.
+async fn restart_on_panic<F>(client_process_id: u32, mut spawn_routine: F) -> usize
+where
+    F: FnMut() -> JoinHandle<()>,
+{
+    let mut restarts = 0;
+    loop {
+        match spawn_routine().await {
+            Ok(_) => return restarts,
+            Err(err) if err.is_panic() && restarts < MAX_NAMED_PIPE_SERVER_ROUTINE_RESTARTS => {
+                restarts += 1;
+                error!(
+                    "Named pipe server routine for client {} panicked, restarting it ({}/{})",
+                    client_process_id, restarts, MAX_NAMED_PIPE_SERVER_ROUTINE_RESTARTS
+                );
+            }
+            Err(err) => {
+                error!(
+                    "Named pipe server routine for client {} stopped: {}",
+                    client_process_id, err
+                );
+                return restarts;
+            }
+        }
+    }
+}
+
+/// Forwards the broadcasted input records to the client connected to the named pipe server.
+///
+/// Whenever nothing was written for `keep_alive_interval`, a [`KEEP_ALIVE_PACKET`] is sent,
+/// which lets the client detect a lost daemon and the routine detect an exited client.
+///
+/// If the client sent no heartbeat for `unresponsive_client_timeout`, it is removed
+/// and the routine stops, closing the named pipe.
+///
+/// The output the client streams is passed to the `monitor_pane`, if enabled.
+pub async fn named_pipe_server_routine(
+    server: &NamedPipeServer,
+    receiver: &mut Receiver<[u8; SERIALIZED_INPUT_RECORD_0_LENGTH]>,
+    client_console_window_handles: Arc<Mutex<BTreeMap<usize, ClientWindow>>>,
+    client_process_id: u32,
+    keep_alive_interval: Duration,
+    unresponsive_client_timeout: Option<Duration>,
+    monitor_pane: Option<Arc<Mutex<MonitorPane>>>,
+) {
+    // Number of input records the client missed so far because it lagged behind.
+    let mut total_skipped: u64 = 0;
+    // Bytes received from the client that don't form a complete packet yet.
+    let mut client_packet_buffer: Vec<u8> = Vec::new();
+    // Whether the client was last told that it receives the broadcasted input.
+    let mut client_notified_enabled = true;
+    // Time the client was last written to, real input or keep-alive.
+    let mut last_write = Instant::now();
+    loop {
+        let client_enabled = is_client_enabled(&client_console_window_handles, client_process_id);
+        if client_enabled != client_notified_enabled {
+            let packet = if client_enabled {
+                ENABLED_PACKET
+            } else {
+                DISABLED_PACKET
+            };
+            // Retried in the next iteration if the pipe is full.
+            if let Ok(SERIALIZED_INPUT_RECORD_0_LENGTH) = server.try_write(&packet) {
+                client_notified_enabled = client_enabled;
+                last_write = Instant::now();
+            }
+        }
+        let ser_input_record = match receiver.try_recv() {
+            Ok(val) => val,
+            Err(TryRecvError::Empty) => {
+                let client_packets = receive_client_packets(server, &mut client_packet_buffer);
+                if !client_packets.is_empty() {
+                    record_heartbeat(&client_console_window_handles, client_process_id);
+                }
+                if let Some(monitor_pane) = monitor_pane.as_deref() {
+                    push_client_output(
+                        monitor_pane,
+                        &client_console_window_handles,
+                        client_process_id,
+                        client_packets,
+                    );
+                }
+                if let Some(unresponsive_client_timeout) = unresponsive_client_timeout {
+                    if remove_unresponsive_client(
+                        &client_console_window_handles,
+                        client_process_id,
+                        unresponsive_client_timeout,
+                    ) {
+                        return;
+                    }
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                if last_write.elapsed() < keep_alive_interval {
+                    continue;
+                }
+                // Send a keep-alive to detect early if the pipe is closed because the client exited
+                match server.try_write(&KEEP_ALIVE_PACKET) {
+                    Ok(_) => {
+                        last_write = Instant::now();
+                        continue;
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                    Err(_) => {
+                        debug!(
+                            "Named pipe server ({:?}) is closed, stopping named pipe server routine",
+                            server
+                        );
+                        return;
+                    }
+                }
+            }
+            Err(TryRecvError::Lagged(skipped)) => {
+                // The client is too slow to keep up, it misses some input records
+                // but stays attached instead of going permanently deaf.
+                total_skipped += skipped;
+                let hostname = get_client_hostname(
+                    &client_console_window_handles.lock().unwrap(),
+                    client_process_id,
+                )
+                .unwrap_or_default();
+                warn!(
+                    "Client `{}` (pid {}) lagged behind, skipped {} input records ({} in total)",
+                    hostname, client_process_id, skipped, total_skipped
+                );
+                continue;
+            }
+            Err(err) => {
+                error!("{}", err);
+                panic!("Failed to receive data from the Receiver");
+            }
+        };
+        // Disabled clients have to quit as well.
+        if ser_input_record != SHUTDOWN_PACKET
+            && !is_client_enabled(&client_console_window_handles, client_process_id)
+        {
+            continue;
+        }
+        loop {
+            server.writable().await.unwrap_or_else(|err| {
+                error!("{}", err);
+                panic!("Timed out waiting for named pipe server to become writable",)
+            });
+            match server.try_write(&ser_input_record) {
+                Ok(SERIALIZED_INPUT_RECORD_0_LENGTH) => {
+                    debug!("Successfully written all data");
+                    last_write = Instant::now();
+                    break;
+                }
+                Ok(n) => {
+                    // The data was only written partially, try again
+                    warn!(
+                        "Partially written data, expected {} but only wrote {}",
+                        SERIALIZED_INPUT_RECORD_0_LENGTH, n
+                    );
+                    continue;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    // Try again
+                    debug!("Writing to named pipe server would have blocked");
+                    continue;
+                }
+                Err(_) => {
+                    // Can happen if the pipe is closed because the
+                    // client exited
+                    debug!(
+                        "Named pipe server ({:?}) is closed, stopping named pipe server routine",
+                        server
+                    );
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Calls `callback` with the index of each of the `items` and the item itself,
+/// waiting `stagger` between two consecutive calls.
+async fn for_each_staggered<T, F>(items: Vec<T>, stagger: Duration, mut callback: F)
+where
+    F: FnMut(usize, T),
+{
+    for (index, item) in items.into_iter().enumerate() {
+        if index > 0 && !stagger.is_zero() {
+            tokio::time::sleep(stagger).await;
+        }
+        callback(index, item);
+    }
+    return;
+}
+
+/// Launches a client console for each given host and
+/// waits for the client windows to exist before
+/// returning their handles.
+async fn launch_clients(
+    hosts: Vec<String>,
+    username: &Option<String>,
+    workspace_grids: &[WorkspaceGrid],
+    config: &DaemonConfig,
+    pipe_name: &str,
+    options: &LaunchOptions,
+) -> BTreeMap<usize, ClientWindow> {
+    let result = Arc::new(Mutex::new(BTreeMap::new()));
+    let mut handles = vec![];
+    let launch_stagger = Duration::from_millis(config.launch_stagger_ms);
+    for_each_staggered(hosts, launch_stagger, |index, host| {
+        let _username = username.clone();
+        let client_window_rect = determine_client_spatial_attributes(index as i32, workspace_grids);
+        let result_arc = Arc::clone(&result);
+        let on_client_connected = config.on_client_connected.clone();
+        let on_client_exited = config.on_client_exited.clone();
+        let _pipe_name = pipe_name.to_owned();
+        let client_priority = config.client_priority;
+        let _options = options.clone();
+        let future = tokio::spawn(async move {
+            let client_window = launch_client_console(
+                &host,
+                _username,
+                index,
+                client_window_rect,
+                &_pipe_name,
+                client_priority,
+                &_options,
+            );
+            run_hook(
+                "on_client_connected",
+                &on_client_connected,
+                vec![
+                    ("CSSHW_HOST", client_window.hostname.clone()),
+                    ("CSSHW_PID", client_window.process_id.to_string()),
+                ],
+            );
+            watch_client_exit(client_window.clone(), on_client_exited);
+            if _options.latency_colors {
+                color_client_border_by_latency(client_window.clone());
+            }
+            result_arc.lock().unwrap().insert(index, client_window);
+        });
+        handles.push(future);
+    })
+    .await;
+    for handle in handles {
+        handle.await.unwrap();
+    }
+    return result.lock().unwrap().clone();
+}
+
+fn enumerate_windows<F>(mut callback: F)
+where
+    F: FnMut(HWND) -> bool,
+{
+    let mut trait_obj: &mut dyn FnMut(HWND) -> bool = &mut callback;
+    let closure_pointer_pointer: *mut c_void = unsafe { mem::transmute(&mut trait_obj) };
+
+    let lparam = LPARAM(closure_pointer_pointer as isize);
+    unsafe { EnumWindows(Some(enumerate_callback), lparam).unwrap() };
+}
+
+unsafe extern "system" fn enumerate_callback(hwnd: HWND, lparam: LPARAM) -> BOOL {
+    let closure: &mut &mut dyn FnMut(HWND) -> bool = &mut *(lparam.0 as *mut c_void
+        as *mut &mut dyn std::ops::FnMut(windows::Win32::Foundation::HWND) -> bool);
+    if closure(hwnd) {
+        return TRUE;
+    } else {
+        return FALSE;
+    }
+}
+
+/// Enables mouse input in the daemon console, which requires disabling the
+/// quick edit mode as it would otherwise consume the mouse events.
+fn enable_mouse_input_mode() {
+    let handle = get_console_input_buffer();
+    let mut mode = CONSOLE_MODE(0u32);
+    unsafe {
+        GetConsoleMode(handle, &mut mode).unwrap();
+    }
+    unsafe {
+        SetConsoleMode(
+            handle,
+            CONSOLE_MODE(
+                (mode.0 | ENABLE_MOUSE_INPUT.0 | ENABLE_EXTENDED_FLAGS.0)
+                    & !ENABLE_QUICK_EDIT_MODE.0,
+            ),
+        )
+        .unwrap();
+    }
+}
+
+fn disable_processed_input_mode() {
+    let handle = get_console_input_buffer();
+    let mut mode = CONSOLE_MODE(0u32);
+    unsafe {
+        GetConsoleMode(handle, &mut mode).unwrap();
+    }
+    unsafe {
+        SetConsoleMode(handle, CONSOLE_MODE(mode.0 ^ ENABLE_PROCESSED_INPUT.0)).unwrap();
+    }
+}
+
+pub async fn main(
+    hosts: Vec<String>,
+    username: Option<String>,
+    clusters: Vec<Cluster>,
+    active_sets: Vec<ActiveSet>,
+    config: &DaemonConfig,
+    options: LaunchOptions,
+) {
+    let daemon: Daemon = Daemon {
+        hosts,
+        username,
+        clusters,
+        config,
+        control_mode_state: ControlModeState::Inactive,
+        // Make the pipe name unique per daemon, so multiple daemons don't share clients.
+        pipe_name: format!("{}-{}", PIPE_NAME, std::process::id()),
+        options,
+        active_sets,
+        active_set_name: None,
+        last_control_mode_activity: Arc::new(Mutex::new(Instant::now())),
+        last_broadcast_activity: Arc::new(Mutex::new(Instant::now())),
+        locked: Arc::new(AtomicBool::new(false)),
+        title: Arc::new(Mutex::new(String::new())),
+        last_key_press: Arc::new(Mutex::new(Instant::now())),
+        broadcast_to_others: false,
+        frozen: false,
+        last_focused_client: Arc::new(Mutex::new(None)),
+        recorded_macro: None,
+        zoomed_client: None,
+        selected_client: None,
+        selection_highlight: BorderHighlight::default(),
+        client_selection: ClientSelection::EnableDisable,
+        monitor_pane: config.monitor_pane.then(|| {
+            return Arc::new(Mutex::new(MonitorPane::new(
+                config.monitor_pane_lines_per_host,
+            )));
+        }),
+    };
+    daemon.launch().await;
+    debug!("Actually exiting");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serde::{serialize_output_packet, HEARTBEAT_TAG};
+    use tokio::net::windows::named_pipe::{ClientOptions, NamedPipeClient};
+    use windows::Win32::UI::Input::KeyboardAndMouse::VK_3;
+
+    fn client_window(hostname: &str, process_id: u32) -> ClientWindow {
+        return ClientWindow {
+            hostname: hostname.to_owned(),
+            hwnd: HWND(process_id as isize),
+            process_id,
+            process_handle: HANDLE::default(),
+            state: PipeServerState::Enabled,
+            excluded: false,
+            last_heartbeat: Instant::now(),
+        };
+    }
+
+    /// Returns client windows for the given hostnames, with process ids starting at 1.
+    fn client_windows(hostnames: &[&str]) -> BTreeMap<usize, ClientWindow> {
+        return hostnames
+            .iter()
+            .enumerate()
+            .map(|(index, hostname)| return (index, client_window(hostname, index as u32 + 1)))
+            .collect();
+    }
+
+    /// Returns a key event of the given virtual key with the given control key state.
+    fn key_event(
+        key_down: bool,
+        virtual_key: VIRTUAL_KEY,
+        control_key_state: u32,
+    ) -> KEY_EVENT_RECORD {
+        return KEY_EVENT_RECORD {
+            bKeyDown: if key_down { TRUE } else { FALSE },
+            wVirtualKeyCode: virtual_key.0,
+            dwControlKeyState: control_key_state,
             ..Default::default()
         };
-        match unsafe { GetWindowPlacement(handle.hwnd, &mut placement) } {
-            Ok(_) => {}
-            Err(_) => {
-                continue;
+    }
+
+    #[test]
+    fn test_get_next_control_mode_state_initiated_by_ctrl_a() {
+        let ctrl_a = key_event(true, VK_A, LEFT_CTRL_PRESSED);
+        assert_eq!(
+            get_next_control_mode_state(&ControlModeState::Inactive, &ctrl_a),
+            ControlModeState::Initiated
+        );
+        let right_ctrl_a = key_event(true, VK_A, RIGHT_CTRL_PRESSED);
+        assert_eq!(
+            get_next_control_mode_state(&ControlModeState::Inactive, &right_ctrl_a),
+            ControlModeState::Initiated
+        );
+        assert_eq!(
+            get_next_control_mode_state(&ControlModeState::Inactive, &key_event(true, VK_A, 0)),
+            ControlModeState::Inactive
+        );
+    }
+
+    #[test]
+    fn test_get_next_control_mode_state_ctrl_a_twice_sends_literal_ctrl_a() {
+        let mut control_mode_state = ControlModeState::Inactive;
+        for event in [
+            key_event(true, VK_A, LEFT_CTRL_PRESSED),
+            key_event(false, VK_A, LEFT_CTRL_PRESSED),
+            key_event(true, VK_A, LEFT_CTRL_PRESSED),
+        ] {
+            control_mode_state = match get_next_control_mode_state(&control_mode_state, &event) {
+                // Like the daemon, which prints the menu once control mode is initiated.
+                ControlModeState::Initiated => ControlModeState::Active,
+                next_control_mode_state => next_control_mode_state,
+            };
+        }
+        // Leaving control mode broadcasts the second Ctrl+A.
+        assert_eq!(control_mode_state, ControlModeState::Inactive);
+    }
+
+    #[test]
+    fn test_get_next_control_mode_state_stays_active_for_menu_keys() {
+        assert_eq!(
+            get_next_control_mode_state(&ControlModeState::Active, &key_event(true, VK_R, 0)),
+            ControlModeState::Active
+        );
+        assert_eq!(
+            get_next_control_mode_state(
+                &ControlModeState::Active,
+                &key_event(false, VK_A, LEFT_CTRL_PRESSED)
+            ),
+            ControlModeState::Active
+        );
+        assert_eq!(
+            get_next_control_mode_state(&ControlModeState::Active, &key_event(true, VK_ESCAPE, 0)),
+            ControlModeState::Inactive
+        );
+    }
+
+    #[test]
+    fn test_get_exited_hosts_returns_missing_host() {
+        let hosts = vec!["web1".to_owned(), "web2".to_owned(), "db1".to_owned()];
+        assert_eq!(get_exited_hosts(&hosts, &["db1", "web1"]), vec!["web2"]);
+        assert!(get_exited_hosts(&hosts, &["web1", "web2", "db1"]).is_empty());
+    }
+
+    #[test]
+    fn test_get_exited_hosts_counts_duplicate_hosts() {
+        let hosts = vec!["web1".to_owned(), "web1".to_owned(), "db1".to_owned()];
+        assert_eq!(get_exited_hosts(&hosts, &["web1", "db1"]), vec!["web1"]);
+        assert_eq!(get_exited_hosts(&hosts, &[]), hosts);
+    }
+
+    #[test]
+    fn test_get_next_client_index_appends_after_highest_index() {
+        assert_eq!(get_next_client_index(&BTreeMap::new()), 0);
+        let mut client_console_window_handles = client_windows(&["a", "b", "c"]);
+        client_console_window_handles.remove(&1);
+        // The number of remaining client windows is an index still in use.
+        assert_eq!(get_next_client_index(&client_console_window_handles), 3);
+    }
+
+    #[test]
+    fn test_enabled_state_follows_the_client_when_sorting() {
+        let mut client_console_window_handles = client_windows(&["host10", "host2", "host1"]);
+        client_console_window_handles.get_mut(&1).unwrap().state = PipeServerState::Disabled;
+
+        sort_client_windows(&mut client_console_window_handles);
+
+        let hostnames: Vec<&str> = client_console_window_handles
+            .values()
+            .map(|client_window| return client_window.hostname.as_str())
+            .collect();
+        assert_eq!(hostnames, vec!["host1", "host2", "host10"]);
+        assert_eq!(
+            client_console_window_handles[&1].state,
+            PipeServerState::Disabled
+        );
+        let client_console_window_handles = Arc::new(Mutex::new(client_console_window_handles));
+        assert!(is_client_enabled(&client_console_window_handles, 1));
+        assert!(!is_client_enabled(&client_console_window_handles, 2));
+        assert!(is_client_enabled(&client_console_window_handles, 3));
+    }
+
+    #[test]
+    fn test_get_client_window_rects_fixed_grid() {
+        let workspace_area = WorkspaceArea::new_logical(100, 0, 1000, 800);
+        assert_eq!(
+            get_client_window_rects(
+                3,
+                &[workspace_area],
+                &DaemonConfig::default(),
+                Some((2, 2)),
+                false
+            ),
+            vec![(100, 0, 500, 400), (600, 0, 500, 400), (100, 400, 500, 400)]
+        );
+    }
+
+    #[test]
+    fn test_get_client_window_rects_stretches_last_row() {
+        let workspace_area = WorkspaceArea::new_logical(0, 0, 1600, 800);
+        assert_eq!(
+            get_client_window_rects(5, &[workspace_area], &DaemonConfig::default(), None, false),
+            vec![
+                (0, 0, 800, 266),
+                (800, 0, 800, 266),
+                (0, 266, 800, 266),
+                (800, 266, 800, 266),
+                (0, 532, 1600, 266)
+            ]
+        );
+    }
+
+    /// Returns the (`column`, `row`) of each of the 9 clients in a 3x3 grid.
+    fn get_grid_positions(fill_direction: FillDirection) -> Vec<(i32, i32)> {
+        let workspace_area = WorkspaceArea::new_logical(0, 0, 900, 900);
+        let config = DaemonConfig {
+            fill_direction,
+            ..Default::default()
+        };
+        return get_client_window_rects(9, &[workspace_area], &config, Some((3, 3)), false)
+            .into_iter()
+            .map(|(x, y, width, height)| {
+                assert_eq!((width, height), (300, 300));
+                return (x / 300, y / 300);
+            })
+            .collect();
+    }
+
+    #[test]
+    fn test_get_client_window_rects_fills_row_major() {
+        assert_eq!(
+            get_grid_positions(FillDirection::RowMajor),
+            vec![
+                (0, 0),
+                (1, 0),
+                (2, 0),
+                (0, 1),
+                (1, 1),
+                (2, 1),
+                (0, 2),
+                (1, 2),
+                (2, 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get_client_window_rects_fills_column_major() {
+        assert_eq!(
+            get_grid_positions(FillDirection::ColumnMajor),
+            vec![
+                (0, 0),
+                (0, 1),
+                (0, 2),
+                (1, 0),
+                (1, 1),
+                (1, 2),
+                (2, 0),
+                (2, 1),
+                (2, 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get_client_window_rects_column_major_stretches_last_column() {
+        let workspace_area = WorkspaceArea::new_logical(0, 0, 1600, 800);
+        let config = DaemonConfig {
+            fill_direction: FillDirection::ColumnMajor,
+            ..Default::default()
+        };
+        assert_eq!(
+            get_client_window_rects(5, &[workspace_area], &config, None, false),
+            vec![
+                (0, 0, 800, 266),
+                (0, 266, 800, 266),
+                (0, 532, 800, 266),
+                (800, 0, 800, 400),
+                (800, 400, 800, 400)
+            ]
+        );
+    }
+
+    /// Whether the two (`x`, `y`, `width`, `height`) rects intersect.
+    fn overlaps(first: (i32, i32, i32, i32), second: (i32, i32, i32, i32)) -> bool {
+        let (first_x, first_y, first_width, first_height) = first;
+        let (second_x, second_y, second_width, second_height) = second;
+        return first_x < second_x + second_width
+            && second_x < first_x + first_width
+            && first_y < second_y + second_height
+            && second_y < first_y + first_height;
+    }
+
+    #[test]
+    fn test_daemon_console_takes_grid_cell() {
+        let workspace_area = WorkspaceArea::new_logical(0, 0, 1600, 800);
+        for (daemon_position, daemon_console_rect) in [
+            (DaemonPosition::TopLeft, (0, 0, 800, 266)),
+            (DaemonPosition::BottomLeft, (0, 532, 800, 266)),
+            (DaemonPosition::BottomRight, (800, 532, 800, 266)),
+        ] {
+            for fill_direction in [FillDirection::RowMajor, FillDirection::ColumnMajor] {
+                let config = DaemonConfig {
+                    daemon_position,
+                    fill_direction,
+                    ..Default::default()
+                };
+                // The 5 clients and the daemon console share a 2x3 grid.
+                let daemon_rect =
+                    get_daemon_console_rect(5, &[workspace_area], &config, None, false);
+                assert_eq!(daemon_rect, daemon_console_rect);
+                let client_rects =
+                    get_client_window_rects(5, &[workspace_area], &config, None, false);
+                assert_eq!(client_rects.len(), 5);
+                for client_rect in client_rects {
+                    assert_eq!((client_rect.2, client_rect.3), (800, 266));
+                    assert!(
+                        !overlaps(daemon_rect, client_rect),
+                        "{:?} client {:?} overlaps daemon {:?}",
+                        daemon_position,
+                        client_rect,
+                        daemon_rect
+                    );
+                }
             }
         }
-        if placement.showCmd == SW_SHOWMINIMIZED.0.try_into().unwrap() {
-            let _ = unsafe { ShowWindow(handle.hwnd, SW_RESTORE) };
+    }
+
+    #[test]
+    fn test_daemon_console_takes_bottom_right_cell_of_fixed_grid() {
+        let workspace_area = WorkspaceArea::new_logical(0, 0, 1600, 800);
+        let config = DaemonConfig {
+            daemon_position: DaemonPosition::BottomRight,
+            ..Default::default()
+        };
+        assert_eq!(
+            get_daemon_console_rect(2, &[workspace_area], &config, Some((2, 2)), false),
+            (800, 400, 800, 400)
+        );
+        assert_eq!(
+            get_client_window_rects(2, &[workspace_area], &config, Some((2, 2)), false),
+            vec![(0, 0, 800, 400), (800, 0, 800, 400)]
+        );
+    }
+
+    #[test]
+    fn test_hidden_daemon_console_overlaps_grid_cell() {
+        let workspace_area = WorkspaceArea::new_logical(0, 0, 1600, 800);
+        let config = DaemonConfig {
+            daemon_position: DaemonPosition::TopLeft,
+            ..Default::default()
+        };
+        // The clients use the whole grid, the revealed daemon console overlaps the first one.
+        assert_eq!(
+            get_client_window_rects(4, &[workspace_area], &config, None, true),
+            vec![
+                (0, 0, 800, 400),
+                (800, 0, 800, 400),
+                (0, 400, 800, 400),
+                (800, 400, 800, 400)
+            ]
+        );
+        assert_eq!(
+            get_daemon_console_rect(4, &[workspace_area], &config, None, true),
+            (0, 0, 800, 266)
+        );
+    }
+
+    #[test]
+    fn test_daemon_console_spans_workspace_edge() {
+        let workspace_area = WorkspaceArea::new_logical(0, 0, 1600, 800);
+        let config = DaemonConfig {
+            daemon_position: DaemonPosition::Bottom,
+            ..Default::default()
+        };
+        assert_eq!(
+            get_daemon_console_rect(4, &[workspace_area], &config, None, false),
+            (0, 800, 1600, 200)
+        );
+    }
+
+    #[test]
+    fn test_distribute_clients() {
+        let first_monitor = WorkspaceArea::new_logical(0, 0, 1600, 800);
+        let second_monitor = WorkspaceArea::new_logical(1600, 0, 1600, 800);
+        assert_eq!(distribute_clients(5, &[first_monitor]), vec![5]);
+        assert_eq!(
+            distribute_clients(5, &[first_monitor, second_monitor]),
+            vec![3, 2]
+        );
+        assert_eq!(
+            distribute_clients(0, &[first_monitor, second_monitor]),
+            vec![0, 0]
+        );
+        // Larger monitors take proportionally more clients.
+        let wide_monitor = WorkspaceArea::new_logical(0, 0, 1920, 1080);
+        let narrow_monitor = WorkspaceArea::new_logical(1920, 0, 960, 1080);
+        assert_eq!(
+            distribute_clients(9, &[wide_monitor, narrow_monitor]),
+            vec![6, 3]
+        );
+        assert_eq!(
+            distribute_clients(1, &[narrow_monitor, wide_monitor]),
+            vec![0, 1]
+        );
+    }
+
+    #[test]
+    fn test_get_client_window_rects_spreads_across_monitors() {
+        let first_monitor = WorkspaceArea::new_logical(0, 0, 1600, 800);
+        // Frames are sized per monitor, as their DPI can differ.
+        let mut second_monitor = WorkspaceArea::new_logical(1600, 0, 1600, 800);
+        second_monitor.x_size_frame = 4;
+        second_monitor.y_size_frame = 4;
+        assert_eq!(
+            get_client_window_rects(
+                4,
+                &[first_monitor, second_monitor],
+                &DaemonConfig::default(),
+                None,
+                false
+            ),
+            vec![
+                (0, 0, 1600, 400),
+                (0, 400, 1600, 400),
+                (1600, 0, 1600 + 4 * 2, 400 + 4 * 2),
+                (1600, 400, 1600 + 4 * 2, 400 + 4 * 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_daemon_console_takes_grid_cell_of_first_monitor() {
+        let workspace_areas = [
+            WorkspaceArea::new_logical(0, 0, 1600, 800),
+            WorkspaceArea::new_logical(1600, 0, 1600, 800),
+        ];
+        let config = DaemonConfig {
+            daemon_position: DaemonPosition::TopLeft,
+            ..Default::default()
+        };
+        let daemon_rect = get_daemon_console_rect(3, &workspace_areas, &config, None, false);
+        assert_eq!(daemon_rect, (0, 0, 1600, 266));
+        let client_rects = get_client_window_rects(3, &workspace_areas, &config, None, false);
+        assert_eq!(
+            client_rects,
+            vec![
+                (0, 266, 1600, 266),
+                (0, 532, 1600, 266),
+                (1600, 0, 1600, 800)
+            ]
+        );
+        for client_rect in client_rects {
+            assert!(!overlaps(daemon_rect, client_rect));
         }
-        // Then bring it to front using UI automation
-        let automation: IUIAutomation =
-            unsafe { CoCreateInstance(&CUIAutomation, None, CLSCTX_ALL) }.unwrap();
-        if let Ok(window) = unsafe { automation.ElementFromHandle(handle.hwnd) } {
-            unsafe { window.SetFocus() }.unwrap();
+    }
+
+    /// Returns the states of the client windows in order.
+    fn client_states(
+        client_console_window_handles: &BTreeMap<usize, ClientWindow>,
+    ) -> Vec<PipeServerState> {
+        return client_console_window_handles
+            .values()
+            .map(|client_window| return client_window.state)
+            .collect();
+    }
+
+    #[test]
+    fn test_toggle_all_clients_enables_mixed_states() {
+        let mut client_console_window_handles = client_windows(&["web1", "web2", "web3"]);
+        client_console_window_handles.get_mut(&1).unwrap().state = PipeServerState::Disabled;
+        toggle_all_clients(&mut client_console_window_handles);
+        assert_eq!(
+            client_states(&client_console_window_handles),
+            vec![PipeServerState::Enabled; 3]
+        );
+    }
+
+    #[test]
+    fn test_toggle_all_clients_disables_all_enabled() {
+        let mut client_console_window_handles = client_windows(&["web1", "web2", "web3"]);
+        toggle_all_clients(&mut client_console_window_handles);
+        assert_eq!(
+            client_states(&client_console_window_handles),
+            vec![PipeServerState::Disabled; 3]
+        );
+        toggle_all_clients(&mut client_console_window_handles);
+        assert_eq!(
+            client_states(&client_console_window_handles),
+            vec![PipeServerState::Enabled; 3]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_broadcast_drained() {
+        let (sender, mut receiver) = broadcast::channel(16);
+        sender.send([0; SERIALIZED_INPUT_RECORD_0_LENGTH]).unwrap();
+        let start = Instant::now();
+        let (_, received) = tokio::join!(wait_for_broadcast_drained(&sender), async {
+            return receiver.recv().await;
+        });
+        assert!(received.is_ok());
+        assert!(start.elapsed() < BROADCAST_DRAIN_TIMEOUT);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_broadcast_drained_gives_up_on_stuck_client() {
+        let (sender, _receiver) = broadcast::channel(16);
+        sender.send([0; SERIALIZED_INPUT_RECORD_0_LENGTH]).unwrap();
+        let start = Instant::now();
+        wait_for_broadcast_drained(&sender).await;
+        assert!(start.elapsed() >= BROADCAST_DRAIN_TIMEOUT);
+    }
+
+    #[test]
+    fn test_get_client_hostname() {
+        let mut client_console_window_handles = client_windows(&["web1", "web2"]);
+        assert_eq!(
+            get_client_hostname(&client_console_window_handles, 2),
+            Some("web2".to_owned())
+        );
+        // The hostname is found by process id, not by index.
+        let client_window = client_console_window_handles.remove(&0).unwrap();
+        client_console_window_handles.insert(5, client_window);
+        assert_eq!(
+            get_client_hostname(&client_console_window_handles, 1),
+            Some("web1".to_owned())
+        );
+        assert_eq!(
+            get_client_hostname(&client_console_window_handles, 42),
+            None
+        );
+    }
+
+    #[test]
+    fn test_is_client_enabled() {
+        let mut client_console_window_handles = client_windows(&["a", "b", "c"]);
+        client_console_window_handles.get_mut(&0).unwrap().state = PipeServerState::Disabled;
+        client_console_window_handles.get_mut(&1).unwrap().excluded = true;
+        let client_console_window_handles = Arc::new(Mutex::new(client_console_window_handles));
+        assert!(!is_client_enabled(&client_console_window_handles, 1));
+        assert!(!is_client_enabled(&client_console_window_handles, 2));
+        assert!(is_client_enabled(&client_console_window_handles, 3));
+        // Unknown clients, e.g. not yet registered ones, receive the input.
+        assert!(is_client_enabled(&client_console_window_handles, 42));
+    }
+
+    #[test]
+    fn test_border_highlight_restores_original_color_when_moving_on() {
+        let mut border_highlight = BorderHighlight::default();
+        assert_eq!(
+            border_highlight.highlight(HWND(1), COLORREF(0x000000FF)),
+            None
+        );
+        assert_eq!(
+            border_highlight.highlight(HWND(2), COLORREF(0x0000FF00)),
+            Some((HWND(1), COLORREF(0x000000FF)))
+        );
+        assert_eq!(
+            border_highlight.clear(),
+            Some((HWND(2), COLORREF(0x0000FF00)))
+        );
+        assert_eq!(border_highlight.clear(), None);
+    }
+
+    #[test]
+    fn test_border_highlight_keeps_original_color_when_highlighting_again() {
+        let mut border_highlight = BorderHighlight::default();
+        border_highlight.highlight(HWND(1), COLORREF(0x000000FF));
+        // Highlighting the same window again reads the highlight color as its current color.
+        assert_eq!(
+            border_highlight.highlight(HWND(1), SELECTED_CLIENT_BORDER_COLOR),
+            None
+        );
+        assert_eq!(
+            border_highlight.clear(),
+            Some((HWND(1), COLORREF(0x000000FF)))
+        );
+    }
+
+    #[test]
+    fn test_border_highlight_restores_each_window_its_own_color() {
+        let mut border_highlight = BorderHighlight::default();
+        border_highlight.highlight(HWND(1), COLORREF(0x000000FF));
+        assert!(border_highlight.is_highlighted(HWND(1)));
+        assert!(!border_highlight.is_highlighted(HWND(2)));
+        assert_eq!(
+            border_highlight.highlight(HWND(2), COLORREF(0x0000FF00)),
+            Some((HWND(1), COLORREF(0x000000FF)))
+        );
+        // Moving back reads the restored color of the first window again.
+        assert_eq!(
+            border_highlight.highlight(HWND(1), COLORREF(0x000000FF)),
+            Some((HWND(2), COLORREF(0x0000FF00)))
+        );
+        assert!(border_highlight.is_highlighted(HWND(1)));
+        assert_eq!(
+            border_highlight.clear(),
+            Some((HWND(1), COLORREF(0x000000FF)))
+        );
+        assert!(!border_highlight.is_highlighted(HWND(1)));
+    }
+
+    #[test]
+    fn test_get_client_selection_wraps_around() {
+        assert_eq!(get_client_selection(0, 3, VK_RIGHT.0), Some(1));
+        assert_eq!(get_client_selection(2, 3, VK_DOWN.0), Some(0));
+        assert_eq!(get_client_selection(0, 3, VK_LEFT.0), Some(2));
+        assert_eq!(get_client_selection(1, 3, VK_UP.0), Some(0));
+        assert_eq!(get_client_selection(0, 0, VK_RIGHT.0), None);
+        assert_eq!(get_client_selection(0, 3, VK_RETURN.0), None);
+    }
+
+    #[test]
+    fn test_get_client_selection_jumps_to_digit() {
+        assert_eq!(get_client_selection(0, 3, VK_1.0), Some(1));
+        assert_eq!(get_client_selection(2, 3, VK_0.0), Some(0));
+        assert_eq!(get_client_selection(0, 10, VK_9.0), Some(9));
+    }
+
+    #[test]
+    fn test_get_client_selection_ignores_out_of_range_digits() {
+        assert_eq!(get_client_selection(1, 3, VK_3.0), Some(1));
+        assert_eq!(get_client_selection(2, 3, VK_9.0), Some(2));
+    }
+
+    #[test]
+    fn test_swap_client_window_order() {
+        let mut client_console_window_handles = client_windows(&["web1", "web2", "web3"]);
+        assert_eq!(
+            swap_client_window_order(&mut client_console_window_handles, HWND(1), HWND(3)),
+            Some(("web1".to_owned(), "web3".to_owned()))
+        );
+        assert_eq!(
+            client_console_window_handles
+                .values()
+                .map(|client_window| return client_window.hostname.as_str())
+                .collect::<Vec<_>>(),
+            ["web3", "web2", "web1"]
+        );
+    }
+
+    #[test]
+    fn test_swap_client_window_order_ignores_same_or_unknown_window() {
+        let mut client_console_window_handles = client_windows(&["web1", "web2"]);
+        assert_eq!(
+            swap_client_window_order(&mut client_console_window_handles, HWND(1), HWND(1)),
+            None
+        );
+        assert_eq!(
+            swap_client_window_order(&mut client_console_window_handles, HWND(1), HWND(42)),
+            None
+        );
+        assert_eq!(client_console_window_handles[&0].hostname, "web1");
+        assert_eq!(client_console_window_handles[&1].hostname, "web2");
+    }
+
+    #[test]
+    fn test_split_into_chunks() {
+        assert_eq!(split_into_chunks("abcdefg\r", 3), vec!["abc", "def", "g\r"]);
+        assert_eq!(split_into_chunks("abc", 3), vec!["abc"]);
+        assert_eq!(split_into_chunks("abcdefg\r", 0), vec!["abcdefg\r"]);
+    }
+
+    #[test]
+    fn test_split_into_chunks_keeps_characters_intact() {
+        // Characters outside the BMP consist of two UTF-16 code units.
+        assert_eq!(split_into_chunks("a😀b", 2), vec!["a😀", "b"]);
+        assert_eq!(split_into_chunks("äöü", 1), vec!["ä", "ö", "ü"]);
+    }
+
+    /// Runs the [`named_pipe_server_routine`] for the client with process id `1` on a pipe
+    /// unique to the test and returns a stub client connected to it.
+    async fn start_named_pipe_server_routine(
+        test_name: &str,
+        mut receiver: Receiver<[u8; SERIALIZED_INPUT_RECORD_0_LENGTH]>,
+        client_console_window_handles: BTreeMap<usize, ClientWindow>,
+        monitor_pane: Option<Arc<Mutex<MonitorPane>>>,
+    ) -> (NamedPipeClient, JoinHandle<()>) {
+        let pipe_name = format!("{}-{}-{}", PIPE_NAME, std::process::id(), test_name);
+        let server = ServerOptions::new()
+            .access_outbound(true)
+            .pipe_mode(PipeMode::Message)
+            .create(&pipe_name)
+            .unwrap();
+        let client = ClientOptions::new().open(&pipe_name).unwrap();
+        server.connect().await.unwrap();
+        let routine = tokio::spawn(async move {
+            named_pipe_server_routine(
+                &server,
+                &mut receiver,
+                Arc::new(Mutex::new(client_console_window_handles)),
+                1,
+                Duration::from_secs(60),
+                None,
+                monitor_pane,
+            )
+            .await;
+        });
+        return (client, routine);
+    }
+
+    /// Reads `count` packets from the stub client.
+    async fn read_packets(client: &NamedPipeClient, count: usize) -> Vec<u8> {
+        let mut received: Vec<u8> = Vec::new();
+        let mut buf = [0; SERIALIZED_INPUT_RECORD_0_LENGTH * 10];
+        while received.len() < count * SERIALIZED_INPUT_RECORD_0_LENGTH {
+            client.readable().await.unwrap();
+            match client.try_read(&mut buf) {
+                Ok(n) => received.extend(&buf[..n]),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(err) => panic!("Failed to read from the named pipe: {}", err),
+            }
         }
+        return received;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_disabled_client_only_receives_shutdown() {
+        let mut client_console_window_handles = client_windows(&["web1"]);
+        client_console_window_handles.get_mut(&0).unwrap().state = PipeServerState::Disabled;
+        let (sender, receiver) = broadcast::channel(16);
+        let (client, routine) = start_named_pipe_server_routine(
+            "disabled-shutdown",
+            receiver,
+            client_console_window_handles,
+            None,
+        )
+        .await;
+
+        sender.send(BREAK_PACKET).unwrap();
+        sender.send(SHUTDOWN_PACKET).unwrap();
+
+        let received = tokio::time::timeout(Duration::from_secs(10), read_packets(&client, 2))
+            .await
+            .expect("Timed out waiting for the shutdown");
+        assert_eq!(received, [DISABLED_PACKET, SHUTDOWN_PACKET].concat());
+        routine.abort();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_client_output_is_passed_to_monitor_pane() {
+        let monitor_pane = Arc::new(Mutex::new(MonitorPane::new(100)));
+        let (_sender, receiver) = broadcast::channel(16);
+        let (client, routine) = start_named_pipe_server_routine(
+            "monitor-pane",
+            receiver,
+            client_windows(&["web1"]),
+            Some(Arc::clone(&monitor_pane)),
+        )
+        .await;
+
+        // The packet is split across two writes, as it may be if the pipe is full.
+        let packet = serialize_output_packet(b"up 3 days\r\n");
+        client.try_write(&[HEARTBEAT_TAG]).unwrap();
+        client.try_write(&packet[..4]).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        client.try_write(&packet[4..]).unwrap();
+
+        let lines = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                let lines = monitor_pane.lock().unwrap().take_lines();
+                if !lines.is_empty() {
+                    return lines;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Timed out waiting for the output");
+        assert_eq!(lines, vec!["[web1] up 3 days"]);
+        routine.abort();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_late_connecting_client_is_not_removed() {
+        let pipe_name = format!("{}-{}-late-connect", PIPE_NAME, std::process::id());
+        let server = ServerOptions::new()
+            .access_outbound(true)
+            .pipe_mode(PipeMode::Message)
+            .create(&pipe_name)
+            .unwrap();
+        // The stub client runs in this process, its window was created long ago.
+        let mut client_window = client_window("web1", std::process::id());
+        client_window.last_heartbeat = Instant::now() - Duration::from_secs(10);
+        let client_console_window_handles =
+            Arc::new(Mutex::new(BTreeMap::from([(0, client_window)])));
+        let (sender, receiver) = broadcast::channel(16);
+        let routine = tokio::spawn(supervise_named_pipe_server_routine(
+            server,
+            receiver,
+            sender.clone(),
+            Arc::clone(&client_console_window_handles),
+            Duration::from_secs(60),
+            Some(Duration::from_secs(5)),
+            None,
+        ));
+
+        let _client = ClientOptions::new().open(&pipe_name).unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert!(!routine.is_finished());
+        assert_eq!(client_console_window_handles.lock().unwrap().len(), 1);
+        routine.abort();
+    }
+
+    #[tokio::test]
+    async fn test_restart_on_panic_gives_up_after_max_restarts() {
+        let spawned = Arc::new(Mutex::new(0));
+        let _spawned = Arc::clone(&spawned);
+        let restarts = restart_on_panic(0, || {
+            *_spawned.lock().unwrap() += 1;
+            return tokio::spawn(async {
+                panic!("routine panicked");
+            });
+        })
+        .await;
+        assert_eq!(restarts, MAX_NAMED_PIPE_SERVER_ROUTINE_RESTARTS);
+        assert_eq!(
+            *spawned.lock().unwrap(),
+            MAX_NAMED_PIPE_SERVER_ROUTINE_RESTARTS + 1
+        );
     }
-}
 
-fn determine_client_spatial_attributes(
-    index: i32,
-    number_of_consoles: i32,
-    workspace_area: &workspace::WorkspaceArea,
-    aspect_ratio_adjustment: f64,
-) -> (i32, i32, i32, i32) {
-    let aspect_ratio = workspace_area.width as f64 / workspace_area.height as f64;
+    #[tokio::test]
+    async fn test_restart_on_panic_stops_once_routine_returns() {
+        let mut spawned = 0;
+        let restarts = restart_on_panic(0, || {
+            spawned += 1;
+            let panics = spawned == 1;
+            return tokio::spawn(async move {
+                if panics {
+                    panic!("routine panicked");
+                }
+            });
+        })
+        .await;
+        assert_eq!(restarts, 1);
+        assert_eq!(spawned, 2);
+    }
 
-    let grid_columns = max(
-        ((number_of_consoles as f64).sqrt() * (aspect_ratio + aspect_ratio_adjustment)) as i32,
-        1,
-    );
-    let grid_rows = max(
-        (number_of_consoles as f64 / grid_columns as f64).ceil() as i32,
-        1,
-    );
+    const DAEMON_CONSOLE_WINDOW: HWND = HWND(1);
+    const CLIENT_WINDOW: HWND = HWND(2);
+    const OTHER_WINDOW: HWND = HWND(3);
 
-    let grid_column_index = index % grid_columns;
-    let grid_row_index = index / grid_columns;
+    #[test]
+    fn test_get_window_to_refocus_keeps_unchanged_focus() {
+        assert_eq!(
+            get_window_to_refocus(CLIENT_WINDOW, CLIENT_WINDOW, true, DAEMON_CONSOLE_WINDOW),
+            None
+        );
+    }
 
-    let is_last_row = grid_row_index == grid_rows - 1;
-    let last_row_console_count = number_of_consoles % grid_columns;
+    #[test]
+    fn test_get_window_to_refocus_restores_previous_window() {
+        assert_eq!(
+            get_window_to_refocus(CLIENT_WINDOW, OTHER_WINDOW, true, DAEMON_CONSOLE_WINDOW),
+            Some(CLIENT_WINDOW)
+        );
+    }
 
-    let console_width = if is_last_row && last_row_console_count != 0 {
-        workspace_area.width / last_row_console_count
-    } else {
-        workspace_area.width / grid_columns
-    };
+    #[test]
+    fn test_get_window_to_refocus_falls_back_to_daemon_console() {
+        assert_eq!(
+            get_window_to_refocus(CLIENT_WINDOW, OTHER_WINDOW, false, DAEMON_CONSOLE_WINDOW),
+            Some(DAEMON_CONSOLE_WINDOW)
+        );
+    }
 
-    let console_height = workspace_area.height / grid_rows;
+    /// Returns client windows for the given hostnames whose last heartbeat was 10 seconds ago.
+    fn silent_client_windows(hostnames: &[&str]) -> Arc<Mutex<BTreeMap<usize, ClientWindow>>> {
+        let mut client_console_window_handles = client_windows(hostnames);
+        for client_window in client_console_window_handles.values_mut() {
+            client_window.last_heartbeat = Instant::now() - Duration::from_secs(10);
+        }
+        return Arc::new(Mutex::new(client_console_window_handles));
+    }
 
-    let x = grid_column_index * console_width;
-    let y = grid_row_index * console_height;
+    #[test]
+    fn test_record_heartbeat_only_refreshes_matching_client() {
+        let client_console_window_handles = silent_client_windows(&["web1", "web2"]);
+        record_heartbeat(&client_console_window_handles, 2);
+        let client_console_window_handles = client_console_window_handles.lock().unwrap();
+        assert!(
+            client_console_window_handles[&0].last_heartbeat.elapsed() >= Duration::from_secs(10)
+        );
+        assert!(
+            client_console_window_handles[&1].last_heartbeat.elapsed() < Duration::from_secs(10)
+        );
+    }
 
-    return get_console_rect(x, y, console_width, console_height, workspace_area);
-}
+    #[test]
+    fn test_remove_unresponsive_client_removes_silent_client() {
+        let client_console_window_handles = silent_client_windows(&["web1", "web2"]);
+        assert!(remove_unresponsive_client(
+            &client_console_window_handles,
+            1,
+            Duration::from_secs(5)
+        ));
+        let client_console_window_handles = client_console_window_handles.lock().unwrap();
+        assert_eq!(
+            client_console_window_handles.keys().collect::<Vec<_>>(),
+            [&1]
+        );
+    }
 
-fn get_console_rect(
-    x: i32,
-    y: i32,
-    width: i32,
-    height: i32,
-    workspace_area: &workspace::WorkspaceArea,
-) -> (i32, i32, i32, i32) {
-    return (
-        workspace_area.x + x,
-        workspace_area.y + y,
-        width + workspace_area.x_fixed_frame + workspace_area.x_size_frame * 2,
-        height + workspace_area.y_size_frame * 2,
-    );
-}
+    #[test]
+    fn test_remove_unresponsive_client_keeps_responsive_client() {
+        let client_console_window_handles = silent_client_windows(&["web1"]);
+        record_heartbeat(&client_console_window_handles, 1);
+        assert!(!remove_unresponsive_client(
+            &client_console_window_handles,
+            1,
+            Duration::from_secs(5)
+        ));
+        assert_eq!(client_console_window_handles.lock().unwrap().len(), 1);
+    }
 
-fn launch_client_console(
-    host: &str,
-    username: Option<String>,
-    debug: bool,
-    index: usize,
-    workspace_area: &workspace::WorkspaceArea,
-    number_of_consoles: usize,
-    aspect_ratio_adjustment: f64,
-) -> HWND {
-    // The first argument must be `--` to ensure all following arguments are treated
-    // as positional arguments and not as options if they start with `-`.
-    let mut client_args: Vec<&str> = Vec::new();
-    if debug {
-        client_args.push("-d");
+    #[test]
+    fn test_remove_unresponsive_client_ignores_unknown_process() {
+        let client_console_window_handles = silent_client_windows(&["web1"]);
+        assert!(!remove_unresponsive_client(
+            &client_console_window_handles,
+            42,
+            Duration::from_secs(5)
+        ));
+        assert_eq!(client_console_window_handles.lock().unwrap().len(), 1);
     }
-    let default_username = DEFAULT_SSH_USERNAME_KEY.to_string();
-    client_args.extend(vec![
-        "client",
-        "--",
-        host,
-        username.as_ref().unwrap_or(&default_username),
-    ]);
-    let process_id = spawn_console_process(&format!("{PKG_NAME}.exe"), client_args).dwProcessId;
-    let mut client_window_handle: Option<HWND> = None;
-    loop {
-        enumerate_windows(|handle| {
-            let mut window_process_id: u32 = 0;
-            unsafe { GetWindowThreadProcessId(handle, Some(&mut window_process_id)) };
-            if process_id == window_process_id {
-                client_window_handle = Some(handle);
-            }
-            return true;
-        });
-        if client_window_handle.is_some() {
-            break;
-        }
+
+    #[test]
+    fn test_push_client_output_prefixes_hostname_of_client() {
+        let monitor_pane = Mutex::new(MonitorPane::new(100));
+        let client_console_window_handles = Arc::new(Mutex::new(client_windows(&["web1", "web2"])));
+        push_client_output(
+            &monitor_pane,
+            &client_console_window_handles,
+            2,
+            vec![
+                ClientPacket::Heartbeat,
+                ClientPacket::Output(b"load average: 0.01\n".to_vec()),
+            ],
+        );
+
+        assert_eq!(
+            monitor_pane.lock().unwrap().take_lines(),
+            vec!["[web2] load average: 0.01"]
+        );
     }
-    arrage_client_window(
-        &client_window_handle.unwrap(),
-        workspace_area,
-        index,
-        number_of_consoles,
-        aspect_ratio_adjustment,
-    );
-    return client_window_handle.unwrap();
-}
 
-async fn named_pipe_server_routine(
-    server: NamedPipeServer,
-    receiver: &mut Receiver<[u8; SERIALIZED_INPUT_RECORD_0_LENGTH]>,
-) {
-    // wait for a client to connect
-    server.connect().await.unwrap_or_else(|err| {
-        error!("{}", err);
-        panic!("Timeded out waiting for clients to connect to named pipe server",)
-    });
-    loop {
-        let ser_input_record = match receiver.try_recv() {
-            Ok(val) => val,
-            Err(TryRecvError::Empty) => {
-                tokio::time::sleep(Duration::from_millis(5)).await;
-                // Try sending dummy data to detect early if the pipe is closed because the client exited
-                match server.try_write(&[u8::MAX; 18]) {
-                    Ok(_) => continue,
-                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
-                    Err(_) => {
-                        debug!(
-                            "Named pipe server ({:?}) is closed, stopping named pipe server routine",
-                            server
-                        );
-                        return;
-                    }
-                }
-            }
-            Err(err) => {
-                error!("{}", err);
-                panic!("Failed to receive data from the Receiver");
-            }
+    #[test]
+    fn test_zoomed_client_fills_workspace_area() {
+        let mut workspace_area = WorkspaceArea::new_logical(100, 50, 1600, 800);
+        workspace_area.x_fixed_frame = 3;
+        workspace_area.x_size_frame = 4;
+        workspace_area.y_size_frame = 5;
+        // The minimum console size doesn't apply to the zoomed client.
+        let config = DaemonConfig {
+            min_console_width: 2000,
+            min_console_height: 1000,
+            ..Default::default()
         };
-        loop {
-            server.writable().await.unwrap_or_else(|err| {
-                error!("{}", err);
-                panic!("Timed out waiting for named pipe server to become writable",)
-            });
-            match server.try_write(&ser_input_record) {
-                Ok(SERIALIZED_INPUT_RECORD_0_LENGTH) => {
-                    debug!("Successfully written all data");
-                    break;
-                }
-                Ok(n) => {
-                    // The data was only written partially, try again
-                    warn!(
-                        "Partially written data, expected {} but only wrote {}",
-                        SERIALIZED_INPUT_RECORD_0_LENGTH, n
-                    );
-                    continue;
-                }
-                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
-                    // Try again
-                    debug!("Writing to named pipe server would have blocked");
-                    continue;
-                }
-                Err(_) => {
-                    // Can happen if the pipe is closed because the
-                    // client exited
-                    debug!(
-                        "Named pipe server ({:?}) is closed, stopping named pipe server routine",
-                        server
-                    );
-                    return;
-                }
-            }
+        assert_eq!(
+            determine_cell_spatial_attributes(
+                0,
+                &get_zoomed_grid_dimensions(&workspace_area, &config),
+                &workspace_area
+            ),
+            (100, 50, 1600 + 3 + 4 * 2, 800 + 5 * 2)
+        );
+    }
+
+    #[test]
+    fn test_get_next_zoomed_client_wraps_around() {
+        assert_eq!(get_next_zoomed_client(0, 3, true), 1);
+        assert_eq!(get_next_zoomed_client(2, 3, true), 0);
+        assert_eq!(get_next_zoomed_client(1, 3, false), 0);
+        assert_eq!(get_next_zoomed_client(0, 3, false), 2);
+        assert_eq!(get_next_zoomed_client(0, 1, false), 0);
+    }
+
+    #[tokio::test]
+    async fn test_for_each_staggered_waits_between_calls() {
+        let stagger = Duration::from_millis(20);
+        let mut calls: Vec<(usize, &str, Instant)> = Vec::new();
+        for_each_staggered(vec!["web1", "web2", "web3"], stagger, |index, host| {
+            calls.push((index, host, Instant::now()));
+        })
+        .await;
+        assert_eq!(
+            calls
+                .iter()
+                .map(|(index, host, _)| return (*index, *host))
+                .collect::<Vec<_>>(),
+            [(0, "web1"), (1, "web2"), (2, "web3")]
+        );
+        for window in calls.windows(2) {
+            assert!(window[1].2 - window[0].2 >= stagger);
         }
     }
-}
 
-/// Launches a client console for each given host and
-/// waits for the client windows to exist before
-/// returning their handles.
-async fn launch_clients(
-    hosts: Vec<String>,
-    username: &Option<String>,
-    debug: bool,
-    workspace_area: &workspace::WorkspaceArea,
-    aspect_ratio_adjustment: f64,
-) -> BTreeMap<usize, ClientWindow> {
-    let result = Arc::new(Mutex::new(BTreeMap::new()));
-    let len_hosts = hosts.len();
-    let host_iter = IntoIterator::into_iter(hosts);
-    let mut handles = vec![];
-    for (index, host) in host_iter.enumerate() {
-        let _username = username.clone();
-        let _workspace = *workspace_area;
-        let result_arc = Arc::clone(&result);
-        let future = tokio::spawn(async move {
-            let handle = launch_client_console(
-                &host,
-                _username,
-                debug,
-                index,
-                &_workspace,
-                len_hosts,
-                aspect_ratio_adjustment,
-            );
-            result_arc.lock().unwrap().insert(
-                index,
-                ClientWindow {
-                    hostname: host.to_string(),
-                    hwnd: handle,
-                },
-            );
-        });
-        handles.push(future);
+    #[tokio::test]
+    async fn test_for_each_staggered_without_stagger_calls_immediately() {
+        let start = Instant::now();
+        let mut indices: Vec<usize> = Vec::new();
+        for_each_staggered(vec!["web1", "web2", "web3"], Duration::ZERO, |index, _| {
+            indices.push(index);
+        })
+        .await;
+        assert_eq!(indices, [0, 1, 2]);
+        assert!(start.elapsed() < Duration::from_secs(1));
     }
-    for handle in handles {
-        handle.await.unwrap();
+
+    /// Returns the (`columns`, `rows`, `cell_width`, `cell_height`) of the grid
+    /// `number_of_consoles` are arranged in on a 1600x800 workspace area.
+    fn get_grid_dimensions(
+        number_of_consoles: i32,
+        grid: Option<(usize, usize)>,
+    ) -> (i32, i32, i32, i32) {
+        let workspace_area = WorkspaceArea::new_logical(0, 0, 1600, 800);
+        let config = DaemonConfig::default();
+        let grid_dimensions = determine_grid_dimensions(
+            number_of_consoles,
+            &workspace_area,
+            config.aspect_ratio_adjustement,
+            grid,
+            config.fill_direction,
+            (config.min_console_width, config.min_console_height),
+            None,
+        );
+        return (
+            grid_dimensions.columns,
+            grid_dimensions.rows,
+            grid_dimensions.cell_width,
+            grid_dimensions.cell_height,
+        );
     }
-    return result.lock().unwrap().clone();
-}
 
-fn enumerate_windows<F>(mut callback: F)
-where
-    F: FnMut(HWND) -> bool,
-{
-    let mut trait_obj: &mut dyn FnMut(HWND) -> bool = &mut callback;
-    let closure_pointer_pointer: *mut c_void = unsafe { mem::transmute(&mut trait_obj) };
+    #[test]
+    fn test_determine_grid_dimensions() {
+        assert_eq!(get_grid_dimensions(1, None), (1, 1, 1600, 800));
+        assert_eq!(get_grid_dimensions(4, None), (2, 2, 800, 400));
+        assert_eq!(get_grid_dimensions(5, None), (2, 3, 800, 266));
+        assert_eq!(get_grid_dimensions(10, None), (3, 4, 533, 200));
+    }
 
-    let lparam = LPARAM(closure_pointer_pointer as isize);
-    unsafe { EnumWindows(Some(enumerate_callback), lparam).unwrap() };
-}
+    /// Returns the (`columns`, `rows`, `cell_width`, `cell_height`) of the grid
+    /// `number_of_consoles` are arranged in on a 1600x800 workspace area with
+    /// a minimum console size of 400x150.
+    fn get_grid_dimensions_with_min_console_size(
+        number_of_consoles: i32,
+        fill_direction: FillDirection,
+    ) -> (i32, i32, i32, i32) {
+        let workspace_area = WorkspaceArea::new_logical(0, 0, 1600, 800);
+        let grid_dimensions = determine_grid_dimensions(
+            number_of_consoles,
+            &workspace_area,
+            DaemonConfig::default().aspect_ratio_adjustement,
+            None,
+            fill_direction,
+            (400, 150),
+            None,
+        );
+        return (
+            grid_dimensions.columns,
+            grid_dimensions.rows,
+            grid_dimensions.cell_width,
+            grid_dimensions.cell_height,
+        );
+    }
 
-unsafe extern "system" fn enumerate_callback(hwnd: HWND, lparam: LPARAM) -> BOOL {
-    let closure: &mut &mut dyn FnMut(HWND) -> bool = &mut *(lparam.0 as *mut c_void
-        as *mut &mut dyn std::ops::FnMut(windows::Win32::Foundation::HWND) -> bool);
-    if closure(hwnd) {
-        return TRUE;
-    } else {
-        return FALSE;
+    #[test]
+    fn test_determine_grid_dimensions_respects_min_console_size() {
+        // Without a minimum the 36 consoles would be 266x133 pixels.
+        assert_eq!(get_grid_dimensions(36, None), (6, 6, 266, 133));
+        // Filling row-major reduces the columns, the additional rows extend beyond the
+        // workspace area.
+        assert_eq!(
+            get_grid_dimensions_with_min_console_size(36, FillDirection::RowMajor),
+            (4, 9, 400, 150)
+        );
+        // Filling column-major reduces the rows, the additional columns extend beyond the
+        // workspace area.
+        assert_eq!(
+            get_grid_dimensions_with_min_console_size(36, FillDirection::ColumnMajor),
+            (8, 5, 400, 160)
+        );
     }
-}
 
-fn disable_processed_input_mode() {
-    let handle = get_console_input_buffer();
-    let mut mode = CONSOLE_MODE(0u32);
-    unsafe {
-        GetConsoleMode(handle, &mut mode).unwrap();
+    #[test]
+    fn test_determine_grid_dimensions_keeps_large_enough_cells() {
+        assert_eq!(
+            get_grid_dimensions_with_min_console_size(4, FillDirection::RowMajor),
+            (2, 2, 800, 400)
+        );
     }
-    unsafe {
-        SetConsoleMode(handle, CONSOLE_MODE(mode.0 ^ ENABLE_PROCESSED_INPUT.0)).unwrap();
+
+    #[test]
+    fn test_get_client_window_rects_overflow_workspace_area() {
+        let workspace_area = WorkspaceArea::new_logical(0, 0, 1600, 800);
+        let config = DaemonConfig {
+            min_console_width: 400,
+            min_console_height: 150,
+            ..Default::default()
+        };
+        let rects = get_client_window_rects(36, &[workspace_area], &config, None, false);
+        assert_eq!(rects[0], (0, 0, 400, 150));
+        assert_eq!(rects[35], (1200, 1200, 400, 150));
     }
-}
 
-pub async fn main(
-    hosts: Vec<String>,
-    username: Option<String>,
-    config: &DaemonConfig,
-    debug: bool,
-) {
-    let daemon: Daemon = Daemon {
-        hosts,
-        username,
-        config,
-        control_mode_state: ControlModeState::Inactive,
-        debug,
-    };
-    daemon.launch().await;
-    debug!("Actually exiting");
+    #[test]
+    fn test_determine_grid_dimensions_adds_rows_to_full_fixed_grid() {
+        assert_eq!(get_grid_dimensions(3, Some((2, 2))), (2, 2, 800, 400));
+        assert_eq!(get_grid_dimensions(5, Some((2, 2))), (2, 3, 800, 266));
+    }
+
+    /// Returns a daemon without hosts using the given `config`.
+    fn daemon(config: &DaemonConfig) -> Daemon<'_> {
+        return Daemon {
+            hosts: Vec::new(),
+            username: None,
+            clusters: Vec::new(),
+            config,
+            control_mode_state: ControlModeState::Inactive,
+            pipe_name: format!("{}-{}", PIPE_NAME, std::process::id()),
+            options: LaunchOptions::default(),
+            active_sets: Vec::new(),
+            active_set_name: None,
+            last_control_mode_activity: Arc::new(Mutex::new(Instant::now())),
+            last_broadcast_activity: Arc::new(Mutex::new(Instant::now())),
+            locked: Arc::new(AtomicBool::new(false)),
+            title: Arc::new(Mutex::new(String::new())),
+            last_key_press: Arc::new(Mutex::new(Instant::now())),
+            broadcast_to_others: false,
+            frozen: false,
+            last_focused_client: Arc::new(Mutex::new(None)),
+            recorded_macro: None,
+            zoomed_client: None,
+            selected_client: None,
+            selection_highlight: BorderHighlight::default(),
+            client_selection: ClientSelection::EnableDisable,
+            monitor_pane: None,
+        };
+    }
+
+    #[tokio::test]
+    async fn test_frozen_daemon_does_not_broadcast_input() {
+        let config = DaemonConfig::default();
+        let mut daemon = daemon(&config);
+        let (sender, mut receiver) = broadcast::channel(16);
+        let mut client_console_window_handles = Arc::new(Mutex::new(client_windows(&["web1"])));
+        let workspace_area = WorkspaceArea::new_logical(0, 0, 1600, 800);
+        let mut servers = Arc::new(Mutex::new(Vec::new()));
+        let input_record = INPUT_RECORD_0 {
+            KeyEvent: key_event(true, VK_A, 0),
+        };
+
+        daemon.frozen = true;
+        daemon
+            .handle_input_record(
+                &sender,
+                input_record,
+                &mut client_console_window_handles,
+                &workspace_area,
+                &mut servers,
+            )
+            .await;
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Empty));
+
+        // Unfreezing resumes broadcasting.
+        daemon.frozen = false;
+        daemon
+            .handle_input_record(
+                &sender,
+                input_record,
+                &mut client_console_window_handles,
+                &workspace_area,
+                &mut servers,
+            )
+            .await;
+        assert!(receiver.try_recv().is_ok());
+    }
 }
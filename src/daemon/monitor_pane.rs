@@ -0,0 +1,224 @@
+use std::collections::{HashMap, VecDeque};
+use std::mem;
+
+/// Maximum number of bytes of a line without line break, longer lines are split.
+const MAX_LINE_LENGTH: usize = 4096;
+
+/// Collects the output of all clients line by line to print it in the daemon console,
+/// each line prefixed with the hostname of the client it stems from.
+pub struct MonitorPane {
+    /// Maximum number of lines per client kept while paused.
+    max_lines_per_host: usize,
+    /// Complete lines, along with the hostname of their client, waiting to be printed.
+    lines: VecDeque<(String, String)>,
+    /// Number of lines waiting to be printed per client.
+    line_counts: HashMap<String, usize>,
+    /// Output per client received after its last line break.
+    partial_lines: HashMap<String, Vec<u8>>,
+    /// Whether printing is paused, e.g. while in control mode.
+    paused: bool,
+}
+
+impl MonitorPane {
+    pub fn new(max_lines_per_host: usize) -> Self {
+        return MonitorPane {
+            max_lines_per_host,
+            lines: VecDeque::new(),
+            line_counts: HashMap::new(),
+            partial_lines: HashMap::new(),
+            paused: false,
+        };
+    }
+
+    /// Splits the `output` of the client connected to `host` into lines and queues them.
+    ///
+    /// Terminal escape sequences are stripped and blank lines are skipped, if the client
+    /// exceeds `max_lines_per_host` lines, its oldest line is dropped.
+    pub fn push_output(&mut self, host: &str, output: &[u8]) {
+        let partial_line = self.partial_lines.entry(host.to_owned()).or_default();
+        partial_line.extend(output);
+        let mut complete_lines: Vec<Vec<u8>> = Vec::new();
+        while let Some(position) = partial_line.iter().position(|byte| return *byte == b'\n') {
+            complete_lines.push(partial_line.drain(..=position).collect());
+        }
+        if partial_line.len() >= MAX_LINE_LENGTH {
+            complete_lines.push(mem::take(partial_line));
+        }
+        for line in complete_lines {
+            if let Some(line) = get_printable_line(&line) {
+                self.push_line(host, line);
+            }
+        }
+    }
+
+    fn push_line(&mut self, host: &str, line: String) {
+        let line_count = self.line_counts.entry(host.to_owned()).or_default();
+        *line_count += 1;
+        if *line_count > self.max_lines_per_host {
+            *line_count -= 1;
+            if let Some(index) = self
+                .lines
+                .iter()
+                .position(|(line_host, _)| return line_host == host)
+            {
+                self.lines.remove(index);
+            }
+            if self.max_lines_per_host == 0 {
+                return;
+            }
+        }
+        self.lines.push_back((host.to_owned(), line));
+    }
+
+    /// Returns the queued lines formatted as `[host] line` and removes them,
+    /// nothing is returned while paused.
+    pub fn take_lines(&mut self) -> Vec<String> {
+        if self.paused {
+            return Vec::new();
+        }
+        self.line_counts.clear();
+        return self
+            .lines
+            .drain(..)
+            .map(|(host, line)| return format!("[{}] {}", host, line))
+            .collect();
+    }
+
+    /// Pauses printing, the lines received in the meantime are kept.
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// Resumes printing, starting with the lines received while paused.
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+}
+
+/// Returns the text of a line of output as it would be displayed,
+/// or `None` if the line is blank.
+///
+/// Only the text after the last carriage return is kept, as it overwrites what was before.
+fn get_printable_line(line: &[u8]) -> Option<String> {
+    let line = strip_escape_sequences(&String::from_utf8_lossy(line));
+    return line
+        .rsplit('\r')
+        .find(|segment| return !segment.trim().is_empty())
+        .map(|segment| return segment.trim_end().to_owned());
+}
+
+/// Removes terminal escape sequences and control characters, except carriage returns
+/// and tabs, from `text`.
+fn strip_escape_sequences(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut characters = text.chars().peekable();
+    while let Some(character) = characters.next() {
+        match character {
+            '\x1b' => match characters.next() {
+                // Control sequence, terminated by a character in the range `@` to `~`.
+                Some('[') => {
+                    let _ = characters
+                        .by_ref()
+                        .find(|character| return ('@'..='~').contains(character));
+                }
+                // Operating system command, terminated by BEL or ST (`ESC \`).
+                Some(']') => {
+                    let terminator = characters
+                        .by_ref()
+                        .find(|character| return *character == '\x07' || *character == '\x1b');
+                    if terminator == Some('\x1b') {
+                        characters.next_if_eq(&'\\');
+                    }
+                }
+                // Character set designation, e.g. `ESC ( B`.
+                Some('(' | ')') => {
+                    characters.next();
+                }
+                // Any other sequence consists of a single character after the escape.
+                _ => {}
+            },
+            '\r' | '\t' => stripped.push(character),
+            character if character.is_control() => {}
+            character => stripped.push(character),
+        }
+    }
+    return stripped;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_escape_sequences() {
+        assert_eq!(
+            strip_escape_sequences("\x1b[1;32muser@web1\x1b[0m:\x1b[?2004h~$\tls\x07"),
+            "user@web1:~$\tls"
+        );
+        assert_eq!(
+            strip_escape_sequences("\x1b]0;user@web1: ~\x07\x1b(Bdone\x1b]2;title\x1b\\"),
+            "done"
+        );
+        assert_eq!(strip_escape_sequences("äöü 😀\x1b="), "äöü 😀");
+    }
+
+    #[test]
+    fn test_push_output_splits_lines() {
+        let mut monitor_pane = MonitorPane::new(100);
+        monitor_pane.push_output("web1", b"up 3 days\r\n\x1b[01;34mvar\x1b[0m\r\n\r\n$ ");
+        monitor_pane.push_output("web2", b"up 5 ");
+        monitor_pane.push_output("web2", b"days\r\n");
+
+        assert_eq!(
+            monitor_pane.take_lines(),
+            vec!["[web1] up 3 days", "[web1] var", "[web2] up 5 days"]
+        );
+        assert!(monitor_pane.take_lines().is_empty());
+        // The prompt is printed along with what was typed after it.
+        monitor_pane.push_output("web1", b"uptime\r\n");
+        assert_eq!(monitor_pane.take_lines(), vec!["[web1] $ uptime"]);
+    }
+
+    #[test]
+    fn test_push_output_keeps_text_after_last_carriage_return() {
+        let mut monitor_pane = MonitorPane::new(100);
+        monitor_pane.push_output("web1", b" 10%\r 50%\r100%\r\n");
+
+        assert_eq!(monitor_pane.take_lines(), vec!["[web1] 100%"]);
+    }
+
+    #[test]
+    fn test_push_output_splits_overlong_lines() {
+        let mut monitor_pane = MonitorPane::new(100);
+        monitor_pane.push_output("web1", &[b'a'; MAX_LINE_LENGTH + 1]);
+
+        let lines = monitor_pane.take_lines();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].len(), "[web1] ".len() + MAX_LINE_LENGTH + 1);
+    }
+
+    #[test]
+    fn test_paused_monitor_pane_keeps_latest_lines_per_host() {
+        let mut monitor_pane = MonitorPane::new(2);
+        monitor_pane.pause();
+        for line in ["1", "2", "3"] {
+            monitor_pane.push_output("web1", format!("{line}\n").as_bytes());
+        }
+        monitor_pane.push_output("web2", b"a\n");
+        assert!(monitor_pane.take_lines().is_empty());
+
+        monitor_pane.resume();
+        assert_eq!(
+            monitor_pane.take_lines(),
+            vec!["[web1] 2", "[web1] 3", "[web2] a"]
+        );
+    }
+
+    #[test]
+    fn test_monitor_pane_without_lines_per_host_drops_everything() {
+        let mut monitor_pane = MonitorPane::new(0);
+        monitor_pane.push_output("web1", b"1\n2\n");
+
+        assert!(monitor_pane.take_lines().is_empty());
+    }
+}
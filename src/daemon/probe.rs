@@ -0,0 +1,57 @@
+use std::time::{Duration, Instant};
+
+use log::debug;
+use tokio::net::TcpStream;
+use windows::Win32::Foundation::COLORREF;
+
+use crate::utils::parse_host_specification;
+
+/// Port probed to measure the latency of a host.
+const PROBE_PORT: u16 = 22;
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Upper latency bounds and their colors (`0x00BBGGRR`) from green to red.
+const LATENCY_COLORS: [(Duration, COLORREF); 3] = [
+    (Duration::from_millis(50), COLORREF(0x0000FF00)),
+    (Duration::from_millis(150), COLORREF(0x0000FFFF)),
+    (Duration::from_millis(300), COLORREF(0x0000A5FF)),
+];
+const HIGH_LATENCY_COLOR: COLORREF = COLORREF(0x000000FF);
+
+/// Measures how long it takes to establish a TCP connection to the SSH port of `host`.
+///
+/// A `<user>@` prefix is ignored, a `:<port>` suffix replaces the default SSH port.
+/// Returns `None` if no connection could be established.
+pub async fn probe_latency(host: &str) -> Option<Duration> {
+    let (_, host, port) = parse_host_specification(host);
+    let port = port
+        .and_then(|port| return port.parse().ok())
+        .unwrap_or(PROBE_PORT);
+    let start = Instant::now();
+    match tokio::time::timeout(PROBE_TIMEOUT, TcpStream::connect((host, port))).await {
+        Ok(Ok(_)) => return Some(start.elapsed()),
+        Ok(Err(err)) => {
+            debug!("Failed to probe host `{}`: {}", host, err);
+            return None;
+        }
+        Err(_) => {
+            debug!("Timed out probing host `{}`", host);
+            return None;
+        }
+    }
+}
+
+/// Returns the border color for the given latency,
+/// unreachable hosts are treated like hosts with a high latency.
+pub fn get_latency_color(latency: Option<Duration>) -> COLORREF {
+    let latency = match latency {
+        Some(latency) => latency,
+        None => return HIGH_LATENCY_COLOR,
+    };
+    for (upper_bound, color) in LATENCY_COLORS {
+        if latency < upper_bound {
+            return color;
+        }
+    }
+    return HIGH_LATENCY_COLOR;
+}
@@ -0,0 +1,301 @@
+use std::ffi::c_void;
+use std::fs::{create_dir_all, write};
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+
+use log::warn;
+use windows::Win32::Foundation::{COLORREF, HWND, RECT};
+use windows::Win32::Graphics::Gdi::{
+    BitBlt, CreateCompatibleBitmap, CreateCompatibleDC, CreateSolidBrush, DeleteDC, DeleteObject,
+    FillRect, GetDC, GetDIBits, ReleaseDC, SelectObject, BITMAPINFO, BITMAPINFOHEADER, BI_RGB,
+    DIB_RGB_COLORS, HDC, SRCCOPY,
+};
+use windows::Win32::Storage::Xps::{PrintWindow, PRINT_WINDOW_FLAGS};
+use windows::Win32::UI::WindowsAndMessaging::GetWindowRect;
+
+use super::workspace::WorkspaceArea;
+
+/// `PW_RENDERFULLCONTENT`, which is not exposed by the windows crate.
+const PW_RENDERFULLCONTENT: PRINT_WINDOW_FLAGS = PRINT_WINDOW_FLAGS(2);
+/// Color (`0x00BBGGRR`) of the placeholder drawn for windows that failed to be captured.
+const PLACEHOLDER_COLOR: COLORREF = COLORREF(0x00404040);
+/// Maximum number of bytes in a stored (uncompressed) deflate block.
+const MAX_STORED_BLOCK_LENGTH: usize = 0xFFFF;
+
+/// Captures the given windows into a single PNG the size of the `workspace_area`,
+/// keeping their arrangement, and saves it with a timestamped name in `directory`.
+///
+/// Windows that fail to be captured are replaced by a placeholder rectangle.
+/// Returns the path of the saved file.
+pub fn save_screenshot(
+    windows: &[HWND],
+    workspace_area: &WorkspaceArea,
+    directory: &str,
+) -> io::Result<PathBuf> {
+    let width = workspace_area.width;
+    let height = workspace_area.height;
+    if width <= 0 || height <= 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The workspace area is empty",
+        ));
+    }
+    let screen_dc = unsafe { GetDC(HWND(0)) };
+    let canvas_dc = unsafe { CreateCompatibleDC(screen_dc) };
+    let canvas = unsafe { CreateCompatibleBitmap(screen_dc, width, height) };
+    let previous_canvas_object = unsafe { SelectObject(canvas_dc, canvas) };
+
+    for window in windows {
+        let mut rect = RECT::default();
+        if unsafe { GetWindowRect(*window, &mut rect) }.is_err() {
+            warn!("Failed to determine position of window {:?}", window);
+            continue;
+        }
+        // Position relative to the workspace area.
+        rect.left -= workspace_area.x;
+        rect.right -= workspace_area.x;
+        rect.top -= workspace_area.y;
+        rect.bottom -= workspace_area.y;
+        if !capture_window(*window, &rect, screen_dc, canvas_dc) {
+            warn!("Failed to capture window {:?}", window);
+            let brush = unsafe { CreateSolidBrush(PLACEHOLDER_COLOR) };
+            unsafe { FillRect(canvas_dc, &rect, brush) };
+            let _ = unsafe { DeleteObject(brush) };
+        }
+    }
+
+    let mut bitmap_info = BITMAPINFO {
+        bmiHeader: BITMAPINFOHEADER {
+            biSize: mem::size_of::<BITMAPINFOHEADER>() as u32,
+            biWidth: width,
+            // Negative height for a top-down bitmap
+            biHeight: -height,
+            biPlanes: 1,
+            biBitCount: 32,
+            biCompression: BI_RGB.0,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut bgra = vec![0_u8; (width * height * 4) as usize];
+    let lines = unsafe {
+        SelectObject(canvas_dc, previous_canvas_object);
+        GetDIBits(
+            canvas_dc,
+            canvas,
+            0,
+            height as u32,
+            Some(bgra.as_mut_ptr() as *mut c_void),
+            &mut bitmap_info,
+            DIB_RGB_COLORS,
+        )
+    };
+    unsafe {
+        let _ = DeleteObject(canvas);
+        let _ = DeleteDC(canvas_dc);
+        ReleaseDC(HWND(0), screen_dc);
+    }
+    if lines != height {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            "Failed to read the captured bitmap",
+        ));
+    }
+
+    let rgb: Vec<u8> = bgra
+        .chunks_exact(4)
+        .flat_map(|pixel| return [pixel[2], pixel[1], pixel[0]])
+        .collect();
+    create_dir_all(directory)?;
+    let path = Path::new(directory).join(format!(
+        "{}_cluster.png",
+        chrono::offset::Utc::now().format("%Y-%m-%d_%H-%M-%S")
+    ));
+    write(&path, encode_png(width as u32, height as u32, &rgb)?)?;
+    return Ok(path);
+}
+
+/// Renders `window` into `canvas_dc` at the given position.
+///
+/// Returns whether the window was captured successfully.
+fn capture_window(window: HWND, rect: &RECT, screen_dc: HDC, canvas_dc: HDC) -> bool {
+    let width = rect.right - rect.left;
+    let height = rect.bottom - rect.top;
+    let window_dc = unsafe { CreateCompatibleDC(screen_dc) };
+    let window_bitmap = unsafe { CreateCompatibleBitmap(screen_dc, width, height) };
+    let previous_object = unsafe { SelectObject(window_dc, window_bitmap) };
+    let captured = unsafe { PrintWindow(window, window_dc, PW_RENDERFULLCONTENT) }.as_bool()
+        && unsafe {
+            BitBlt(
+                canvas_dc, rect.left, rect.top, width, height, window_dc, 0, 0, SRCCOPY,
+            )
+        }
+        .is_ok();
+    unsafe {
+        SelectObject(window_dc, previous_object);
+        let _ = DeleteObject(window_bitmap);
+        let _ = DeleteDC(window_dc);
+    }
+    return captured;
+}
+
+/// Encodes 8 bit RGB pixel data as PNG.
+///
+/// The image data is stored uncompressed, trading file size for not
+/// requiring an additional dependency.
+/// Fails for empty images, which PNG doesn't support, or if `rgb` doesn't
+/// hold exactly `width` times `height` pixels.
+fn encode_png(width: u32, height: u32, rgb: &[u8]) -> io::Result<Vec<u8>> {
+    if width == 0 || height == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Can't encode an empty image ({width}x{height}) as PNG"),
+        ));
+    }
+    if rgb.len() != width as usize * height as usize * 3 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Pixel data doesn't match the image size",
+        ));
+    }
+    // Every scanline is prefixed by its filter type, `0` (none).
+    let mut scanlines: Vec<u8> = Vec::new();
+    for row in rgb.chunks_exact(width as usize * 3) {
+        scanlines.push(0);
+        scanlines.extend_from_slice(row);
+    }
+
+    // zlib stream made of stored deflate blocks
+    let mut image_data: Vec<u8> = vec![0x78, 0x01];
+    let number_of_blocks = scanlines.chunks(MAX_STORED_BLOCK_LENGTH).len();
+    for (index, block) in scanlines.chunks(MAX_STORED_BLOCK_LENGTH).enumerate() {
+        image_data.push(u8::from(index + 1 == number_of_blocks));
+        image_data.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        image_data.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        image_data.extend_from_slice(block);
+    }
+    image_data.extend_from_slice(&adler32(&scanlines).to_be_bytes());
+
+    let mut header: Vec<u8> = Vec::new();
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // Bit depth 8, color type 2 (RGB), default compression, filter and no interlace
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut png: Vec<u8> = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+    write_png_chunk(&mut png, b"IHDR", &header);
+    write_png_chunk(&mut png, b"IDAT", &image_data);
+    write_png_chunk(&mut png, b"IEND", &[]);
+    return Ok(png);
+}
+
+fn write_png_chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let crc_start = png.len();
+    png.extend_from_slice(chunk_type);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[crc_start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0xFFFFFFFF;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                0xEDB88320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    return !crc;
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b): (u32, u32) = (1, 0);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    return (b << 16) | a;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the type and data of all chunks of `png`, checking their CRCs.
+    fn read_png_chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        let mut chunks = Vec::new();
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let chunk_type: [u8; 4] = rest[4..8].try_into().unwrap();
+            let data = rest[8..8 + length].to_vec();
+            let crc = u32::from_be_bytes(rest[8 + length..12 + length].try_into().unwrap());
+            assert_eq!(crc, crc32(&rest[4..8 + length]));
+            chunks.push((chunk_type, data));
+            rest = &rest[12 + length..];
+        }
+        return chunks;
+    }
+
+    /// Decodes the pixel data of an unfiltered RGB `png` of the given width.
+    fn decode_png(png: &[u8], width: usize) -> Vec<u8> {
+        let chunks = read_png_chunks(png);
+        assert_eq!(&chunks[0].0, b"IHDR");
+        assert_eq!(&chunks.last().unwrap().0, b"IEND");
+        let image_data: Vec<u8> = chunks
+            .iter()
+            .filter(|(chunk_type, _)| return chunk_type == b"IDAT")
+            .flat_map(|(_, data)| return data.clone())
+            .collect();
+        let scanlines = miniz_oxide::inflate::decompress_to_vec_zlib(&image_data).unwrap();
+        return scanlines
+            .chunks_exact(width * 3 + 1)
+            .flat_map(|scanline| {
+                assert_eq!(scanline[0], 0);
+                return scanline[1..].to_vec();
+            })
+            .collect();
+    }
+
+    #[test]
+    fn test_encode_png_round_trip() {
+        let (width, height) = (3, 2);
+        let rgb: Vec<u8> = (0..width * height * 3)
+            .map(|value| return value as u8)
+            .collect();
+        let png = encode_png(width, height, &rgb).unwrap();
+        let chunks = read_png_chunks(&png);
+        assert_eq!(&chunks[0].1[..8], &[0, 0, 0, 3, 0, 0, 0, 2]);
+        assert_eq!(decode_png(&png, width as usize), rgb);
+    }
+
+    #[test]
+    fn test_encode_png_spans_multiple_stored_blocks() {
+        // Each scanline holds 3 * 30000 bytes, so they don't fit into a single stored block.
+        let (width, height) = (30000, 3);
+        let rgb: Vec<u8> = (0..width * height * 3)
+            .map(|value| return (value % 251) as u8)
+            .collect();
+        let png = encode_png(width, height, &rgb).unwrap();
+        assert_eq!(decode_png(&png, width as usize), rgb);
+    }
+
+    #[test]
+    fn test_encode_png_rejects_empty_images() {
+        assert!(encode_png(0, 0, &[]).is_err());
+        assert!(encode_png(0, 2, &[]).is_err());
+        assert!(encode_png(2, 0, &[]).is_err());
+    }
+
+    #[test]
+    fn test_encode_png_rejects_mismatching_pixel_data() {
+        assert!(encode_png(2, 2, &[0; 9]).is_err());
+    }
+}
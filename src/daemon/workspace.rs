@@ -1,16 +1,34 @@
 use std::ffi::c_void;
-use std::ptr;
+use std::{mem, ptr};
 
-use windows::Win32::Foundation::{POINT, RECT};
-use windows::Win32::Graphics::Gdi::{MonitorFromPoint, HMONITOR, MONITOR_DEFAULTTOPRIMARY};
+use log::warn;
+use windows::Win32::Foundation::{BOOL, LPARAM, POINT, RECT, TRUE};
+use windows::Win32::Graphics::Gdi::{
+    EnumDisplayMonitors, GetMonitorInfoW, MonitorFromPoint, HDC, HMONITOR, MONITORINFO,
+    MONITOR_DEFAULTTOPRIMARY,
+};
+use windows::Win32::UI::HiDpi::{GetDpiForMonitor, GetSystemMetricsForDpi, MDT_EFFECTIVE_DPI};
 use windows::Win32::UI::Shell::GetScaleFactorForMonitor;
 use windows::Win32::UI::WindowsAndMessaging::{
-    GetSystemMetrics, SystemParametersInfoW, SM_CXFIXEDFRAME, SM_CXSIZEFRAME, SM_CYFIXEDFRAME,
-    SM_CYSIZEFRAME, SPI_GETWORKAREA, SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS,
+    GetSystemMetrics, SystemParametersInfoW, SM_CXFIXEDFRAME, SM_CXSCREEN, SM_CXSIZEFRAME,
+    SM_CYFIXEDFRAME, SM_CYSCREEN, SM_CYSIZEFRAME, SPI_GETWORKAREA,
+    SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS,
 };
 
+use crate::utils::config::DaemonPosition;
 use crate::utils::is_windows_10;
 
+/// Minimum width and height of a usable work area.
+/// Some headless or RDP sessions report no or a tiny work area.
+const MIN_WORKSPACE_SIZE: i32 = 200;
+/// Work area used if neither the work area nor the primary monitor report a usable size.
+const FALLBACK_WORKSPACE_RECT: RECT = RECT {
+    left: 0,
+    top: 0,
+    right: 1920,
+    bottom: 1080,
+};
+
 #[derive(Clone, Copy, Debug)]
 pub enum Scaling {
     Physical,
@@ -47,6 +65,57 @@ impl WorkspaceArea {
         }
     }
 
+    /// Returns the `index`th of `count` equally wide regions of this workspace area.
+    pub fn split(&self, index: usize, count: usize) -> WorkspaceArea {
+        let count = count.max(1) as i32;
+        let index = (index as i32).min(count - 1);
+        let width = self.width / count;
+        return WorkspaceArea {
+            x: self.x + index * width,
+            width,
+            ..*self
+        };
+    }
+
+    /// Returns the smallest workspace area containing all of the given ones,
+    /// with the scaling and frames of the first one.
+    pub fn bounding(workspace_areas: &[WorkspaceArea]) -> WorkspaceArea {
+        let left = workspace_areas.iter().map(|area| return area.x).min();
+        let top = workspace_areas.iter().map(|area| return area.y).min();
+        let right = workspace_areas
+            .iter()
+            .map(|area| return area.x + area.width)
+            .max();
+        let bottom = workspace_areas
+            .iter()
+            .map(|area| return area.y + area.height)
+            .max();
+        return WorkspaceArea {
+            x: left.unwrap_or(0),
+            y: top.unwrap_or(0),
+            width: right.unwrap_or(0) - left.unwrap_or(0),
+            height: bottom.unwrap_or(0) - top.unwrap_or(0),
+            ..workspace_areas[0]
+        };
+    }
+
+    /// Returns a logical workspace area without window frames.
+    #[cfg(test)]
+    pub fn new_logical(x: i32, y: i32, width: i32, height: i32) -> WorkspaceArea {
+        return WorkspaceArea {
+            x,
+            y,
+            width,
+            height,
+            scaling: Scaling::Logical,
+            x_fixed_frame: 0,
+            y_fixed_frame: 0,
+            x_size_frame: 0,
+            y_size_frame: 0,
+            scale_factor: 1_f64,
+        };
+    }
+
     fn convert_scaling(&self) -> WorkspaceArea {
         let scale_factor = 1_f64 / self.scale_factor;
         let x = self.x as f64 * scale_factor;
@@ -73,46 +142,215 @@ fn get_primary_monitor() -> HMONITOR {
     return unsafe { MonitorFromPoint(POINT::default(), MONITOR_DEFAULTTOPRIMARY) };
 }
 
-fn get_scale_factor() -> f64 {
+unsafe extern "system" fn enumerate_monitors_callback(
+    monitor: HMONITOR,
+    _: HDC,
+    _: *mut RECT,
+    lparam: LPARAM,
+) -> BOOL {
+    let monitors = &mut *(lparam.0 as *mut Vec<HMONITOR>);
+    monitors.push(monitor);
+    return TRUE;
+}
+
+/// Returns all monitors with their work area, ordered from left to right
+/// (and top to bottom for monitors stacked on top of each other).
+fn get_monitor_work_areas() -> Vec<(HMONITOR, RECT)> {
+    let mut monitors: Vec<HMONITOR> = Vec::new();
+    unsafe {
+        EnumDisplayMonitors(
+            None,
+            None,
+            Some(enumerate_monitors_callback),
+            LPARAM(ptr::addr_of_mut!(monitors) as isize),
+        )
+    };
+    let mut work_areas: Vec<(HMONITOR, RECT)> = monitors
+        .into_iter()
+        .filter_map(|monitor| {
+            let mut monitor_info = MONITORINFO {
+                cbSize: mem::size_of::<MONITORINFO>() as u32,
+                ..Default::default()
+            };
+            if !unsafe { GetMonitorInfoW(monitor, &mut monitor_info) }.as_bool() {
+                warn!("Failed to retrieve the work area of monitor {:?}", monitor);
+                return None;
+            }
+            return Some((monitor, monitor_info.rcWork));
+        })
+        .collect();
+    work_areas.sort_by_key(|(_, rect)| return (rect.left, rect.top));
+    return work_areas;
+}
+
+fn get_scale_factor(monitor: HMONITOR) -> f64 {
     let scale_factor = unsafe {
-        GetScaleFactorForMonitor(get_primary_monitor())
-            .expect("Failed to retrieve scale factor for primary monitor")
+        GetScaleFactorForMonitor(monitor)
+            .expect("Failed to retrieve scale factor for monitor")
             .0
     };
     // https://learn.microsoft.com/en-us/windows/win32/api/shtypes/ne-shtypes-device_scale_factor#constants
     return (scale_factor / 100).into();
 }
 
-pub fn get_workspace_area(scaling: Scaling, daemon_console_height: i32) -> WorkspaceArea {
+fn is_degenerate(rect: &RECT) -> bool {
+    return rect.right - rect.left < MIN_WORKSPACE_SIZE
+        || rect.bottom - rect.top < MIN_WORKSPACE_SIZE;
+}
+
+/// Returns the work area of the primary monitor.
+fn get_primary_work_area() -> RECT {
     let mut workspace_rect = RECT::default();
-    unsafe {
+    if let Err(err) = unsafe {
         SystemParametersInfoW(
             SPI_GETWORKAREA,
             0,
             Some(ptr::addr_of_mut!(workspace_rect) as *mut c_void),
             SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0),
         )
-        .unwrap();
+    } {
+        warn!("Failed to retrieve the work area: {}", err);
+    }
+    return workspace_rect;
+}
+
+/// Returns the workspace area of the given monitor (numbered from left to right,
+/// starting at 1), or the workspace areas of all monitors if none is given.
+///
+/// The first workspace area is the one of the daemon console: the given monitor,
+/// or the primary monitor followed by the other monitors from left to right.
+/// Its space of the daemon console at the given position is left out,
+/// unless the daemon console takes a cell of the client grid.
+pub fn get_workspace_area(
+    scaling: Scaling,
+    daemon_console_height: i32,
+    daemon_position: DaemonPosition,
+    monitor: Option<usize>,
+) -> Vec<WorkspaceArea> {
+    let monitor_work_areas = get_monitor_work_areas();
+    let selected_monitor_work_area = monitor.and_then(|number| {
+        let work_area = number
+            .checked_sub(1)
+            .and_then(|index| return monitor_work_areas.get(index).copied());
+        if work_area.is_none() {
+            warn!(
+                "Monitor {} doesn't exist, using the primary monitor",
+                number
+            );
+        }
+        return work_area;
+    });
+    let (daemon_monitor, daemon_work_area) = match selected_monitor_work_area {
+        Some(monitor_work_area) => monitor_work_area,
+        None => (get_primary_monitor(), get_primary_work_area()),
+    };
+    let daemon_console_height = if daemon_position.is_grid_cell() {
+        0
+    } else {
+        daemon_console_height
+    };
+    let mut workspace_areas = vec![get_monitor_workspace_area(
+        daemon_monitor,
+        get_usable_work_area(daemon_work_area),
+        daemon_console_height,
+        daemon_position,
+    )];
+    if monitor.is_none() {
+        for (other_monitor, work_area) in monitor_work_areas {
+            if other_monitor == daemon_monitor || is_degenerate(&work_area) {
+                continue;
+            }
+            workspace_areas.push(get_monitor_workspace_area(
+                other_monitor,
+                work_area,
+                0,
+                daemon_position,
+            ));
+        }
+    }
+    return workspace_areas
+        .into_iter()
+        .map(|workspace_area| match scaling {
+            Scaling::Physical => return workspace_area,
+            Scaling::Logical => return workspace_area.logical(),
+        })
+        .collect();
+}
+
+/// Returns the given work area, or a fallback if it is too small to be used.
+fn get_usable_work_area(work_area: RECT) -> RECT {
+    if !is_degenerate(&work_area) {
+        return work_area;
     }
-    let x_fixed_frame = unsafe { GetSystemMetrics(SM_CXFIXEDFRAME) };
-    let y_fixed_frame = unsafe { GetSystemMetrics(SM_CYFIXEDFRAME) };
-    let x_size_frame = unsafe { GetSystemMetrics(SM_CXSIZEFRAME) };
-    let y_size_frame = unsafe { GetSystemMetrics(SM_CYSIZEFRAME) };
-    let workspace_area = WorkspaceArea {
+    let primary_monitor_rect = RECT {
+        left: 0,
+        top: 0,
+        right: unsafe { GetSystemMetrics(SM_CXSCREEN) },
+        bottom: unsafe { GetSystemMetrics(SM_CYSCREEN) },
+    };
+    let fallback_rect = if is_degenerate(&primary_monitor_rect) {
+        FALLBACK_WORKSPACE_RECT
+    } else {
+        primary_monitor_rect
+    };
+    warn!(
+        "Work area {:?} is too small, falling back to {:?}",
+        work_area, fallback_rect
+    );
+    return fallback_rect;
+}
+
+/// Returns the physical workspace area of the given monitor's work area,
+/// leaving out the daemon console at the given position.
+fn get_monitor_workspace_area(
+    monitor: HMONITOR,
+    workspace_rect: RECT,
+    daemon_console_height: i32,
+    daemon_position: DaemonPosition,
+) -> WorkspaceArea {
+    let (x_fixed_frame, y_fixed_frame, x_size_frame, y_size_frame) = get_frame_sizes(monitor);
+    return WorkspaceArea {
         x: workspace_rect.left - (x_fixed_frame + x_size_frame),
-        y: workspace_rect.top,
+        y: workspace_rect.top
+            + match daemon_position {
+                DaemonPosition::Top => daemon_console_height,
+                _ => 0,
+            },
         width: workspace_rect.right - workspace_rect.left
             + (if is_windows_10() { -x_size_frame } else { 0 }),
         height: workspace_rect.bottom - workspace_rect.top - daemon_console_height,
         scaling: Scaling::Physical,
-        scale_factor: get_scale_factor(),
+        scale_factor: get_scale_factor(monitor),
         x_fixed_frame,
         y_fixed_frame,
         x_size_frame,
         y_size_frame,
     };
-    match scaling {
-        Scaling::Physical => return workspace_area,
-        Scaling::Logical => return workspace_area.logical(),
+}
+
+/// Returns the (`x_fixed_frame`, `y_fixed_frame`, `x_size_frame`, `y_size_frame`) of
+/// windows on the given monitor, the frames of other monitors than the primary one
+/// are sized for their DPI.
+fn get_frame_sizes(monitor: HMONITOR) -> (i32, i32, i32, i32) {
+    let (mut dpi_x, mut dpi_y) = (0, 0);
+    if monitor == get_primary_monitor()
+        || unsafe { GetDpiForMonitor(monitor, MDT_EFFECTIVE_DPI, &mut dpi_x, &mut dpi_y) }.is_err()
+    {
+        return unsafe {
+            (
+                GetSystemMetrics(SM_CXFIXEDFRAME),
+                GetSystemMetrics(SM_CYFIXEDFRAME),
+                GetSystemMetrics(SM_CXSIZEFRAME),
+                GetSystemMetrics(SM_CYSIZEFRAME),
+            )
+        };
     }
+    return unsafe {
+        (
+            GetSystemMetricsForDpi(SM_CXFIXEDFRAME, dpi_x),
+            GetSystemMetricsForDpi(SM_CYFIXEDFRAME, dpi_y),
+            GetSystemMetricsForDpi(SM_CXSIZEFRAME, dpi_x),
+            GetSystemMetricsForDpi(SM_CYSIZEFRAME, dpi_y),
+        )
+    };
 }
@@ -6,11 +6,12 @@ use std::{mem, ptr};
 
 use std::os::windows::ffi::OsStrExt;
 
+use clap::ValueEnum;
 use simplelog::{format_description, ConfigBuilder, LevelFilter, WriteLogger};
 use windows::core::{HSTRING, PCWSTR, PWSTR};
 use windows::Win32::Foundation::BOOL;
 use windows::Win32::System::Threading::{
-    CreateProcessW, CREATE_NEW_CONSOLE, PROCESS_INFORMATION, STARTUPINFOW,
+    CreateProcessW, CREATE_NEW_CONSOLE, PROCESS_CREATION_FLAGS, PROCESS_INFORMATION, STARTUPINFOW,
 };
 
 pub mod client;
@@ -18,7 +19,110 @@ pub mod daemon;
 pub mod serde;
 pub mod utils;
 
-pub fn spawn_console_process(application: &str, args: Vec<&str>) -> PROCESS_INFORMATION {
+use crate::utils::json_logger::JsonLogger;
+
+/// Format of the log files written in debug mode.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+impl LogFormat {
+    /// Returns the command line value of the format, to be passed on to child processes.
+    pub fn as_arg(&self) -> &'static str {
+        return match self {
+            LogFormat::Text => "text",
+            LogFormat::Json => "json",
+        };
+    }
+}
+
+/// Options of a session given on the command line, which the daemon
+/// passes on to the clients it launches.
+#[derive(Clone, Debug, Default)]
+pub struct LaunchOptions {
+    /// Whether to write debug logs.
+    pub debug: bool,
+    /// Format of the log files written in debug mode.
+    pub log_format: LogFormat,
+    /// Name of the configuration profile in use, if any.
+    pub profile: Option<String>,
+    /// Path of the Ansible inventory whose groups are used as additional clusters, if any.
+    pub ansible_inventory: Option<String>,
+    /// Index of the region of the workspace the daemon occupies when the hosts
+    /// are split across multiple daemons.
+    pub split_index: usize,
+    /// Total number of daemons the hosts are split across.
+    pub split_count: usize,
+    /// Whether to color the client window borders based on their latency.
+    pub latency_colors: bool,
+    /// Whether the daemon console is hidden until revealed via hotkey.
+    pub hidden_daemon: bool,
+    /// Monitor (numbered from left to right, starting at 1) the windows are placed on,
+    /// all monitors if none is given.
+    pub monitor: Option<usize>,
+    /// Fixed number of grid columns and rows overriding the aspect ratio based layout.
+    pub grid: Option<(usize, usize)>,
+    /// Whether the broadcasted input is shown in the daemon console.
+    pub echo: bool,
+    /// Whether the clients print the received input instead of launching SSH.
+    pub echo_only: bool,
+    /// Environment variables (`KEY=VALUE`) passed to the clients and their SSH processes.
+    pub environment: Vec<String>,
+    /// Command the clients run on their host instead of an interactive session.
+    pub command: Option<String>,
+    /// Whether the clients fall back to the current Windows user if neither a username
+    /// was given nor the SSH config defines one.
+    pub username_from_whoami: bool,
+    /// Port used to connect to hosts not given with a port, if any.
+    pub port: Option<u16>,
+    /// Whether the clients relaunch SSH processes that failed to connect.
+    pub reconnect: bool,
+    /// Name of the layout in use, if any, the active sets are saved to it.
+    pub layout: Option<String>,
+}
+
+/// Quotes `arg` so that it is parsed back as a single, unaltered argument
+/// by the MSVC runtime (`CommandLineToArgvW` rules).
+///
+/// Quotes inside the argument are escaped as `\"` and backslashes preceding a quote,
+/// including the closing one, are doubled. All other backslashes are taken literally.
+fn quote_argument(arg: &str) -> String {
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    quoted.push('"');
+    let mut backslashes = 0;
+    for character in arg.chars() {
+        match character {
+            '\\' => {
+                backslashes += 1;
+                continue;
+            }
+            '"' => {
+                quoted.push_str(&"\\".repeat(backslashes * 2 + 1));
+            }
+            _ => {
+                quoted.push_str(&"\\".repeat(backslashes));
+            }
+        }
+        backslashes = 0;
+        quoted.push(character);
+    }
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+    return quoted;
+}
+
+/// Spawns `application` with the given arguments in a new console,
+/// using the given priority class (e.g. `NORMAL_PRIORITY_CLASS`).
+pub fn spawn_console_process(
+    application: &str,
+    args: Vec<&str>,
+    priority_class: PROCESS_CREATION_FLAGS,
+) -> PROCESS_INFORMATION {
     let mut cmd: Vec<u16> = Vec::new();
     cmd.push(b'"' as u16);
     cmd.extend(OsString::from(application).encode_wide());
@@ -26,9 +130,7 @@ pub fn spawn_console_process(application: &str, args: Vec<&str>) -> PROCESS_INFO
 
     for arg in args {
         cmd.push(' ' as u16);
-        cmd.push(b'"' as u16);
-        cmd.extend(OsString::from(arg).encode_wide());
-        cmd.push(b'"' as u16);
+        cmd.extend(OsString::from(quote_argument(arg)).encode_wide());
     }
     cmd.push(0); // add null terminator
 
@@ -47,7 +149,7 @@ pub fn spawn_console_process(application: &str, args: Vec<&str>) -> PROCESS_INFO
             Some(ptr::null_mut()),
             Some(ptr::null_mut()),
             BOOL::from(false),
-            CREATE_NEW_CONSOLE,
+            CREATE_NEW_CONSOLE | priority_class,
             Some(ptr::null_mut()),
             PCWSTR::null(),
             ptr::addr_of_mut!(startupinfo),
@@ -58,18 +160,62 @@ pub fn spawn_console_process(application: &str, args: Vec<&str>) -> PROCESS_INFO
     return process_information;
 }
 
-pub fn init_logger(name: &str) {
+pub fn init_logger(name: &str, log_format: LogFormat) {
     let utc_now = chrono::offset::Utc::now()
         .format("%Y-%m-%d_%H-%M-%S.%f")
         .to_string();
     let _ = create_dir("logs"); // directory already exists is fine too
-    WriteLogger::init(
-        LevelFilter::Debug,
-        ConfigBuilder::new()
-            .set_time_format_custom(format_description!("[hour]:[minute]:[second].[subsecond]"))
-            .build(),
-        File::create(format!("logs/{utc_now}_{name}.log")).unwrap(),
-    )
+    let file = File::create(format!("logs/{utc_now}_{name}.log")).unwrap();
+    match log_format {
+        LogFormat::Text => WriteLogger::init(
+            LevelFilter::Debug,
+            ConfigBuilder::new()
+                .set_time_format_custom(format_description!("[hour]:[minute]:[second].[subsecond]"))
+                .build(),
+            file,
+        ),
+        LogFormat::Json => JsonLogger::init(LevelFilter::Debug, file),
+    }
     .unwrap();
     log_panics::init();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_format_as_arg_parses_back() {
+        for log_format in LogFormat::value_variants() {
+            assert_eq!(
+                LogFormat::from_str(log_format.as_arg(), false),
+                Ok(*log_format)
+            );
+        }
+    }
+
+    #[test]
+    fn test_quote_argument_plain() {
+        assert_eq!(quote_argument("host"), "\"host\"");
+        assert_eq!(quote_argument(""), "\"\"");
+        assert_eq!(quote_argument("a b c"), "\"a b c\"");
+    }
+
+    #[test]
+    fn test_quote_argument_escapes_quotes() {
+        assert_eq!(quote_argument("KEY=\"v a l\""), r#""KEY=\"v a l\"""#);
+        assert_eq!(quote_argument("a\\\"b"), r#""a\\\"b""#);
+    }
+
+    #[test]
+    fn test_quote_argument_keeps_inner_backslashes() {
+        assert_eq!(quote_argument("C:\\path\\to"), r#""C:\path\to""#);
+        assert_eq!(quote_argument("\\\\server\\share"), r#""\\server\share""#);
+    }
+
+    #[test]
+    fn test_quote_argument_doubles_trailing_backslashes() {
+        assert_eq!(quote_argument("C:\\dir\\"), r#""C:\dir\\""#);
+        assert_eq!(quote_argument("a\\\\"), r#""a\\\\""#);
+    }
+}
@@ -1,13 +1,26 @@
 #![deny(clippy::implicit_return)]
 #![allow(clippy::needless_return)]
 
+use std::path::Path;
+use std::time::Duration;
+
 use clap::{ArgAction, Parser, Subcommand};
-use csshw::client::main as client_main;
-use csshw::daemon::main as daemon_main;
-use csshw::utils::config::{Cluster, Config, ConfigOpt};
-use csshw::{init_logger, spawn_console_process};
+use csshw::client::{describe_connection, main as client_main};
+use csshw::daemon::{main as daemon_main, plan_client_windows};
+use csshw::utils::ansible::load_ansible_inventory;
+use csshw::utils::config::{
+    deduplicate_hosts, expand_cidr_clusters, get_added_config_keys, get_config_path,
+    get_layout_path, get_unresolved_cluster_references, load_layout, resolve_cluster_tags,
+    save_layout, validate_config, Cluster, Config, ConfigOpt, Layout,
+};
+use csshw::utils::constants::DEFAULT_SSH_USERNAME_KEY;
+use csshw::utils::host_picker::pick_hosts;
+use csshw::utils::natural_cmp;
+use csshw::utils::ssh_config::get_configured_hosts;
+use csshw::{init_logger, spawn_console_process, LaunchOptions, LogFormat};
 use windows::core::PCWSTR;
 use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::System::Threading::NORMAL_PRIORITY_CLASS;
 use windows::Win32::UI::WindowsAndMessaging::{LoadImageW, IMAGE_ICON, LR_DEFAULTSIZE};
 
 const PKG_NAME: &str = env!("CARGO_PKG_NAME");
@@ -21,12 +34,86 @@ struct Args {
     /// Username used to connect to the hosts
     #[clap(short, long)]
     username: Option<String>,
+    /// Connect as the current Windows user to hosts the SSH config defines no `User` for,
+    /// unless a username is given
+    #[clap(long, action=ArgAction::SetTrue)]
+    username_from_whoami: bool,
+    /// Port used to connect to hosts not given as `<host>:<port>`
+    #[clap(short, long)]
+    port: Option<u16>,
+    /// Relaunch the SSH process of clients whose connection failed (exit code 255),
+    /// see `reconnect_attempts`
+    #[clap(long, action=ArgAction::SetTrue)]
+    reconnect: bool,
     /// Hosts to connect to
     #[clap(required = false)]
     hosts: Vec<String>,
+    /// Additionally connect to the hosts (or clusters) listed in the given file, one per line
+    #[clap(long, value_name = "PATH")]
+    hosts_file: Option<String>,
     /// Enable extensive logging
     #[clap(short, long, action=ArgAction::SetTrue)]
     debug: bool,
+    /// Format of the log files written with `--debug`
+    #[clap(long, value_enum, value_name = "FORMAT", default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+    /// Split the hosts across N independent daemons, each occupying its own region of the screen
+    #[clap(long, value_name = "N", default_value_t = 1)]
+    split: usize,
+    /// Color the client window borders based on the latency of a TCP probe to the SSH port
+    #[clap(long, action=ArgAction::SetTrue)]
+    latency_colors: bool,
+    /// Sort the hosts in natural order (e.g. `host2` before `host10`) instead of keeping the given order
+    #[clap(long, action=ArgAction::SetTrue)]
+    sort: bool,
+    /// Connect only once to hosts given multiple times (e.g. via overlapping clusters)
+    #[clap(long, action=ArgAction::SetTrue)]
+    unique: bool,
+    /// Arrange the clients in a fixed grid of COLUMNS x ROWS, e.g. `4x3`
+    #[clap(
+        long,
+        visible_alias = "tile-layout",
+        value_name = "COLUMNSxROWS",
+        value_parser = parse_grid
+    )]
+    grid: Option<(usize, usize)>,
+    /// Hide the daemon console, giving its space to the clients (Ctrl+Alt+A reveals it)
+    #[clap(long, action=ArgAction::SetTrue)]
+    hidden_daemon: bool,
+    /// Place the windows on monitor N only (numbered from left to right, starting at 1)
+    /// instead of across all monitors
+    #[clap(long, value_name = "N")]
+    monitor: Option<usize>,
+    /// Use the configuration of the given profile instead of the default configuration
+    #[clap(long, value_name = "NAME")]
+    profile: Option<String>,
+    /// Arrange the windows according to the given layout saved via `save-layout`
+    #[clap(long, value_name = "NAME")]
+    layout: Option<String>,
+    /// Additionally use the groups of the given Ansible INI inventory as clusters
+    #[clap(long, value_name = "PATH")]
+    ansible_inventory: Option<String>,
+    /// Show the broadcasted input in the daemon console
+    #[clap(long, action=ArgAction::SetTrue)]
+    echo: bool,
+    /// Print the received input in the client windows instead of launching SSH (for debugging)
+    #[clap(long, hide = true, action=ArgAction::SetTrue)]
+    echo_only: bool,
+    /// Set an environment variable for the SSH processes, can be given multiple times
+    #[clap(
+        long = "env",
+        value_name = "KEY=VALUE",
+        value_parser = parse_environment_variable,
+        action = ArgAction::Append
+    )]
+    environment: Vec<String>,
+    /// Run the given command on all hosts instead of an interactive session,
+    /// the clients exit once it completed
+    #[clap(long = "command", value_name = "COMMAND")]
+    remote_command: Option<String>,
+    /// Print the connection and window position of each host instead of launching anything
+    #[clap(long, action=ArgAction::SetTrue)]
+    dry_run: bool,
 }
 
 #[derive(Debug, Subcommand)]
@@ -36,6 +123,35 @@ enum Commands {
         host: String,
         /// Username used to connect to the hosts
         username: String,
+        /// Name of the daemon's named pipe to read input from
+        #[clap(long)]
+        pipe_name: String,
+        /// Print the received input instead of launching SSH (for debugging)
+        #[clap(long, hide = true, action=ArgAction::SetTrue)]
+        echo_only: bool,
+        /// Position of the client among the clients launched together
+        #[clap(long, default_value_t = 0)]
+        index: usize,
+        /// Environment variable set for the SSH process
+        #[clap(
+            long = "env",
+            value_name = "KEY=VALUE",
+            value_parser = parse_environment_variable,
+            action = ArgAction::Append
+        )]
+        environment: Vec<String>,
+        /// Command to run on the host instead of an interactive session
+        #[clap(long)]
+        command: Option<String>,
+        /// Fall back to the current Windows user if the SSH config defines no `User`
+        #[clap(long, action=ArgAction::SetTrue)]
+        username_from_whoami: bool,
+        /// Port used to connect if the host isn't given with a port
+        #[clap(long)]
+        port: Option<u16>,
+        /// Relaunch the SSH process if it failed to connect
+        #[clap(long, action=ArgAction::SetTrue)]
+        reconnect: bool,
     },
     Daemon {
         /// Username used to connect to the hosts
@@ -44,29 +160,167 @@ enum Commands {
 
         /// Host(s) to connect to
         hosts: Vec<String>,
+
+        /// Index of the screen region this daemon occupies when splitting hosts across daemons
+        #[clap(long, default_value_t = 0)]
+        split_index: usize,
+
+        /// Number of daemons the hosts are split across
+        #[clap(long, default_value_t = 1)]
+        split_count: usize,
+
+        /// Color the client window borders based on the latency of a TCP probe to the SSH port
+        #[clap(long, action=ArgAction::SetTrue)]
+        latency_colors: bool,
+
+        /// Hide the daemon console, giving its space to the clients (Ctrl+Alt+A reveals it)
+        #[clap(long, action=ArgAction::SetTrue)]
+        hidden_daemon: bool,
+
+        /// Monitor (numbered from left to right, starting at 1) the windows are placed on,
+        /// all monitors if none is given
+        #[clap(long)]
+        monitor: Option<usize>,
+
+        /// Arrange the clients in a fixed grid of COLUMNS x ROWS, e.g. `4x3`
+        #[clap(long, value_name = "COLUMNSxROWS", value_parser = parse_grid)]
+        grid: Option<(usize, usize)>,
+
+        /// Show the broadcasted input in the daemon console
+        #[clap(long, action=ArgAction::SetTrue)]
+        echo: bool,
+
+        /// Print the received input in the client windows instead of launching SSH (for debugging)
+        #[clap(long, hide = true, action=ArgAction::SetTrue)]
+        echo_only: bool,
+
+        /// Set an environment variable for the SSH processes, can be given multiple times
+        #[clap(
+            long = "env",
+            value_name = "KEY=VALUE",
+            value_parser = parse_environment_variable,
+            action = ArgAction::Append
+        )]
+        environment: Vec<String>,
+
+        /// Run the given command on all hosts instead of an interactive session
+        #[clap(long)]
+        command: Option<String>,
+
+        /// Fall back to the current Windows user if the SSH config defines no `User`
+        #[clap(long, action=ArgAction::SetTrue)]
+        username_from_whoami: bool,
+
+        /// Port used to connect to hosts not given with a port
+        #[clap(long)]
+        port: Option<u16>,
+
+        /// Relaunch the SSH processes of clients whose connection failed
+        #[clap(long, action=ArgAction::SetTrue)]
+        reconnect: bool,
+    },
+    /// List the configured clusters or hosts without launching anything
+    List {
+        #[clap(subcommand)]
+        list_command: ListCommands,
+    },
+    /// Check the configuration for common mistakes without launching anything
+    CheckConfig,
+    /// Pick the hosts to connect to from the hosts of the SSH config
+    Pick,
+    /// Save the current window layout (including `--grid` and `--monitor`) under the given name
+    SaveLayout {
+        /// Name of the layout, used with `--layout`
+        name: String,
     },
 }
 
-fn resolve_cluster_tags<'a>(hosts: Vec<&'a str>, clusters: &'a Vec<Cluster>) -> Vec<&'a str> {
-    let mut resolved_hosts: Vec<&str> = Vec::new();
-    let mut is_cluster_tag: bool;
-    for host in hosts {
-        is_cluster_tag = false;
-        for cluster in clusters {
-            if host == cluster.name {
-                is_cluster_tag = true;
-                resolved_hosts.extend(resolve_cluster_tags(
-                    cluster.hosts.iter().map(|host| return &**host).collect(),
-                    clusters,
-                ));
-                break;
-            }
-        }
-        if !is_cluster_tag {
-            resolved_hosts.push(host);
-        }
+#[derive(Debug, Subcommand)]
+enum ListCommands {
+    /// List all clusters with their resolved hosts, one cluster per line
+    Clusters,
+    /// List the resolved hosts of a cluster, one host per line
+    Hosts {
+        /// Cluster tag to resolve
+        cluster: String,
+    },
+}
+
+/// Parses a `<COLUMNS>x<ROWS>` grid specification, e.g. `4x3`.
+fn parse_grid(grid: &str) -> Result<(usize, usize), String> {
+    let error = || return format!("Invalid grid `{grid}`, expected `<COLUMNS>x<ROWS>`, e.g. `4x3`");
+    let (columns, rows) = grid.split_once('x').ok_or_else(error)?;
+    let columns: usize = columns.parse().map_err(|_| return error())?;
+    let rows: usize = rows.parse().map_err(|_| return error())?;
+    if columns == 0 || rows == 0 {
+        return Err(error());
     }
-    return resolved_hosts;
+    return Ok((columns, rows));
+}
+
+/// Validates a `<KEY>=<VALUE>` environment variable specification, e.g. `SSH_AUTH_SOCK=...`.
+fn parse_environment_variable(environment_variable: &str) -> Result<String, String> {
+    return match environment_variable.split_once('=') {
+        Some((key, _)) if !key.is_empty() => Ok(environment_variable.to_owned()),
+        _ => Err(format!(
+            "Invalid environment variable `{environment_variable}`, expected `<KEY>=<VALUE>`"
+        )),
+    };
+}
+
+/// Reads the hosts (or cluster tags) listed in the file at `path`, one per line.
+///
+/// Blank lines and lines starting with `#` are ignored.
+fn read_hosts_file(path: &Path) -> std::io::Result<Vec<String>> {
+    return Ok(std::fs::read_to_string(path)?
+        .lines()
+        .map(|line| return line.trim())
+        .filter(|line| return !line.is_empty() && !line.starts_with('#'))
+        .map(|line| return line.to_owned())
+        .collect());
+}
+
+/// Splits `hosts` into `number_of_groups` consecutive groups of (almost) equal size.
+fn split_hosts(hosts: Vec<&str>, number_of_groups: usize) -> Vec<Vec<&str>> {
+    let number_of_hosts = hosts.len();
+    return (0..number_of_groups)
+        .map(|group| {
+            return hosts[group * number_of_hosts / number_of_groups
+                ..(group + 1) * number_of_hosts / number_of_groups]
+                .to_vec();
+        })
+        .collect();
+}
+
+/// Tells the user where the default configuration was created on first run
+/// and which defaults are in effect.
+fn print_config_created_notice(config_path: &str, config: &Config) {
+    let config_path = std::env::current_dir()
+        .map(|directory| return directory.join(config_path))
+        .unwrap_or_else(|_| return config_path.into());
+    println!(
+        "Created default configuration at `{}`",
+        config_path.display()
+    );
+    println!(
+        "  client: `{} {}`",
+        config.client.program,
+        config.client.arguments.join(" ")
+    );
+    println!("  ssh config: `{}`", config.client.ssh_config_path);
+    println!("  daemon height: {}", config.daemon.height);
+}
+
+/// Lists the settings that were added with their defaults
+/// while migrating an existing configuration to the current format.
+fn print_config_migrated_notice(config_path: &str, added_keys: &[String]) {
+    if added_keys.is_empty() {
+        return;
+    }
+    println!(
+        "Updated configuration `{config_path}`, added default(s) for: {}",
+        added_keys.join(", ")
+    );
 }
 
 #[tokio::main]
@@ -83,6 +337,8 @@ async fn main() {
         .unwrap()
     };
 
+    // Relative paths given as arguments refer to the original working directory.
+    let initial_working_directory = std::env::current_dir().unwrap_or_default();
     match std::env::current_exe() {
         Ok(path) => match path.parent() {
             None => {
@@ -100,46 +356,491 @@ async fn main() {
 
     let args = Args::parse();
 
-    let config_path = format!("{PKG_NAME}-config.toml");
-    let config_on_disk: ConfigOpt = confy::load_path(&config_path).unwrap();
-    let config: Config = config_on_disk.into();
+    let config_path = get_config_path(args.profile.as_deref());
+    let config_existed = std::path::Path::new(&config_path).exists();
+    if let Some(profile) = args.profile.as_ref() {
+        if !config_existed {
+            eprintln!(
+                "Profile `{profile}` does not exist, creating it from defaults at `{config_path}`"
+            );
+        }
+    }
+    let config_on_disk: ConfigOpt = confy::load_path(&config_path).unwrap_or_else(|err| {
+        eprintln!("Failed to load configuration `{config_path}`: {err}");
+        std::process::exit(1);
+    });
+    let mut config: Config = config_on_disk.into();
+
+    let layout = args.layout.as_ref().map(|layout_name| {
+        return load_layout(layout_name).unwrap_or_else(|err| {
+            eprintln!("{err}");
+            std::process::exit(1);
+        });
+    });
+    // Explicitly given choices take precedence over those of the layout.
+    let grid = args
+        .grid
+        .or_else(|| return layout.as_ref().and_then(|layout| return layout.grid));
+    let monitor = args
+        .monitor
+        .or_else(|| return layout.as_ref().and_then(|layout| return layout.monitor));
+
+    let ansible_inventory = args
+        .ansible_inventory
+        .as_ref()
+        .map(|path| return initial_working_directory.join(path));
+    let mut clusters: Vec<Cluster> = config.clusters.clone();
+    if let Some(ansible_inventory) = ansible_inventory.as_ref() {
+        match load_ansible_inventory(ansible_inventory) {
+            Ok(inventory_clusters) => clusters.extend(inventory_clusters),
+            Err(err) => {
+                eprintln!(
+                    "Failed to load Ansible inventory `{}`: {err}",
+                    ansible_inventory.display()
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+    expand_cidr_clusters(&mut clusters);
+
+    let mut given_hosts = args.hosts.clone();
+    if let Some(Commands::Pick) = args.command {
+        let picked_hosts = pick_hosts(&get_configured_hosts(Path::new(
+            &config.client.ssh_config_path,
+        )));
+        if picked_hosts.is_empty() {
+            return;
+        }
+        given_hosts.extend(picked_hosts);
+    }
+    if let Some(hosts_file) = args.hosts_file.as_ref() {
+        let hosts_file = initial_working_directory.join(hosts_file);
+        match read_hosts_file(&hosts_file) {
+            Ok(file_hosts) => given_hosts.extend(file_hosts),
+            Err(err) => {
+                eprintln!(
+                    "Failed to read hosts file `{}`: {err}",
+                    hosts_file.display()
+                );
+                std::process::exit(1);
+            }
+        }
+    }
 
     match &args.command {
-        Some(Commands::Client { host, username }) => {
+        Some(Commands::Client {
+            host,
+            username,
+            pipe_name,
+            echo_only,
+            index,
+            environment,
+            command,
+            username_from_whoami,
+            port,
+            reconnect,
+        }) => {
             if args.debug {
-                init_logger(&format!("csshw_client_{host}"));
+                init_logger(&format!("csshw_client_{host}"), args.log_format);
             }
-            client_main(host.to_owned(), username.to_owned(), &config.client).await;
+            client_main(
+                host.to_owned(),
+                username.to_owned(),
+                pipe_name.to_owned(),
+                &config.client,
+                &clusters,
+                Duration::from_millis(config.daemon.keep_alive_interval_ms),
+                config.daemon.monitor_pane,
+                *index,
+                &LaunchOptions {
+                    debug: args.debug,
+                    log_format: args.log_format,
+                    profile: args.profile.clone(),
+                    echo_only: *echo_only,
+                    environment: environment.to_owned(),
+                    command: command.to_owned(),
+                    username_from_whoami: *username_from_whoami,
+                    port: *port,
+                    reconnect: *reconnect,
+                    ..Default::default()
+                },
+            )
+            .await;
         }
-        Some(Commands::Daemon { username, hosts }) => {
+        Some(Commands::Daemon {
+            username,
+            hosts,
+            split_index,
+            split_count,
+            latency_colors,
+            hidden_daemon,
+            monitor,
+            grid,
+            echo,
+            echo_only,
+            environment,
+            command,
+            username_from_whoami,
+            port,
+            reconnect,
+        }) => {
             if args.debug {
-                init_logger("csshw_daemon");
+                init_logger("csshw_daemon", args.log_format);
+            }
+            if let Some(layout) = layout.as_ref() {
+                layout.apply(&mut config.daemon);
             }
             daemon_main(
                 hosts.to_owned(),
                 username.clone(),
+                clusters,
+                layout
+                    .map(|layout| return layout.active_sets)
+                    .unwrap_or_default(),
                 &config.daemon,
-                args.debug,
+                LaunchOptions {
+                    debug: args.debug,
+                    log_format: args.log_format,
+                    profile: args.profile.clone(),
+                    ansible_inventory: ansible_inventory
+                        .as_ref()
+                        .map(|path| return path.display().to_string()),
+                    split_index: *split_index,
+                    split_count: *split_count,
+                    latency_colors: *latency_colors,
+                    hidden_daemon: *hidden_daemon,
+                    monitor: *monitor,
+                    grid: *grid,
+                    echo: *echo,
+                    echo_only: *echo_only,
+                    environment: environment.to_owned(),
+                    command: command.to_owned(),
+                    username_from_whoami: *username_from_whoami,
+                    port: *port,
+                    reconnect: *reconnect,
+                    layout: args.layout.clone(),
+                },
             )
             .await;
         }
-        None => {
+        Some(Commands::List { list_command }) => match list_command {
+            ListCommands::Clusters => {
+                let known_hosts = get_configured_hosts(Path::new(&config.client.ssh_config_path));
+                for cluster in &clusters {
+                    let hosts = resolve_cluster_tags(vec![cluster.name.as_str()], &clusters);
+                    let unresolved =
+                        get_unresolved_cluster_references(&hosts, &clusters, &known_hosts);
+                    if unresolved.is_empty() {
+                        println!("{}: {}", cluster.name, hosts.join(" "));
+                    } else {
+                        println!(
+                            "{}: {} # unresolved: {}",
+                            cluster.name,
+                            hosts.join(" "),
+                            unresolved.join(" ")
+                        );
+                    }
+                }
+            }
+            ListCommands::Hosts { cluster } => {
+                if !clusters
+                    .iter()
+                    .any(|configured_cluster| return configured_cluster.name == *cluster)
+                {
+                    eprintln!("Unknown cluster `{cluster}`");
+                    std::process::exit(1);
+                }
+                for host in resolve_cluster_tags(vec![cluster.as_str()], &clusters) {
+                    println!("{host}");
+                }
+            }
+        },
+        Some(Commands::CheckConfig) => {
+            let problems = validate_config(&config);
+            if problems.is_empty() {
+                println!("Configuration `{config_path}` is valid");
+            } else {
+                eprintln!("Configuration `{config_path}` has problems:");
+                for problem in &problems {
+                    eprintln!("  {problem}");
+                }
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::SaveLayout { name }) => {
+            if let Some(layout) = layout.as_ref() {
+                layout.apply(&mut config.daemon);
+            }
+            // Keep the swaps and active sets of the layout the new one is based on.
+            let (swaps, active_sets) = layout
+                .map(|layout| return (layout.swaps, layout.active_sets))
+                .unwrap_or_default();
+            let layout = Layout {
+                swaps,
+                active_sets,
+                ..Layout::new(&config.daemon, grid, monitor)
+            };
+            match save_layout(name, &layout) {
+                Ok(()) => println!("Saved layout `{name}` to `{}`", get_layout_path(name)),
+                Err(err) => {
+                    eprintln!("Failed to save layout `{name}`: {err}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        None | Some(Commands::Pick) if args.dry_run => {
+            if let Some(layout) = layout.as_ref() {
+                layout.apply(&mut config.daemon);
+            }
+            let mut hosts = resolve_cluster_tags(
+                given_hosts.iter().map(|host| return &**host).collect(),
+                &clusters,
+            );
+            if args.unique {
+                hosts = deduplicate_hosts(hosts);
+            }
+            if args.sort {
+                hosts.sort_by(|a, b| return natural_cmp(a, b));
+            }
+            if let Some(layout) = layout.as_ref() {
+                layout.apply_swaps(&mut hosts);
+            }
+            let username = args.username.as_deref().unwrap_or(DEFAULT_SSH_USERNAME_KEY);
+            let split_count = args.split.clamp(1, hosts.len().max(1));
+            for (split_index, hosts) in split_hosts(hosts, split_count).into_iter().enumerate() {
+                let client_windows = plan_client_windows(
+                    hosts.len(),
+                    &config.daemon,
+                    args.hidden_daemon,
+                    monitor,
+                    grid,
+                    split_index,
+                    split_count,
+                );
+                for (host, (x, y, width, height)) in hosts.iter().zip(client_windows) {
+                    println!(
+                        "{} {} {} {} {}",
+                        describe_connection(
+                            username,
+                            host,
+                            &config.client,
+                            &clusters,
+                            args.username_from_whoami,
+                            args.port
+                        ),
+                        x,
+                        y,
+                        width,
+                        height
+                    );
+                }
+            }
+        }
+        None | Some(Commands::Pick) => {
+            let previous_config = std::fs::read_to_string(&config_path).unwrap_or_default();
             confy::store_path(&config_path, &config).unwrap();
+            if !config_existed {
+                print_config_created_notice(&config_path, &config);
+            } else {
+                let current_config = std::fs::read_to_string(&config_path).unwrap_or_default();
+                print_config_migrated_notice(
+                    &config_path,
+                    &get_added_config_keys(&previous_config, &current_config),
+                );
+            }
 
-            let mut daemon_args: Vec<&str> = Vec::new();
-            if args.debug {
-                daemon_args.push("-d");
-            }
-            daemon_args.push("daemon");
-            if let Some(username) = args.username.as_ref() {
-                daemon_args.push("-u");
-                daemon_args.push(username);
-            }
-            daemon_args.extend(resolve_cluster_tags(
-                args.hosts.iter().map(|host| return &**host).collect(),
-                &config.clusters,
-            ));
-            spawn_console_process(&format!("{PKG_NAME}.exe"), daemon_args);
+            let mut hosts = resolve_cluster_tags(
+                given_hosts.iter().map(|host| return &**host).collect(),
+                &clusters,
+            );
+            if args.unique {
+                hosts = deduplicate_hosts(hosts);
+            }
+            if args.sort {
+                hosts.sort_by(|a, b| return natural_cmp(a, b));
+            }
+            if let Some(layout) = layout.as_ref() {
+                layout.apply_swaps(&mut hosts);
+            }
+            let split_count = args.split.clamp(1, hosts.len().max(1));
+            let split_count_arg = split_count.to_string();
+            let ansible_inventory_arg = ansible_inventory
+                .as_ref()
+                .map(|path| return path.display().to_string());
+            let monitor_arg = monitor.map(|monitor| return monitor.to_string());
+            let grid_arg = grid.map(|(columns, rows)| return format!("{columns}x{rows}"));
+            let port_arg = args.port.map(|port| return port.to_string());
+            for (split_index, hosts) in split_hosts(hosts, split_count).into_iter().enumerate() {
+                let split_index_arg = split_index.to_string();
+                let mut daemon_args: Vec<&str> = Vec::new();
+                if args.debug {
+                    daemon_args.push("-d");
+                    daemon_args.extend(["--log-format", args.log_format.as_arg()]);
+                }
+                if let Some(profile) = args.profile.as_ref() {
+                    daemon_args.push("--profile");
+                    daemon_args.push(profile);
+                }
+                if let Some(layout) = args.layout.as_ref() {
+                    daemon_args.push("--layout");
+                    daemon_args.push(layout);
+                }
+                if let Some(ansible_inventory_arg) = ansible_inventory_arg.as_ref() {
+                    daemon_args.push("--ansible-inventory");
+                    daemon_args.push(ansible_inventory_arg);
+                }
+                daemon_args.push("daemon");
+                if let Some(username) = args.username.as_ref() {
+                    daemon_args.push("-u");
+                    daemon_args.push(username);
+                }
+                daemon_args.extend(["--split-index", split_index_arg.as_str()]);
+                daemon_args.extend(["--split-count", split_count_arg.as_str()]);
+                if args.latency_colors {
+                    daemon_args.push("--latency-colors");
+                }
+                if args.hidden_daemon {
+                    daemon_args.push("--hidden-daemon");
+                }
+                if let Some(monitor_arg) = monitor_arg.as_ref() {
+                    daemon_args.push("--monitor");
+                    daemon_args.push(monitor_arg);
+                }
+                if let Some(grid_arg) = grid_arg.as_ref() {
+                    daemon_args.push("--grid");
+                    daemon_args.push(grid_arg);
+                }
+                if args.echo {
+                    daemon_args.push("--echo");
+                }
+                if args.echo_only {
+                    daemon_args.push("--echo-only");
+                }
+                for environment_variable in &args.environment {
+                    daemon_args.extend(["--env", environment_variable.as_str()]);
+                }
+                if let Some(remote_command) = args.remote_command.as_ref() {
+                    daemon_args.extend(["--command", remote_command.as_str()]);
+                }
+                if args.username_from_whoami {
+                    daemon_args.push("--username-from-whoami");
+                }
+                if let Some(port_arg) = port_arg.as_ref() {
+                    daemon_args.extend(["--port", port_arg.as_str()]);
+                }
+                if args.reconnect {
+                    daemon_args.push("--reconnect");
+                }
+                daemon_args.extend(hosts);
+                spawn_console_process(
+                    &format!("{PKG_NAME}.exe"),
+                    daemon_args,
+                    NORMAL_PRIORITY_CLASS,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_hosts_file_ignores_comments_and_blank_lines() {
+        let path = std::env::temp_dir().join(format!("csshw-test-hosts-{}", std::process::id()));
+        std::fs::write(
+            &path,
+            concat!(
+                "# web servers\nweb1\n  web2:2222  \n\n\t\n",
+                "  # database servers\nalice@db1\r\ndb-cluster\n"
+            ),
+        )
+        .unwrap();
+        let hosts = read_hosts_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            hosts.unwrap(),
+            vec!["web1", "web2:2222", "alice@db1", "db-cluster"]
+        );
+    }
+
+    #[test]
+    fn test_read_hosts_file_fails_for_missing_file() {
+        let path = std::env::temp_dir().join("csshw-test-missing-hosts");
+        assert_eq!(
+            read_hosts_file(&path).unwrap_err().kind(),
+            std::io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn test_split_hosts_into_consecutive_groups() {
+        let hosts = vec!["web1", "web2", "web3", "web4", "web5"];
+        assert_eq!(
+            split_hosts(hosts.clone(), 2),
+            vec![vec!["web1", "web2"], vec!["web3", "web4", "web5"]]
+        );
+        assert_eq!(
+            split_hosts(hosts.clone(), 3),
+            vec![vec!["web1"], vec!["web2", "web3"], vec!["web4", "web5"]]
+        );
+        assert_eq!(split_hosts(hosts.clone(), 1), vec![hosts]);
+    }
+
+    #[test]
+    fn test_split_hosts_into_more_groups_than_hosts() {
+        assert_eq!(
+            split_hosts(vec!["web1", "web2"], 3),
+            vec![vec![], vec!["web1"], vec!["web2"]]
+        );
+    }
+
+    #[test]
+    fn test_parse_grid() {
+        assert_eq!(parse_grid("4x3"), Ok((4, 3)));
+        assert_eq!(parse_grid("1x1"), Ok((1, 1)));
+    }
+
+    #[test]
+    fn test_parse_grid_rejects_invalid_grids() {
+        for grid in ["", "4", "4x", "x3", "4X3", "4x3x2", "-4x3", "0x3", "4x0"] {
+            assert_eq!(
+                parse_grid(grid),
+                Err(format!(
+                    "Invalid grid `{grid}`, expected `<COLUMNS>x<ROWS>`, e.g. `4x3`"
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_environment_variable() {
+        assert_eq!(
+            parse_environment_variable("SSH_AUTH_SOCK=/tmp/agent.sock"),
+            Ok("SSH_AUTH_SOCK=/tmp/agent.sock".to_owned())
+        );
+        // The value may be empty or contain `=` itself.
+        assert_eq!(
+            parse_environment_variable("DEBUG="),
+            Ok("DEBUG=".to_owned())
+        );
+        assert_eq!(
+            parse_environment_variable("OPTIONS=a=b"),
+            Ok("OPTIONS=a=b".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_parse_environment_variable_rejects_invalid_specifications() {
+        for variable in ["DEBUG", "=1", ""] {
+            assert_eq!(
+                parse_environment_variable(variable),
+                Err(format!(
+                    "Invalid environment variable `{variable}`, expected `<KEY>=<VALUE>`"
+                ))
+            );
         }
     }
 }
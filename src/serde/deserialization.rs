@@ -1,4 +1,9 @@
-use windows::Win32::System::Console::{INPUT_RECORD_0, KEY_EVENT_RECORD, KEY_EVENT_RECORD_0};
+use windows::Win32::System::Console::{
+    COORD, INPUT_RECORD, INPUT_RECORD_0, KEY_EVENT, KEY_EVENT_RECORD, KEY_EVENT_RECORD_0,
+    MOUSE_EVENT, MOUSE_EVENT_RECORD,
+};
+
+use super::MOUSE_EVENT_TAG;
 
 pub trait Deserialize {
     fn deserialize(slice: &mut [u8]) -> Self;
@@ -32,3 +37,34 @@ impl Deserialize for INPUT_RECORD_0 {
         };
     }
 }
+
+impl Deserialize for MOUSE_EVENT_RECORD {
+    fn deserialize(slice: &mut [u8]) -> MOUSE_EVENT_RECORD {
+        return MOUSE_EVENT_RECORD {
+            dwMousePosition: COORD {
+                X: i16::from_le_bytes(slice[1..3].try_into().unwrap()),
+                Y: i16::from_le_bytes(slice[3..5].try_into().unwrap()),
+            },
+            dwButtonState: u32::from_le_bytes(slice[5..9].try_into().unwrap()),
+            dwControlKeyState: u32::from_le_bytes(slice[9..13].try_into().unwrap()),
+            dwEventFlags: u32::from_le_bytes(slice[13..17].try_into().unwrap()),
+        };
+    }
+}
+
+impl Deserialize for INPUT_RECORD {
+    fn deserialize(slice: &mut [u8]) -> INPUT_RECORD {
+        if slice[0] == MOUSE_EVENT_TAG {
+            return INPUT_RECORD {
+                EventType: MOUSE_EVENT as u16,
+                Event: INPUT_RECORD_0 {
+                    MouseEvent: MOUSE_EVENT_RECORD::deserialize(slice),
+                },
+            };
+        }
+        return INPUT_RECORD {
+            EventType: KEY_EVENT as u16,
+            Event: INPUT_RECORD_0::deserialize(slice),
+        };
+    }
+}
@@ -2,3 +2,227 @@ pub mod deserialization;
 pub mod serialization;
 
 pub const SERIALIZED_INPUT_RECORD_0_LENGTH: usize = 18;
+/// First byte of serialized mouse events, serialized key events start with
+/// the MessagePack encoded `bKeyDown` boolean instead.
+pub const MOUSE_EVENT_TAG: u8 = 0x01;
+/// Packet the daemon sends whenever no input was sent for the keep-alive interval.
+pub const KEEP_ALIVE_PACKET: [u8; SERIALIZED_INPUT_RECORD_0_LENGTH] =
+    [u8::MAX; SERIALIZED_INPUT_RECORD_0_LENGTH];
+/// Packet instructing the clients to send their configured break sequence.
+pub const BREAK_PACKET: [u8; SERIALIZED_INPUT_RECORD_0_LENGTH] =
+    [u8::MAX - 1; SERIALIZED_INPUT_RECORD_0_LENGTH];
+/// Packet instructing the clients to terminate their SSH process and exit.
+pub const SHUTDOWN_PACKET: [u8; SERIALIZED_INPUT_RECORD_0_LENGTH] =
+    [u8::MAX - 2; SERIALIZED_INPUT_RECORD_0_LENGTH];
+/// Packet telling a client that it no longer receives the broadcasted input,
+/// e.g. because it was disabled via control mode.
+pub const DISABLED_PACKET: [u8; SERIALIZED_INPUT_RECORD_0_LENGTH] =
+    [u8::MAX - 3; SERIALIZED_INPUT_RECORD_0_LENGTH];
+/// Packet telling a client that it receives the broadcasted input again.
+pub const ENABLED_PACKET: [u8; SERIALIZED_INPUT_RECORD_0_LENGTH] =
+    [u8::MAX - 4; SERIALIZED_INPUT_RECORD_0_LENGTH];
+
+/// Byte the clients periodically send to the daemon to signal they are still responsive.
+pub const HEARTBEAT_TAG: u8 = 0x00;
+/// First byte of packets carrying output of a client's SSH process, followed by
+/// the little-endian `u16` length of the output and the output itself.
+pub const OUTPUT_TAG: u8 = 0x01;
+/// Maximum number of bytes of output carried by a single output packet.
+pub const MAX_OUTPUT_PACKET_LENGTH: usize = 1024;
+
+/// Packet sent from a client to the daemon.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ClientPacket {
+    Heartbeat,
+    Output(Vec<u8>),
+}
+
+/// Serializes at most [`MAX_OUTPUT_PACKET_LENGTH`] bytes of `output` of a client's
+/// SSH process into an output packet.
+pub fn serialize_output_packet(output: &[u8]) -> Vec<u8> {
+    assert!(output.len() <= MAX_OUTPUT_PACKET_LENGTH);
+    let mut packet = Vec::with_capacity(output.len() + 3);
+    packet.push(OUTPUT_TAG);
+    packet.extend((output.len() as u16).to_le_bytes());
+    packet.extend(output);
+    return packet;
+}
+
+/// Deserializes all complete packets at the start of `buffer` and removes them from it.
+///
+/// An incomplete packet at the end remains in the `buffer` until the rest of it was received,
+/// unknown bytes are treated as heartbeats.
+pub fn deserialize_client_packets(buffer: &mut Vec<u8>) -> Vec<ClientPacket> {
+    let mut packets = Vec::new();
+    let mut offset = 0;
+    while offset < buffer.len() {
+        if buffer[offset] != OUTPUT_TAG {
+            packets.push(ClientPacket::Heartbeat);
+            offset += 1;
+            continue;
+        }
+        if buffer.len() < offset + 3 {
+            break;
+        }
+        let length = u16::from_le_bytes([buffer[offset + 1], buffer[offset + 2]]) as usize;
+        if buffer.len() < offset + 3 + length {
+            break;
+        }
+        packets.push(ClientPacket::Output(
+            buffer[offset + 3..offset + 3 + length].to_vec(),
+        ));
+        offset += 3 + length;
+    }
+    buffer.drain(..offset);
+    return packets;
+}
+
+#[cfg(test)]
+mod tests {
+    use windows::Win32::System::Console::{
+        COORD, INPUT_RECORD, INPUT_RECORD_0, KEY_EVENT, KEY_EVENT_RECORD, KEY_EVENT_RECORD_0,
+        LEFT_CTRL_PRESSED, MOUSE_EVENT, MOUSE_EVENT_RECORD, MOUSE_MOVED, MOUSE_WHEELED,
+    };
+
+    use super::deserialization::Deserialize;
+    use super::serialization::Serialize;
+    use super::*;
+
+    fn round_trip(input_record: INPUT_RECORD) -> INPUT_RECORD {
+        let mut buf = input_record.serialize();
+        let bytes = buf.as_mut_vec();
+        assert_eq!(bytes.len(), SERIALIZED_INPUT_RECORD_0_LENGTH);
+        return INPUT_RECORD::deserialize(bytes);
+    }
+
+    fn mouse_input_record(mouse_event_record: MOUSE_EVENT_RECORD) -> INPUT_RECORD {
+        return INPUT_RECORD {
+            EventType: MOUSE_EVENT as u16,
+            Event: INPUT_RECORD_0 {
+                MouseEvent: mouse_event_record,
+            },
+        };
+    }
+
+    #[test]
+    fn test_key_event_round_trip() {
+        let key_event_record = KEY_EVENT_RECORD {
+            bKeyDown: true.into(),
+            wRepeatCount: 1,
+            wVirtualKeyCode: 0x41,
+            wVirtualScanCode: 0x1E,
+            uChar: KEY_EVENT_RECORD_0 {
+                UnicodeChar: 'a' as u16,
+            },
+            dwControlKeyState: LEFT_CTRL_PRESSED,
+        };
+        let input_record = round_trip(INPUT_RECORD {
+            EventType: KEY_EVENT as u16,
+            Event: INPUT_RECORD_0 {
+                KeyEvent: key_event_record,
+            },
+        });
+
+        assert_eq!(input_record.EventType, KEY_EVENT as u16);
+        let deserialized = unsafe { input_record.Event.KeyEvent };
+        assert!(deserialized.bKeyDown.as_bool());
+        assert_eq!(deserialized.wRepeatCount, 1);
+        assert_eq!(deserialized.wVirtualKeyCode, 0x41);
+        assert_eq!(deserialized.wVirtualScanCode, 0x1E);
+        assert_eq!(unsafe { deserialized.uChar.UnicodeChar }, 'a' as u16);
+        assert_eq!(deserialized.dwControlKeyState, LEFT_CTRL_PRESSED);
+    }
+
+    #[test]
+    fn test_mouse_move_round_trip() {
+        let mouse_event_record = MOUSE_EVENT_RECORD {
+            dwMousePosition: COORD { X: 42, Y: -7 },
+            dwButtonState: 0,
+            dwControlKeyState: LEFT_CTRL_PRESSED,
+            dwEventFlags: MOUSE_MOVED,
+        };
+        let input_record = round_trip(mouse_input_record(mouse_event_record));
+
+        assert_eq!(input_record.EventType, MOUSE_EVENT as u16);
+        assert_eq!(unsafe { input_record.Event.MouseEvent }, mouse_event_record);
+    }
+
+    #[test]
+    fn test_mouse_wheel_round_trip() {
+        // The high word of the button state holds the signed wheel delta.
+        let mouse_event_record = MOUSE_EVENT_RECORD {
+            dwMousePosition: COORD { X: 0, Y: i16::MAX },
+            dwButtonState: ((-120i16 as u16) as u32) << 16,
+            dwControlKeyState: 0,
+            dwEventFlags: MOUSE_WHEELED,
+        };
+        let input_record = round_trip(mouse_input_record(mouse_event_record));
+
+        assert_eq!(input_record.EventType, MOUSE_EVENT as u16);
+        assert_eq!(unsafe { input_record.Event.MouseEvent }, mouse_event_record);
+    }
+
+    #[test]
+    fn test_sentinel_packets_are_distinct_from_input_records() {
+        let packets = [
+            KEEP_ALIVE_PACKET,
+            BREAK_PACKET,
+            SHUTDOWN_PACKET,
+            DISABLED_PACKET,
+            ENABLED_PACKET,
+        ];
+        for (i, packet) in packets.iter().enumerate() {
+            for other in &packets[i + 1..] {
+                assert_ne!(packet, other);
+            }
+            // Serialized key events start with a MessagePack boolean.
+            assert_ne!(packet[0], MOUSE_EVENT_TAG);
+            assert_ne!(packet[0], rmp::Marker::True.to_u8());
+            assert_ne!(packet[0], rmp::Marker::False.to_u8());
+        }
+    }
+
+    #[test]
+    fn test_client_packets_round_trip() {
+        let mut buffer = vec![HEARTBEAT_TAG];
+        buffer.extend(serialize_output_packet(b"uptime\r\n"));
+        buffer.push(HEARTBEAT_TAG);
+
+        assert_eq!(
+            deserialize_client_packets(&mut buffer),
+            vec![
+                ClientPacket::Heartbeat,
+                ClientPacket::Output(b"uptime\r\n".to_vec()),
+                ClientPacket::Heartbeat,
+            ]
+        );
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_output_packet_of_maximum_length_round_trip() {
+        let output = vec![OUTPUT_TAG; MAX_OUTPUT_PACKET_LENGTH];
+        let mut buffer = serialize_output_packet(&output);
+
+        assert_eq!(
+            deserialize_client_packets(&mut buffer),
+            vec![ClientPacket::Output(output)]
+        );
+    }
+
+    #[test]
+    fn test_incomplete_client_packet_remains_in_buffer() {
+        let packet = serialize_output_packet(b"load average");
+        let mut buffer = packet[..5].to_vec();
+
+        assert_eq!(deserialize_client_packets(&mut buffer), vec![]);
+        assert_eq!(buffer, packet[..5]);
+
+        buffer.extend(&packet[5..]);
+        assert_eq!(
+            deserialize_client_packets(&mut buffer),
+            vec![ClientPacket::Output(b"load average".to_vec())]
+        );
+        assert!(buffer.is_empty());
+    }
+}
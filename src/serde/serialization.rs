@@ -1,5 +1,10 @@
 use rmp::encode::ByteBuf;
-use windows::Win32::System::Console::{INPUT_RECORD_0, KEY_EVENT_RECORD, KEY_EVENT_RECORD_0};
+use windows::Win32::System::Console::{
+    INPUT_RECORD, INPUT_RECORD_0, KEY_EVENT_RECORD, KEY_EVENT_RECORD_0, MOUSE_EVENT,
+    MOUSE_EVENT_RECORD,
+};
+
+use super::{MOUSE_EVENT_TAG, SERIALIZED_INPUT_RECORD_0_LENGTH};
 
 pub trait Serialize {
     fn serialize(&self) -> ByteBuf;
@@ -34,3 +39,29 @@ impl Serialize for INPUT_RECORD_0 {
         return buf;
     }
 }
+
+impl Serialize for MOUSE_EVENT_RECORD {
+    fn serialize(&self) -> ByteBuf {
+        // MessagePack would exceed the fixed record length, so the fields
+        // are written as little endian bytes following the tag.
+        let mut buf = ByteBuf::new();
+        let bytes = buf.as_mut_vec();
+        bytes.push(MOUSE_EVENT_TAG);
+        bytes.extend(self.dwMousePosition.X.to_le_bytes());
+        bytes.extend(self.dwMousePosition.Y.to_le_bytes());
+        bytes.extend(self.dwButtonState.to_le_bytes());
+        bytes.extend(self.dwControlKeyState.to_le_bytes());
+        bytes.extend(self.dwEventFlags.to_le_bytes());
+        bytes.resize(SERIALIZED_INPUT_RECORD_0_LENGTH, 0);
+        return buf;
+    }
+}
+
+impl Serialize for INPUT_RECORD {
+    fn serialize(&self) -> ByteBuf {
+        if self.EventType == MOUSE_EVENT as u16 {
+            return unsafe { self.Event.MouseEvent }.serialize();
+        }
+        return self.Event.serialize();
+    }
+}
@@ -0,0 +1,92 @@
+use std::fs::read_to_string;
+use std::io;
+use std::path::Path;
+
+use log::warn;
+
+use crate::utils::config::Cluster;
+
+/// Group Ansible assigns hosts to which are listed before the first section.
+const UNGROUPED: &str = "ungrouped";
+
+/// Loads the groups of an Ansible INI inventory as clusters.
+///
+/// Each `[group]` becomes a cluster of its hosts, each `[group:children]`
+/// section adds the child groups to the cluster, so they are resolved as nested clusters.
+/// The host variables `ansible_host`, `ansible_user` and `ansible_port` are
+/// supported, all other variables and `[group:vars]` sections are ignored.
+pub fn load_ansible_inventory(path: &Path) -> io::Result<Vec<Cluster>> {
+    return Ok(parse_ansible_inventory(&read_to_string(path)?));
+}
+
+fn parse_ansible_inventory(inventory: &str) -> Vec<Cluster> {
+    let mut clusters: Vec<Cluster> = Vec::new();
+    let mut group = UNGROUPED.to_owned();
+    let mut section = "";
+    for line in inventory.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(header) = line
+            .strip_prefix('[')
+            .and_then(|header| return header.strip_suffix(']'))
+        {
+            (group, section) = match header.split_once(':') {
+                Some((name, "children")) => (name.to_owned(), "children"),
+                Some((name, "vars")) => (name.to_owned(), "vars"),
+                Some((name, kind)) => {
+                    warn!("Ignoring unknown inventory section `{}:{}`", name, kind);
+                    (name.to_owned(), "vars")
+                }
+                None => (header.to_owned(), ""),
+            };
+            get_or_insert_cluster(&mut clusters, &group);
+            continue;
+        }
+        let cluster = get_or_insert_cluster(&mut clusters, &group);
+        match section {
+            "vars" => {}
+            "children" => {
+                cluster.hosts.push(line.to_owned());
+            }
+            _ => {
+                let mut fields = line.split_whitespace();
+                let mut host = fields.next().unwrap_or_default().to_owned();
+                let mut ssh_options: Vec<String> = Vec::new();
+                for (key, value) in fields.filter_map(|field| return field.split_once('=')) {
+                    match key {
+                        "ansible_host" => host = value.to_owned(),
+                        "ansible_user" => ssh_options.extend(["-l".to_owned(), value.to_owned()]),
+                        "ansible_port" => ssh_options.extend(["-p".to_owned(), value.to_owned()]),
+                        _ => {}
+                    }
+                }
+                if !ssh_options.is_empty() {
+                    cluster.host_ssh_options.insert(host.clone(), ssh_options);
+                }
+                cluster.hosts.push(host);
+            }
+        }
+    }
+    // Ansible implicitly creates the `ungrouped` group, don't list it if unused.
+    clusters.retain(|cluster| return cluster.name != UNGROUPED || !cluster.hosts.is_empty());
+    return clusters;
+}
+
+fn get_or_insert_cluster<'a>(clusters: &'a mut Vec<Cluster>, name: &str) -> &'a mut Cluster {
+    let index = match clusters
+        .iter()
+        .position(|cluster| return cluster.name == name)
+    {
+        Some(index) => index,
+        None => {
+            clusters.push(Cluster {
+                name: name.to_owned(),
+                ..Default::default()
+            });
+            clusters.len() - 1
+        }
+    };
+    return &mut clusters[index];
+}
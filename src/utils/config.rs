@@ -1,11 +1,28 @@
+use log::warn;
 use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 use std::env;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+
+use crate::utils::constants::{CONFIG_PATH, PKG_NAME};
+use crate::utils::{matches_wildcard_pattern, parse_host_specification};
 use windows::Win32::System::Console::{
     BACKGROUND_INTENSITY, BACKGROUND_RED, FOREGROUND_BLUE, FOREGROUND_GREEN, FOREGROUND_INTENSITY,
-    FOREGROUND_RED,
+    FOREGROUND_RED, KEY_EVENT_RECORD, LEFT_ALT_PRESSED, LEFT_CTRL_PRESSED, RIGHT_ALT_PRESSED,
+    RIGHT_CTRL_PRESSED, SHIFT_PRESSED,
+};
+use windows::Win32::System::Threading::{
+    ABOVE_NORMAL_PRIORITY_CLASS, BELOW_NORMAL_PRIORITY_CLASS, HIGH_PRIORITY_CLASS,
+    IDLE_PRIORITY_CLASS, NORMAL_PRIORITY_CLASS, PROCESS_CREATION_FLAGS,
 };
+use windows::Win32::UI::Input::KeyboardAndMouse::{VK_ESCAPE, VK_LWIN, VK_RWIN, VK_TAB};
 
 const DEFAULT_USERNAME_HOST_PLACEHOLDER: &str = "{{USERNAME_AT_HOST}}";
+const DEFAULT_TITLE_TEMPLATE: &str = "{{PKG}} - {{USER}}@{{HOST}}";
+const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
+/// Maximum number of hosts a `cidr` range expands to unless configured otherwise.
+const DEFAULT_MAX_CIDR_HOSTS: usize = 256;
 
 #[derive(Serialize, Deserialize, Default)]
 pub struct Config {
@@ -44,7 +61,401 @@ impl From<ConfigOpt> for Config {
 #[derive(Serialize, Deserialize, Default, Clone, Debug)]
 pub struct Cluster {
     pub name: String,
+    #[serde(default)]
     pub hosts: Vec<String>,
+    /// IPv4 or IPv6 network whose addresses are added to the `hosts`.
+    /// IPv4 networks exclude the network and broadcast address (except for `/31` and `/32`).
+    /// e.g. `'10.0.1.0/28'`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cidr: Option<String>,
+    /// Maximum number of hosts the `cidr` expands to, defaults to 256.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_hosts: Option<usize>,
+    /// Additional arguments passed to the program for all hosts of this cluster,
+    /// taking precedence over the `client.arguments`.
+    /// e.g. `['-o', 'StrictHostKeyChecking=no']`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ssh_options: Vec<String>,
+    /// SSH identity file used for all hosts of this cluster,
+    /// environment variables (e.g. `%USERPROFILE%`) are expanded.
+    /// e.g. `'%USERPROFILE%\.ssh\id_ed25519_dev'`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity_file: Option<String>,
+    /// Per host overrides of the `identity_file`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub identity_files: BTreeMap<String, String>,
+    /// Per host additional arguments passed to the program, taking precedence
+    /// over the `ssh_options`.
+    /// e.g. `{ 'web1' = ['-p', '2222'] }`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub host_ssh_options: BTreeMap<String, Vec<String>>,
+}
+
+/// Adds the addresses of each cluster's `cidr` to its `hosts`.
+///
+/// Invalid networks are ignored and networks larger than the
+/// cluster's `max_hosts` are truncated, both with a warning.
+pub fn expand_cidr_clusters(clusters: &mut [Cluster]) {
+    for cluster in clusters {
+        let cidr = match cluster.cidr.as_ref() {
+            Some(cidr) => cidr,
+            None => continue,
+        };
+        let max_hosts = cluster.max_hosts.unwrap_or(DEFAULT_MAX_CIDR_HOSTS);
+        match expand_cidr(cidr, max_hosts) {
+            Some((addresses, total)) => {
+                if total > addresses.len() as u128 {
+                    warn!(
+                        "Network `{}` of cluster `{}` contains {} hosts, only using the first {}",
+                        cidr,
+                        cluster.name,
+                        total,
+                        addresses.len()
+                    );
+                }
+                cluster.hosts.extend(addresses);
+            }
+            None => {
+                warn!(
+                    "Ignoring invalid network `{}` of cluster `{}`",
+                    cidr, cluster.name
+                );
+            }
+        }
+    }
+}
+
+/// Returns up to `max_hosts` host addresses of the given network and the
+/// total number of host addresses it contains, or `None` if it is invalid.
+fn expand_cidr(cidr: &str, max_hosts: usize) -> Option<(Vec<String>, u128)> {
+    let (address, prefix_length) = cidr.split_once('/')?;
+    let address: IpAddr = address.parse().ok()?;
+    let prefix_length: u32 = prefix_length.parse().ok()?;
+    let address_bits: u32 = if address.is_ipv4() { 32 } else { 128 };
+    if prefix_length > address_bits {
+        return None;
+    }
+    let host_bits = address_bits - prefix_length;
+    let network = match address {
+        IpAddr::V4(address) => u32::from(address) as u128,
+        IpAddr::V6(address) => u128::from(address),
+    }
+    .checked_shr(host_bits)
+    .unwrap_or(0)
+    .checked_shl(host_bits)
+    .unwrap_or(0);
+    let size = 1_u128.checked_shl(host_bits).unwrap_or(u128::MAX);
+    // Skip the network and broadcast address of IPv4 networks with room for hosts.
+    let (first, total) = if address.is_ipv4() && host_bits > 1 {
+        (network + 1, size - 2)
+    } else {
+        (network, size)
+    };
+    let addresses = (0..total.min(max_hosts as u128))
+        .map(|offset| {
+            return match address {
+                IpAddr::V4(_) => Ipv4Addr::from((first + offset) as u32).to_string(),
+                IpAddr::V6(_) => Ipv6Addr::from(first + offset).to_string(),
+            };
+        })
+        .collect();
+    return Some((addresses, total));
+}
+
+/// Returns the path of the configuration file of the given profile,
+/// or of the default configuration file if no profile is given.
+pub fn get_config_path(profile: Option<&str>) -> String {
+    return match profile {
+        Some(profile) => format!("{}-config.{}.toml", PKG_NAME, profile),
+        None => CONFIG_PATH.to_string(),
+    };
+}
+
+/// Returns the path of the file the window layout with the given name is stored in.
+pub fn get_layout_path(name: &str) -> String {
+    return format!("{}-layout.{}.toml", PKG_NAME, name);
+}
+
+/// Saves `layout` under the given name, replacing an existing layout of that name.
+pub fn save_layout(name: &str, layout: &Layout) -> Result<(), confy::ConfyError> {
+    return save_layout_to_path(Path::new(&get_layout_path(name)), layout);
+}
+
+fn save_layout_to_path(layout_path: &Path, layout: &Layout) -> Result<(), confy::ConfyError> {
+    return confy::store_path(layout_path, layout);
+}
+
+/// Loads the window layout with the given name, which must have been saved before.
+pub fn load_layout(name: &str) -> Result<Layout, String> {
+    return load_layout_from_path(Path::new(&get_layout_path(name)), name);
+}
+
+fn load_layout_from_path(layout_path: &Path, name: &str) -> Result<Layout, String> {
+    if !layout_path.exists() {
+        return Err(format!(
+            "Layout `{name}` does not exist, save it via `save-layout {name}` first"
+        ));
+    }
+    return confy::load_path(layout_path)
+        .map_err(|err| return format!("Failed to load layout `{name}`: {err}"));
+}
+
+/// Returns the (dotted) keys of the `current` configuration file content
+/// that are missing in the `previous` one, e.g. `daemon.height`.
+///
+/// Used to tell which settings were added with their defaults when an
+/// existing configuration file is migrated to the current format.
+pub fn get_added_config_keys(previous: &str, current: &str) -> Vec<String> {
+    let previous_keys = get_config_keys(previous);
+    return get_config_keys(current)
+        .into_iter()
+        .filter(|key| return !previous_keys.contains(key))
+        .collect();
+}
+
+fn get_config_keys(content: &str) -> BTreeSet<String> {
+    let mut keys = BTreeSet::new();
+    let mut table = String::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if let Some(header) = line
+            .strip_prefix('[')
+            .and_then(|header| return header.strip_suffix(']'))
+        {
+            table = header
+                .trim_matches(|c| return c == '[' || c == ']')
+                .to_owned();
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+        let key = match line.split_once('=') {
+            Some((key, _)) => key.trim(),
+            None => continue,
+        };
+        // Skip continuation lines of multi-line arrays, e.g. `'-o', 'ConnectTimeout=10',`.
+        if key.is_empty()
+            || !key
+                .chars()
+                .all(|c| return c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            continue;
+        }
+        if table.is_empty() {
+            keys.insert(key.to_owned());
+        } else {
+            keys.insert(format!("{table}.{key}"));
+        }
+    }
+    return keys;
+}
+
+/// Returns a description of each problem found in the `config`, e.g. arguments
+/// missing the `username_host_placeholder`, which would be passed to SSH as is.
+pub fn validate_config(config: &Config) -> Vec<String> {
+    let mut problems: Vec<String> = Vec::new();
+    let client = &config.client;
+    if !Path::new(&client.ssh_config_path).exists() {
+        problems.push(format!(
+            "client.ssh_config_path: `{}` does not exist",
+            client.ssh_config_path
+        ));
+    }
+    if client.username_host_placeholder.is_empty() {
+        problems.push("client.username_host_placeholder: must not be empty".to_owned());
+    } else {
+        let contains_placeholder = |arguments: &[String]| {
+            return arguments
+                .iter()
+                .any(|argument| return argument.contains(&client.username_host_placeholder));
+        };
+        if !contains_placeholder(&client.arguments) {
+            problems.push(format!(
+                "client.arguments: missing the username_host_placeholder `{}`",
+                client.username_host_placeholder
+            ));
+        }
+        for program_override in &client.program_overrides {
+            if !contains_placeholder(&program_override.arguments) {
+                problems.push(format!(
+                    "client.program_overrides: arguments for `{}` are missing `{}`",
+                    program_override.host, client.username_host_placeholder
+                ));
+            }
+        }
+    }
+    let daemon = &config.daemon;
+    if daemon.height <= 0 {
+        problems.push(format!(
+            "daemon.height: must be positive, is {}",
+            daemon.height
+        ));
+    }
+    // Only the lower byte holds the foreground (bits 0-3) and background (bits 4-7) color.
+    if daemon.console_color > 0xFF {
+        problems.push(format!(
+            "daemon.console_color: must be at most 255, is {}",
+            daemon.console_color
+        ));
+    } else if daemon.console_color & 0x0F == daemon.console_color >> 4 {
+        problems.push(format!(
+            "daemon.console_color: foreground and background color of {} are the same",
+            daemon.console_color
+        ));
+    }
+    return problems;
+}
+
+/// Returns the SSH options of all clusters containing `host`,
+/// either directly or through nested clusters.
+///
+/// The host specific options of a cluster precede its general options.
+pub fn get_cluster_ssh_options<'a>(host: &str, clusters: &'a Vec<Cluster>) -> Vec<&'a str> {
+    let mut ssh_options: Vec<&str> = Vec::new();
+    for cluster in clusters {
+        if resolve_cluster_tags(vec![cluster.name.as_str()], clusters)
+            .iter()
+            .any(|cluster_host| return *cluster_host == host)
+        {
+            if let Some(host_ssh_options) = cluster.host_ssh_options.get(host) {
+                ssh_options.extend(host_ssh_options.iter().map(|option| return option.as_str()));
+            }
+            ssh_options.extend(
+                cluster
+                    .ssh_options
+                    .iter()
+                    .map(|option| return option.as_str()),
+            );
+        }
+    }
+    return ssh_options;
+}
+
+/// Returns the SSH identity file configured for `host`.
+///
+/// A per host override takes precedence over the `identity_file` of a cluster,
+/// otherwise the first cluster containing `host` (directly or through nested clusters)
+/// that configures an identity file is used.
+pub fn get_cluster_identity_file<'a>(host: &str, clusters: &'a Vec<Cluster>) -> Option<&'a str> {
+    let containing_clusters: Vec<&Cluster> = clusters
+        .iter()
+        .filter(|cluster| {
+            return resolve_cluster_tags(vec![cluster.name.as_str()], clusters)
+                .iter()
+                .any(|cluster_host| return *cluster_host == host);
+        })
+        .collect();
+    return containing_clusters
+        .iter()
+        .find_map(|cluster| return cluster.identity_files.get(host))
+        .or_else(|| {
+            return containing_clusters
+                .iter()
+                .find_map(|cluster| return cluster.identity_file.as_ref());
+        })
+        .map(|identity_file| return identity_file.as_str());
+}
+
+/// Replaces all cluster tags in `hosts` by the hosts of the respective cluster.
+///
+/// Nested clusters are resolved recursively. A cluster tag that is already
+/// being resolved (i.e. a cycle in the cluster definitions) is kept as host.
+/// Entries prefixed with `!` (e.g. `!db2` or `!web*`) are no hosts but remove all
+/// matching hosts once the other entries are fully resolved.
+pub fn resolve_cluster_tags<'a>(hosts: Vec<&'a str>, clusters: &'a Vec<Cluster>) -> Vec<&'a str> {
+    return resolve_cluster_tags_on_path(hosts, clusters, &mut Vec::new());
+}
+
+/// Resolves the cluster tags in `hosts`, `path` holds the cluster tags
+/// currently being resolved.
+fn resolve_cluster_tags_on_path<'a>(
+    hosts: Vec<&'a str>,
+    clusters: &'a Vec<Cluster>,
+    path: &mut Vec<&'a str>,
+) -> Vec<&'a str> {
+    let mut resolved_hosts: Vec<&str> = Vec::new();
+    let mut exclusions: Vec<&str> = Vec::new();
+    let mut is_cluster_tag: bool;
+    for host in hosts {
+        if let Some(exclusion) = host.strip_prefix('!') {
+            exclusions.push(exclusion);
+            continue;
+        }
+        is_cluster_tag = false;
+        for cluster in clusters {
+            if host == cluster.name {
+                if path.contains(&host) {
+                    warn!(
+                        "Cluster `{}` contains itself via `{}`, treating it as host",
+                        host,
+                        path.join(" -> ")
+                    );
+                    break;
+                }
+                is_cluster_tag = true;
+                path.push(host);
+                resolved_hosts.extend(resolve_cluster_tags_on_path(
+                    cluster.hosts.iter().map(|host| return &**host).collect(),
+                    clusters,
+                    path,
+                ));
+                path.pop();
+                break;
+            }
+        }
+        if !is_cluster_tag {
+            resolved_hosts.push(host);
+        }
+    }
+    resolved_hosts.retain(|host| {
+        let (_, hostname, _) = parse_host_specification(host);
+        return !exclusions.iter().any(|exclusion| {
+            return matches_wildcard_pattern(exclusion, host)
+                || matches_wildcard_pattern(exclusion, hostname);
+        });
+    });
+    return resolved_hosts;
+}
+
+/// Returns the entries of the resolved `hosts` that presumably are cluster references
+/// which could not be resolved.
+///
+/// These are cluster tags kept as host due to a cycle and bare names, given without
+/// domain, user or port, that neither name a cluster nor a host of `known_hosts`
+/// (e.g. a misspelled cluster tag).
+pub fn get_unresolved_cluster_references<'a>(
+    hosts: &[&'a str],
+    clusters: &[Cluster],
+    known_hosts: &[String],
+) -> Vec<&'a str> {
+    return hosts
+        .iter()
+        .filter(|host| {
+            if clusters.iter().any(|cluster| return cluster.name == **host) {
+                return true;
+            }
+            let (username, hostname, port) = parse_host_specification(host);
+            return username.is_none()
+                && port.is_none()
+                && !hostname.contains('.')
+                && !hostname.eq_ignore_ascii_case("localhost")
+                && hostname.parse::<IpAddr>().is_err()
+                && !known_hosts
+                    .iter()
+                    .any(|known_host| return known_host == hostname);
+        })
+        .copied()
+        .collect();
+}
+
+/// Removes duplicate hosts, keeping the first occurrence of each host in order.
+pub fn deduplicate_hosts(hosts: Vec<&str>) -> Vec<&str> {
+    let mut seen_hosts: BTreeSet<&str> = BTreeSet::new();
+    return hosts
+        .into_iter()
+        .filter(|host| return seen_hosts.insert(*host))
+        .collect();
 }
 
 #[derive(Serialize, Deserialize)]
@@ -62,6 +473,74 @@ pub struct ClientConfig {
     /// Placeholder string used to inject `<user>@<host>` into the list of arguments.
     /// e.g. `'{{USERNAME_AT_HOST}}'`
     pub username_host_placeholder: String,
+    /// Seconds to wait for the SSH connection to be established before giving up
+    /// if the SSH config defines no `ConnectTimeout` for the host, `0` disables it.
+    /// Passed to the program as `-o ConnectTimeout=<seconds>`.
+    /// e.g. `10`
+    pub connect_timeout_secs: Option<u64>,
+    /// Characters sent to the program when a break is requested via control mode.
+    /// Defaults to the OpenSSH escape sequence `~B`, preceded by a newline as
+    /// escape sequences are only recognized at the beginning of a line.
+    pub break_sequence: String,
+    /// Keep the SSH session alive for local use if the connection to the daemon is lost
+    /// (e.g. because it crashed), instead of exiting the client.
+    pub detach_on_daemon_loss: bool,
+    /// Number of times an SSH process that failed to connect (exit code `255`) is
+    /// relaunched with `--reconnect` before giving up, `0` disables reconnecting.
+    /// The attempts are counted anew once a connection stays up for a while.
+    pub reconnect_attempts: u32,
+    /// Maximum number of seconds to wait before reconnecting, the wait starts
+    /// at one second and doubles with each consecutive attempt.
+    pub reconnect_backoff_cap_secs: u64,
+    /// Size in points of the client console font, unset keeps the default font size.
+    /// e.g. `12`
+    pub font_size: Option<i16>,
+    /// Title of the client windows, `{{PKG}}`, `{{USER}}`, `{{HOST}}` and `{{INDEX}}`
+    /// (position of the client, starting at 1) are replaced accordingly.
+    /// e.g. `'{{INDEX}}: {{HOST}}'`
+    pub title_template: String,
+    /// Command typed into the SSH session right after it launched, `{{HOST}}` is
+    /// replaced by the host name, unset sends no command.
+    /// e.g. `'cd /var/log && tail -f app.log'`
+    pub initial_command: Option<String>,
+    /// Directory the output of each SSH session is additionally written to, as
+    /// `<host>_<timestamp>.log`, unset doesn't log the sessions.
+    /// e.g. `'C:\Users\me\csshw-logs'`
+    pub session_log_dir: Option<String>,
+    /// Steps executed after the SSH process launched, before the broadcasted input is
+    /// handed to it, e.g. to uniformly run `sudo -i` or `cd` on all hosts.
+    pub onboarding: Vec<OnboardingStep>,
+    /// Program and arguments used instead of the `program` and `arguments` for the
+    /// hosts matching the `host` pattern, the first matching override applies.
+    // Must remain the last field, as toml requires values to be emitted before tables.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub program_overrides: Vec<ProgramOverride>,
+}
+
+/// Program used to connect to the hosts matching a pattern.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProgramOverride {
+    /// Host pattern supporting the `*` and `?` wildcards.
+    /// e.g. `'router*'`
+    pub host: String,
+    /// e.g. `'plink'`
+    pub program: String,
+    /// Arguments of the program, the `username_host_placeholder` is replaced as well.
+    /// e.g. `['-ssh', '{{USERNAME_AT_HOST}}']`
+    pub arguments: Vec<String>,
+}
+
+/// Single step of the client onboarding sequence.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum OnboardingStep {
+    /// Wait until the line of the console cursor contains the given text (e.g. the prompt),
+    /// the text is matched literally, not as a pattern.
+    WaitForText(String),
+    /// Type the given text, e.g. `"sudo -i\r"`.
+    Send(String),
+    /// Sleep the given number of milliseconds.
+    SleepMs(u64),
 }
 
 impl Default for ClientConfig {
@@ -74,6 +553,17 @@ impl Default for ClientConfig {
                 DEFAULT_USERNAME_HOST_PLACEHOLDER.to_string(),
             ],
             username_host_placeholder: DEFAULT_USERNAME_HOST_PLACEHOLDER.to_string(),
+            connect_timeout_secs: Some(DEFAULT_CONNECT_TIMEOUT_SECS),
+            break_sequence: "\r~B".to_string(),
+            detach_on_daemon_loss: false,
+            reconnect_attempts: 5,
+            reconnect_backoff_cap_secs: 30,
+            font_size: None,
+            title_template: DEFAULT_TITLE_TEMPLATE.to_string(),
+            initial_command: None,
+            session_log_dir: None,
+            onboarding: Vec::new(),
+            program_overrides: Vec::new(),
         };
     }
 }
@@ -85,6 +575,17 @@ impl From<ClientConfig> for ClientConfigOpt {
             program: Some(val.program),
             arguments: Some(val.arguments),
             username_host_placeholder: Some(val.username_host_placeholder),
+            connect_timeout_secs: val.connect_timeout_secs,
+            break_sequence: Some(val.break_sequence),
+            detach_on_daemon_loss: Some(val.detach_on_daemon_loss),
+            reconnect_attempts: Some(val.reconnect_attempts),
+            reconnect_backoff_cap_secs: Some(val.reconnect_backoff_cap_secs),
+            font_size: val.font_size,
+            title_template: Some(val.title_template),
+            initial_command: val.initial_command,
+            session_log_dir: val.session_log_dir,
+            onboarding: Some(val.onboarding),
+            program_overrides: Some(val.program_overrides),
         };
     }
 }
@@ -95,6 +596,18 @@ pub struct ClientConfigOpt {
     pub program: Option<String>,
     pub arguments: Option<Vec<String>>,
     pub username_host_placeholder: Option<String>,
+    pub connect_timeout_secs: Option<u64>,
+    pub break_sequence: Option<String>,
+    pub detach_on_daemon_loss: Option<bool>,
+    pub reconnect_attempts: Option<u32>,
+    pub reconnect_backoff_cap_secs: Option<u64>,
+    pub font_size: Option<i16>,
+    pub title_template: Option<String>,
+    pub initial_command: Option<String>,
+    pub session_log_dir: Option<String>,
+    pub onboarding: Option<Vec<OnboardingStep>>,
+    // Must remain the last field, as toml requires values to be emitted before tables.
+    pub program_overrides: Option<Vec<ProgramOverride>>,
 }
 
 impl Default for ClientConfigOpt {
@@ -113,23 +626,323 @@ impl From<ClientConfigOpt> for ClientConfig {
             username_host_placeholder: val
                 .username_host_placeholder
                 .unwrap_or(_default.username_host_placeholder),
+            connect_timeout_secs: val.connect_timeout_secs.or(_default.connect_timeout_secs),
+            break_sequence: val.break_sequence.unwrap_or(_default.break_sequence),
+            detach_on_daemon_loss: val
+                .detach_on_daemon_loss
+                .unwrap_or(_default.detach_on_daemon_loss),
+            reconnect_attempts: val
+                .reconnect_attempts
+                .unwrap_or(_default.reconnect_attempts),
+            reconnect_backoff_cap_secs: val
+                .reconnect_backoff_cap_secs
+                .unwrap_or(_default.reconnect_backoff_cap_secs),
+            font_size: val.font_size.or(_default.font_size),
+            title_template: val.title_template.unwrap_or(_default.title_template),
+            initial_command: val.initial_command.or(_default.initial_command),
+            session_log_dir: val.session_log_dir.or(_default.session_log_dir),
+            onboarding: val.onboarding.unwrap_or(_default.onboarding),
+            program_overrides: val.program_overrides.unwrap_or(_default.program_overrides),
+        };
+    }
+}
+
+/// Placement of the daemon console relative to the client windows.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DaemonPosition {
+    /// Above the clients, spanning the whole workspace width.
+    Top,
+    /// Below the clients, spanning the whole workspace width.
+    Bottom,
+    /// In the top left cell of the client grid.
+    TopLeft,
+    /// In the bottom left cell of the client grid.
+    BottomLeft,
+    /// In the bottom right cell of the client grid.
+    BottomRight,
+}
+
+impl DaemonPosition {
+    /// Whether the daemon console takes a cell of the client grid
+    /// instead of a strip along the workspace edge.
+    pub fn is_grid_cell(&self) -> bool {
+        return matches!(
+            self,
+            DaemonPosition::TopLeft | DaemonPosition::BottomLeft | DaemonPosition::BottomRight
+        );
+    }
+}
+
+/// Named set of hosts which can be activated to only broadcast
+/// input to its members.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ActiveSet {
+    pub name: String,
+    pub hostnames: Vec<String>,
+}
+
+/// Named window layout: the daemon settings determining where the windows are
+/// placed, together with the `--grid` and `--monitor` choices and the swaps and
+/// active sets made while the layout was in use.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Layout {
+    pub height: i32,
+    #[serde(alias = "position")]
+    pub daemon_position: DaemonPosition,
+    pub aspect_ratio_adjustement: f64,
+    pub fill_direction: FillDirection,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grid: Option<(usize, usize)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monitor: Option<usize>,
+    /// Hostnames of the clients whose positions were swapped, in order of swapping.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub swaps: Vec<(String, String)>,
+    // Must remain the last field, as toml requires values to be emitted before tables.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub active_sets: Vec<ActiveSet>,
+}
+
+impl Layout {
+    /// Captures the layout relevant settings of the given daemon `config`.
+    pub fn new(
+        config: &DaemonConfig,
+        grid: Option<(usize, usize)>,
+        monitor: Option<usize>,
+    ) -> Self {
+        return Layout {
+            height: config.height,
+            daemon_position: config.daemon_position,
+            aspect_ratio_adjustement: config.aspect_ratio_adjustement,
+            fill_direction: config.fill_direction,
+            grid,
+            monitor,
+            swaps: Vec::new(),
+            active_sets: Vec::new(),
+        };
+    }
+
+    /// Overrides the layout relevant settings of the given daemon `config`.
+    pub fn apply(&self, config: &mut DaemonConfig) {
+        config.height = self.height;
+        config.daemon_position = self.daemon_position;
+        config.aspect_ratio_adjustement = self.aspect_ratio_adjustement;
+        config.fill_direction = self.fill_direction;
+    }
+
+    /// Swaps the positions of the given `hosts` as they were swapped while the layout
+    /// was in use, swaps of hosts that aren't given are skipped.
+    pub fn apply_swaps<T: AsRef<str>>(&self, hosts: &mut [T]) {
+        for (first, second) in &self.swaps {
+            let first_index = hosts.iter().position(|host| return host.as_ref() == first);
+            let second_index = hosts.iter().position(|host| return host.as_ref() == second);
+            if let (Some(first_index), Some(second_index)) = (first_index, second_index) {
+                hosts.swap(first_index, second_index);
+            }
+        }
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        return Layout::new(&DaemonConfig::default(), None, None);
+    }
+}
+
+/// Order in which the client windows fill the grid cells.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FillDirection {
+    /// Left to right, then top to bottom.
+    RowMajor,
+    /// Top to bottom, then left to right.
+    ColumnMajor,
+}
+
+/// Priority class of a process.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessPriority {
+    Idle,
+    BelowNormal,
+    Normal,
+    AboveNormal,
+    High,
+}
+
+impl ProcessPriority {
+    pub fn priority_class(&self) -> PROCESS_CREATION_FLAGS {
+        return match self {
+            ProcessPriority::Idle => IDLE_PRIORITY_CLASS,
+            ProcessPriority::BelowNormal => BELOW_NORMAL_PRIORITY_CLASS,
+            ProcessPriority::Normal => NORMAL_PRIORITY_CLASS,
+            ProcessPriority::AboveNormal => ABOVE_NORMAL_PRIORITY_CLASS,
+            ProcessPriority::High => HIGH_PRIORITY_CLASS,
+        };
+    }
+}
+
+/// Virtual key with the modifier keys that must be pressed along with it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct KeyCombination {
+    pub virtual_key_code: u16,
+    #[serde(default)]
+    pub ctrl: bool,
+    #[serde(default)]
+    pub alt: bool,
+    #[serde(default)]
+    pub shift: bool,
+}
+
+impl KeyCombination {
+    fn new(virtual_key_code: u16, ctrl: bool, alt: bool) -> Self {
+        return KeyCombination {
+            virtual_key_code,
+            ctrl,
+            alt,
+            shift: false,
         };
     }
+
+    /// Returns whether the key event belongs to this combination.
+    ///
+    /// Additional modifier keys being pressed don't matter.
+    pub fn matches(&self, key_event: &KEY_EVENT_RECORD) -> bool {
+        let control_key_state = key_event.dwControlKeyState;
+        return key_event.wVirtualKeyCode == self.virtual_key_code
+            && (!self.ctrl || control_key_state & (LEFT_CTRL_PRESSED | RIGHT_CTRL_PRESSED) != 0)
+            && (!self.alt || control_key_state & (LEFT_ALT_PRESSED | RIGHT_ALT_PRESSED) != 0)
+            && (!self.shift || control_key_state & SHIFT_PRESSED != 0);
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct DaemonConfig {
     pub height: i32,
+    /// Where the daemon console is placed, above or below the clients or in
+    /// one of the corner cells of the client grid. e.g. `'bottom'`
+    #[serde(alias = "position")]
+    pub daemon_position: DaemonPosition,
     pub aspect_ratio_adjustement: f64,
+    /// Whether consecutive clients are placed next to (`'row_major'`)
+    /// or below (`'column_major'`) each other.
+    pub fill_direction: FillDirection,
+    /// Minimum width in pixels of the client windows, `0` disables it.
+    /// Windows that don't fit onto the screen anymore extend beyond it.
+    /// e.g. `400`
+    pub min_console_width: i32,
+    /// Minimum height in pixels of the client windows, `0` disables it.
+    /// e.g. `200`
+    pub min_console_height: i32,
     pub console_color: u16,
+    /// Command (program followed by its arguments) run whenever a client window appeared.
+    /// The hostname and process id are provided via the `CSSHW_HOST` and `CSSHW_PID`
+    /// environment variables.
+    /// e.g. `['notify.exe', '--title', 'csshw']`
+    pub on_client_connected: Vec<String>,
+    /// Command (program followed by its arguments) run whenever a client exited.
+    /// The hostname, process id and exit code are provided via the `CSSHW_HOST`,
+    /// `CSSHW_PID` and `CSSHW_EXIT_CODE` environment variables.
+    pub on_client_exited: Vec<String>,
+    /// Command (program followed by its arguments) run whenever control mode is entered.
+    pub on_control_mode_entered: Vec<String>,
+    /// Command (program followed by its arguments) run whenever the title bar of a client
+    /// window is double-clicked, the hostname is appended as last argument and provided via
+    /// the `CSSHW_HOST` environment variable.
+    /// Requires polling the mouse, so it is only active if configured.
+    /// e.g. `['powershell.exe', '-File', 'open-web-console.ps1']`
+    pub on_client_double_clicked: Vec<String>,
+    /// Minimize the daemon console after some time without control mode activity.
+    /// Entering control mode restores it.
+    pub auto_minimize_daemon: bool,
+    /// Raise the member windows of an active set and flash their title bars when
+    /// switching to it, e.g. to point out the targets during presentations.
+    pub highlight_active_set: bool,
+    /// Border color (`0x00BBGGRR`) of the client window that was focused last,
+    /// unset (disabled) by default, not supported on Windows 10.
+    /// e.g. `0x00FFFF00`
+    pub focused_client_border_color: Option<u32>,
+    /// Seconds without broadcasted input after which the daemon locks and stops
+    /// broadcasting until the lock is confirmed with Enter, unset (disabled) by default.
+    /// e.g. `900`
+    pub idle_lock_secs: Option<u64>,
+    /// Move client windows back into their grid cell whenever they are resized,
+    /// so all clients keep the same size.
+    pub enforce_uniform_client_size: bool,
+    /// Broadcast mouse events (e.g. scrolling or clicks) in the daemon console
+    /// in addition to key events, disables the quick edit mode of the daemon console.
+    pub forward_mouse_events: bool,
+    /// Seconds without a heartbeat after which a client is considered unresponsive
+    /// and its window border is highlighted, unset (disabled) by default.
+    /// e.g. `30`
+    pub client_heartbeat_timeout_secs: Option<u64>,
+    /// Remove clients that sent no heartbeat for `client_heartbeat_timeout_secs`
+    /// and close their named pipe, instead of only highlighting them.
+    pub remove_unresponsive_clients: bool,
+    /// Milliseconds without any input sent to a client after which a keep-alive is sent,
+    /// clients consider the daemon gone after three intervals without any message.
+    pub keep_alive_interval_ms: u64,
+    /// Directory screenshots of the cluster are saved to, relative to the executable.
+    pub screenshot_directory: String,
+    /// Seconds the daemon waits before exiting once all clients exited,
+    /// any key-press during that time cancels the exit.
+    pub exit_grace_secs: u64,
+    /// Priority the client processes are created with, lowering it keeps the
+    /// daemon responsive with many clients.
+    /// One of `idle`, `below_normal`, `normal`, `above_normal` or `high`.
+    pub client_priority: ProcessPriority,
+    /// Milliseconds waited between launching consecutive clients, e.g. to not launch
+    /// all SSH processes at once, `0` launches all clients without delay.
+    pub launch_stagger_ms: u64,
+    /// Maximum number of characters of a line of text broadcasted via control mode that are
+    /// typed at once, `0` types the whole line at once.
+    pub broadcast_chunk_size: usize,
+    /// Milliseconds waited between typing consecutive chunks of a broadcasted line of text.
+    pub broadcast_chunk_pause_ms: u64,
+    /// Print the output of all clients in the daemon console, each line prefixed
+    /// with the hostname of the client, paused while in control mode.
+    pub monitor_pane: bool,
+    /// Maximum number of lines per client kept while the monitor pane is paused.
+    pub monitor_pane_lines_per_host: usize,
+    /// Key combinations which are never broadcasted, so they only act locally.
+    /// Defaults to the Windows keys and system combinations like Alt+Tab.
+    // Must remain the last field, as toml requires values to be emitted before tables.
+    pub swallowed_keys: Vec<KeyCombination>,
 }
 
 impl From<DaemonConfig> for DaemonConfigOpt {
     fn from(val: DaemonConfig) -> Self {
         return DaemonConfigOpt {
             height: Some(val.height),
+            daemon_position: Some(val.daemon_position),
             aspect_ratio_adjustement: Some(val.aspect_ratio_adjustement),
+            fill_direction: Some(val.fill_direction),
+            min_console_width: Some(val.min_console_width),
+            min_console_height: Some(val.min_console_height),
             console_color: Some(val.console_color),
+            on_client_connected: Some(val.on_client_connected),
+            on_client_exited: Some(val.on_client_exited),
+            on_control_mode_entered: Some(val.on_control_mode_entered),
+            on_client_double_clicked: Some(val.on_client_double_clicked),
+            auto_minimize_daemon: Some(val.auto_minimize_daemon),
+            highlight_active_set: Some(val.highlight_active_set),
+            focused_client_border_color: val.focused_client_border_color,
+            idle_lock_secs: val.idle_lock_secs,
+            enforce_uniform_client_size: Some(val.enforce_uniform_client_size),
+            forward_mouse_events: Some(val.forward_mouse_events),
+            client_heartbeat_timeout_secs: val.client_heartbeat_timeout_secs,
+            remove_unresponsive_clients: Some(val.remove_unresponsive_clients),
+            keep_alive_interval_ms: Some(val.keep_alive_interval_ms),
+            screenshot_directory: Some(val.screenshot_directory),
+            exit_grace_secs: Some(val.exit_grace_secs),
+            client_priority: Some(val.client_priority),
+            launch_stagger_ms: Some(val.launch_stagger_ms),
+            broadcast_chunk_size: Some(val.broadcast_chunk_size),
+            broadcast_chunk_pause_ms: Some(val.broadcast_chunk_pause_ms),
+            monitor_pane: Some(val.monitor_pane),
+            monitor_pane_lines_per_host: Some(val.monitor_pane_lines_per_host),
+            swallowed_keys: Some(val.swallowed_keys),
         };
     }
 }
@@ -138,7 +951,11 @@ impl Default for DaemonConfig {
     fn default() -> Self {
         return DaemonConfig {
             height: 200,
+            daemon_position: DaemonPosition::Bottom,
             aspect_ratio_adjustement: -1f64,
+            fill_direction: FillDirection::RowMajor,
+            min_console_width: 0,
+            min_console_height: 0,
             console_color: (FOREGROUND_INTENSITY
                 | FOREGROUND_RED
                 | FOREGROUND_GREEN
@@ -146,6 +963,34 @@ impl Default for DaemonConfig {
                 | BACKGROUND_INTENSITY
                 | BACKGROUND_RED)
                 .0,
+            on_client_connected: Vec::new(),
+            on_client_exited: Vec::new(),
+            on_control_mode_entered: Vec::new(),
+            on_client_double_clicked: Vec::new(),
+            auto_minimize_daemon: false,
+            highlight_active_set: false,
+            focused_client_border_color: None,
+            idle_lock_secs: None,
+            enforce_uniform_client_size: false,
+            forward_mouse_events: false,
+            client_heartbeat_timeout_secs: None,
+            remove_unresponsive_clients: false,
+            keep_alive_interval_ms: 1000,
+            screenshot_directory: "screenshots".to_string(),
+            exit_grace_secs: 0,
+            client_priority: ProcessPriority::Normal,
+            launch_stagger_ms: 0,
+            broadcast_chunk_size: 256,
+            broadcast_chunk_pause_ms: 20,
+            monitor_pane: false,
+            monitor_pane_lines_per_host: 100,
+            swallowed_keys: vec![
+                KeyCombination::new(VK_LWIN.0, false, false),
+                KeyCombination::new(VK_RWIN.0, false, false),
+                KeyCombination::new(VK_TAB.0, false, true),
+                KeyCombination::new(VK_ESCAPE.0, false, true),
+                KeyCombination::new(VK_ESCAPE.0, true, false),
+            ],
         };
     }
 }
@@ -153,8 +998,36 @@ impl Default for DaemonConfig {
 #[derive(Serialize, Deserialize)]
 pub struct DaemonConfigOpt {
     pub height: Option<i32>,
+    #[serde(alias = "position")]
+    pub daemon_position: Option<DaemonPosition>,
     pub aspect_ratio_adjustement: Option<f64>,
+    pub fill_direction: Option<FillDirection>,
+    pub min_console_width: Option<i32>,
+    pub min_console_height: Option<i32>,
     pub console_color: Option<u16>,
+    pub on_client_connected: Option<Vec<String>>,
+    pub on_client_exited: Option<Vec<String>>,
+    pub on_control_mode_entered: Option<Vec<String>>,
+    pub on_client_double_clicked: Option<Vec<String>>,
+    pub auto_minimize_daemon: Option<bool>,
+    pub highlight_active_set: Option<bool>,
+    pub focused_client_border_color: Option<u32>,
+    pub idle_lock_secs: Option<u64>,
+    pub enforce_uniform_client_size: Option<bool>,
+    pub forward_mouse_events: Option<bool>,
+    pub client_heartbeat_timeout_secs: Option<u64>,
+    pub remove_unresponsive_clients: Option<bool>,
+    pub keep_alive_interval_ms: Option<u64>,
+    pub screenshot_directory: Option<String>,
+    pub exit_grace_secs: Option<u64>,
+    pub client_priority: Option<ProcessPriority>,
+    pub launch_stagger_ms: Option<u64>,
+    pub broadcast_chunk_size: Option<usize>,
+    pub broadcast_chunk_pause_ms: Option<u64>,
+    pub monitor_pane: Option<bool>,
+    pub monitor_pane_lines_per_host: Option<usize>,
+    // Must remain the last field, as toml requires values to be emitted before tables.
+    pub swallowed_keys: Option<Vec<KeyCombination>>,
 }
 
 impl Default for DaemonConfigOpt {
@@ -168,10 +1041,536 @@ impl From<DaemonConfigOpt> for DaemonConfig {
         let _default = DaemonConfig::default();
         return DaemonConfig {
             height: val.height.unwrap_or(_default.height),
+            daemon_position: val.daemon_position.unwrap_or(_default.daemon_position),
             aspect_ratio_adjustement: val
                 .aspect_ratio_adjustement
                 .unwrap_or(_default.aspect_ratio_adjustement),
+            fill_direction: val.fill_direction.unwrap_or(_default.fill_direction),
+            min_console_width: val.min_console_width.unwrap_or(_default.min_console_width),
+            min_console_height: val
+                .min_console_height
+                .unwrap_or(_default.min_console_height),
             console_color: val.console_color.unwrap_or(_default.console_color),
+            on_client_connected: val
+                .on_client_connected
+                .unwrap_or(_default.on_client_connected),
+            on_client_exited: val.on_client_exited.unwrap_or(_default.on_client_exited),
+            on_control_mode_entered: val
+                .on_control_mode_entered
+                .unwrap_or(_default.on_control_mode_entered),
+            on_client_double_clicked: val
+                .on_client_double_clicked
+                .unwrap_or(_default.on_client_double_clicked),
+            auto_minimize_daemon: val
+                .auto_minimize_daemon
+                .unwrap_or(_default.auto_minimize_daemon),
+            highlight_active_set: val
+                .highlight_active_set
+                .unwrap_or(_default.highlight_active_set),
+            focused_client_border_color: val
+                .focused_client_border_color
+                .or(_default.focused_client_border_color),
+            idle_lock_secs: val.idle_lock_secs.or(_default.idle_lock_secs),
+            enforce_uniform_client_size: val
+                .enforce_uniform_client_size
+                .unwrap_or(_default.enforce_uniform_client_size),
+            forward_mouse_events: val
+                .forward_mouse_events
+                .unwrap_or(_default.forward_mouse_events),
+            client_heartbeat_timeout_secs: val
+                .client_heartbeat_timeout_secs
+                .or(_default.client_heartbeat_timeout_secs),
+            remove_unresponsive_clients: val
+                .remove_unresponsive_clients
+                .unwrap_or(_default.remove_unresponsive_clients),
+            keep_alive_interval_ms: val
+                .keep_alive_interval_ms
+                .unwrap_or(_default.keep_alive_interval_ms),
+            screenshot_directory: val
+                .screenshot_directory
+                .unwrap_or(_default.screenshot_directory),
+            exit_grace_secs: val.exit_grace_secs.unwrap_or(_default.exit_grace_secs),
+            client_priority: val.client_priority.unwrap_or(_default.client_priority),
+            launch_stagger_ms: val.launch_stagger_ms.unwrap_or(_default.launch_stagger_ms),
+            broadcast_chunk_size: val
+                .broadcast_chunk_size
+                .unwrap_or(_default.broadcast_chunk_size),
+            broadcast_chunk_pause_ms: val
+                .broadcast_chunk_pause_ms
+                .unwrap_or(_default.broadcast_chunk_pause_ms),
+            monitor_pane: val.monitor_pane.unwrap_or(_default.monitor_pane),
+            monitor_pane_lines_per_host: val
+                .monitor_pane_lines_per_host
+                .unwrap_or(_default.monitor_pane_lines_per_host),
+            swallowed_keys: val.swallowed_keys.unwrap_or(_default.swallowed_keys),
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_expand_cidr_skips_ipv4_network_and_broadcast_address() {
+        assert_eq!(
+            expand_cidr("10.0.1.0/30", 256),
+            Some((vec!["10.0.1.1".to_owned(), "10.0.1.2".to_owned()], 2))
+        );
+        // Host bits of the given address are ignored.
+        assert_eq!(
+            expand_cidr("10.0.1.6/30", 256),
+            Some((vec!["10.0.1.5".to_owned(), "10.0.1.6".to_owned()], 2))
+        );
+    }
+
+    #[test]
+    fn test_expand_cidr_point_to_point_and_single_host() {
+        assert_eq!(
+            expand_cidr("10.0.1.0/31", 256),
+            Some((vec!["10.0.1.0".to_owned(), "10.0.1.1".to_owned()], 2))
+        );
+        assert_eq!(
+            expand_cidr("10.0.1.7/32", 256),
+            Some((vec!["10.0.1.7".to_owned()], 1))
+        );
+    }
+
+    #[test]
+    fn test_expand_cidr_whole_address_space() {
+        assert_eq!(
+            expand_cidr("0.0.0.0/0", 2),
+            Some((
+                vec!["0.0.0.1".to_owned(), "0.0.0.2".to_owned()],
+                (1 << 32) - 2
+            ))
+        );
+        // 2^128 addresses don't fit, the total saturates.
+        assert_eq!(
+            expand_cidr("::/0", 1),
+            Some((vec!["::".to_owned()], u128::MAX))
+        );
+    }
+
+    #[test]
+    fn test_expand_cidr_ipv6() {
+        assert_eq!(
+            expand_cidr("fd00::5/126", 256),
+            Some((
+                vec![
+                    "fd00::4".to_owned(),
+                    "fd00::5".to_owned(),
+                    "fd00::6".to_owned(),
+                    "fd00::7".to_owned()
+                ],
+                4
+            ))
+        );
+        assert_eq!(
+            expand_cidr("fd00::1/128", 256),
+            Some((vec!["fd00::1".to_owned()], 1))
+        );
+    }
+
+    #[test]
+    fn test_expand_cidr_truncates_to_max_hosts() {
+        assert_eq!(
+            expand_cidr("10.0.0.0/24", 1),
+            Some((vec!["10.0.0.1".to_owned()], 254))
+        );
+    }
+
+    #[test]
+    fn test_expand_cidr_rejects_invalid_networks() {
+        assert_eq!(expand_cidr("10.0.0.0/33", 256), None);
+        assert_eq!(expand_cidr("fd00::/129", 256), None);
+        assert_eq!(expand_cidr("10.0.0.0", 256), None);
+        assert_eq!(expand_cidr("10.0.0.0/", 256), None);
+        assert_eq!(expand_cidr("10.0.0.0/-1", 256), None);
+        assert_eq!(expand_cidr("web/24", 256), None);
+    }
+
+    fn cluster(name: &str, hosts: &[&str]) -> Cluster {
+        return Cluster {
+            name: name.to_owned(),
+            hosts: hosts.iter().map(|host| return host.to_string()).collect(),
+            ..Default::default()
+        };
+    }
+
+    #[test]
+    fn test_resolve_cluster_tags_resolves_nested_clusters() {
+        let clusters = vec![
+            cluster("all", &["web", "db1"]),
+            cluster("web", &["web1", "web2"]),
+        ];
+        assert_eq!(
+            resolve_cluster_tags(vec!["all", "other"], &clusters),
+            vec!["web1", "web2", "db1", "other"]
+        );
+    }
+
+    #[test]
+    fn test_resolve_cluster_tags_keeps_self_reference_as_host() {
+        let clusters = vec![cluster("web", &["web", "web1"])];
+        assert_eq!(
+            resolve_cluster_tags(vec!["web"], &clusters),
+            vec!["web", "web1"]
+        );
+    }
+
+    #[test]
+    fn test_resolve_cluster_tags_breaks_indirect_cycle() {
+        let clusters = vec![
+            cluster("web", &["db", "web1"]),
+            cluster("db", &["web", "db1"]),
+        ];
+        assert_eq!(
+            resolve_cluster_tags(vec!["web"], &clusters),
+            vec!["web", "db1", "web1"]
+        );
+    }
+
+    #[test]
+    fn test_resolve_cluster_tags_resolves_shared_cluster_in_each_branch() {
+        // Reaching a cluster twice on different branches is no cycle.
+        let clusters = vec![
+            cluster("all", &["eu", "us"]),
+            cluster("eu", &["shared", "eu1"]),
+            cluster("us", &["shared", "us1"]),
+            cluster("shared", &["bastion"]),
+        ];
+        assert_eq!(
+            resolve_cluster_tags(vec!["all"], &clusters),
+            vec!["bastion", "eu1", "bastion", "us1"]
+        );
+    }
+
+    #[test]
+    fn test_resolve_cluster_tags_excludes_listed_host() {
+        let clusters = vec![cluster("all", &["web1", "db1", "db2"])];
+        assert_eq!(
+            resolve_cluster_tags(vec!["all", "!db2"], &clusters),
+            vec!["web1", "db1"]
+        );
+        // Exclusions apply regardless of their position.
+        assert_eq!(
+            resolve_cluster_tags(vec!["!db2", "all"], &clusters),
+            vec!["web1", "db1"]
+        );
+    }
+
+    #[test]
+    fn test_resolve_cluster_tags_excludes_nested_cluster_host() {
+        let clusters = vec![
+            cluster("all", &["web", "db1"]),
+            cluster("web", &["web1", "web2"]),
+        ];
+        assert_eq!(
+            resolve_cluster_tags(vec!["all", "!web2"], &clusters),
+            vec!["web1", "db1"]
+        );
+    }
+
+    #[test]
+    fn test_resolve_cluster_tags_excludes_glob() {
+        let clusters = vec![cluster("all", &["web1", "alice@web2:2222", "db1", "db12"])];
+        // A pattern matches the hostname without user and port as well.
+        assert_eq!(
+            resolve_cluster_tags(vec!["all", "!web*"], &clusters),
+            vec!["db1", "db12"]
+        );
+        assert_eq!(
+            resolve_cluster_tags(vec!["all", "!db?"], &clusters),
+            vec!["web1", "alice@web2:2222", "db12"]
+        );
+    }
+
+    #[test]
+    fn test_resolve_cluster_tags_excludes_within_cluster_definition() {
+        let clusters = vec![
+            cluster("all", &["web", "!web2"]),
+            cluster("web", &["web1", "web2"]),
+        ];
+        assert_eq!(
+            resolve_cluster_tags(vec!["all", "web2"], &clusters),
+            vec!["web1", "web2"]
+        );
+    }
+
+    #[test]
+    fn test_deduplicate_hosts_of_overlapping_clusters() {
+        let clusters = vec![cluster("web", &["web1", "web2"])];
+        let hosts = resolve_cluster_tags(vec!["web", "web1"], &clusters);
+        // Duplicates are kept unless deduplicated explicitly.
+        assert_eq!(hosts, vec!["web1", "web2", "web1"]);
+        assert_eq!(deduplicate_hosts(hosts), vec!["web1", "web2"]);
+    }
+
+    #[test]
+    fn test_deduplicate_hosts_keeps_first_occurrence_order() {
+        assert_eq!(
+            deduplicate_hosts(vec!["db1", "web2", "db1", "web1", "web2"]),
+            vec!["db1", "web2", "web1"]
+        );
+        // Hosts with a different user or port are different hosts.
+        assert_eq!(
+            deduplicate_hosts(vec!["web1", "alice@web1", "web1:2222"]),
+            vec!["web1", "alice@web1", "web1:2222"]
+        );
+        assert!(deduplicate_hosts(vec![]).is_empty());
+    }
+
+    #[test]
+    fn test_get_unresolved_cluster_references_flags_undefined_cluster() {
+        let clusters = vec![
+            cluster("web", &["web1.dev", "webservrs"]),
+            cluster("prod", &["web", "db1"]),
+        ];
+        let known_hosts = vec!["db1".to_owned()];
+        let hosts = resolve_cluster_tags(vec!["prod"], &clusters);
+        assert_eq!(hosts, vec!["web1.dev", "webservrs", "db1"]);
+        assert_eq!(
+            get_unresolved_cluster_references(&hosts, &clusters, &known_hosts),
+            vec!["webservrs"]
+        );
+    }
+
+    #[test]
+    fn test_get_unresolved_cluster_references_flags_cycle() {
+        let clusters = vec![cluster("a", &["b"]), cluster("b", &["a", "host.dev"])];
+        let hosts = resolve_cluster_tags(vec!["a"], &clusters);
+        assert_eq!(hosts, vec!["a", "host.dev"]);
+        assert_eq!(
+            get_unresolved_cluster_references(&hosts, &clusters, &[]),
+            vec!["a"]
+        );
+    }
+
+    #[test]
+    fn test_get_unresolved_cluster_references_keeps_host_like_entries() {
+        let hosts = vec![
+            "host.dev",
+            "root@host",
+            "host:2222",
+            "10.0.0.1",
+            "::1",
+            "[::1]:22",
+            "localhost",
+        ];
+        assert!(get_unresolved_cluster_references(&hosts, &[], &[]).is_empty());
+    }
+
+    /// Returns a directory unique to the given test to store layouts in.
+    fn layout_directory(test_name: &str) -> PathBuf {
+        let directory =
+            env::temp_dir().join(format!("csshw-test-{}-{}", test_name, std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        return directory;
+    }
+
+    #[test]
+    fn test_save_and_load_layout() {
+        let directory = layout_directory("save-and-load-layout");
+        let layout_path = directory.join(get_layout_path("test"));
+        let layout = Layout {
+            height: 300,
+            daemon_position: DaemonPosition::Bottom,
+            aspect_ratio_adjustement: 0.5,
+            fill_direction: FillDirection::ColumnMajor,
+            grid: Some((3, 2)),
+            monitor: Some(1),
+            swaps: vec![("web1".to_owned(), "web3".to_owned())],
+            active_sets: Vec::new(),
+        };
+        save_layout_to_path(&layout_path, &layout).unwrap();
+        let loaded_layout = load_layout_from_path(&layout_path, "test");
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(loaded_layout, Ok(layout));
+    }
+
+    #[test]
+    fn test_save_and_load_layout_with_active_sets() {
+        let directory = layout_directory("save-and-load-layout-with-active-sets");
+        let layout_path = directory.join(get_layout_path("test"));
+        let layout = Layout {
+            grid: Some((2, 2)),
+            active_sets: vec![
+                ActiveSet {
+                    name: "web".to_owned(),
+                    hostnames: vec!["web1".to_owned(), "web2".to_owned()],
+                },
+                ActiveSet {
+                    name: "db".to_owned(),
+                    hostnames: vec!["db1".to_owned()],
+                },
+            ],
+            ..Default::default()
+        };
+        save_layout_to_path(&layout_path, &layout).unwrap();
+        let loaded_layout = load_layout_from_path(&layout_path, "test");
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(loaded_layout, Ok(layout));
+    }
+
+    #[test]
+    fn test_apply_layout_swaps() {
+        let layout = Layout {
+            swaps: vec![
+                ("web1".to_owned(), "web3".to_owned()),
+                ("web1".to_owned(), "web2".to_owned()),
+                ("web1".to_owned(), "db1".to_owned()),
+            ],
+            ..Default::default()
+        };
+        let mut hosts = vec!["web1".to_owned(), "web2".to_owned(), "web3".to_owned()];
+        layout.apply_swaps(&mut hosts);
+        assert_eq!(hosts, ["web3", "web1", "web2"]);
+    }
+
+    #[test]
+    fn test_load_missing_layout() {
+        let directory = layout_directory("load-missing-layout");
+        let err = load_layout_from_path(&directory.join(get_layout_path("missing")), "missing")
+            .unwrap_err();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert!(err.starts_with("Layout `missing` does not exist"));
+    }
+
+    #[test]
+    fn test_apply_layout() {
+        let layout = Layout {
+            height: 300,
+            daemon_position: DaemonPosition::Bottom,
+            aspect_ratio_adjustement: 0.5,
+            fill_direction: FillDirection::ColumnMajor,
+            grid: None,
+            monitor: None,
+            swaps: Vec::new(),
+            active_sets: Vec::new(),
+        };
+        let mut config = DaemonConfig::default();
+        layout.apply(&mut config);
+        assert_eq!(Layout::new(&config, None, None), layout);
+    }
+
+    const PREVIOUS_CONFIG: &str = r#"
+[[clusters]]
+name = "web"
+hosts = ["web1", "web2"]
+
+[client]
+ssh_config_path = 'C:\Users\alice\.ssh\config'
+arguments = [
+    "-XY",
+    "{{USERNAME_AT_HOST}}",
+]
+
+[daemon]
+height = 200
+"#;
+
+    const CURRENT_CONFIG: &str = r#"
+[[clusters]]
+name = "web"
+hosts = ["web1", "web2"]
+
+[client]
+program = "ssh"
+ssh_config_path = 'C:\Users\alice\.ssh\config'
+arguments = [
+    "-XY",
+    "{{USERNAME_AT_HOST}}",
+]
+
+[daemon]
+# height = 300
+height = 200
+console_color = 207
+"#;
+
+    #[test]
+    fn test_get_added_config_keys() {
+        assert_eq!(
+            get_added_config_keys(PREVIOUS_CONFIG, CURRENT_CONFIG),
+            vec!["client.program", "daemon.console_color"]
+        );
+    }
+
+    #[test]
+    fn test_get_added_config_keys_of_unchanged_config() {
+        assert!(get_added_config_keys(PREVIOUS_CONFIG, PREVIOUS_CONFIG).is_empty());
+    }
+
+    /// Returns the default config with an existing `ssh_config_path`.
+    fn valid_config() -> Config {
+        let mut config = Config::default();
+        config.client.ssh_config_path = env::temp_dir().to_string_lossy().into_owned();
+        return config;
+    }
+
+    #[test]
+    fn test_validate_config_accepts_valid_config() {
+        assert!(validate_config(&valid_config()).is_empty());
+    }
+
+    #[test]
+    fn test_validate_config_reports_missing_ssh_config() {
+        let mut config = valid_config();
+        config.client.ssh_config_path = env::temp_dir()
+            .join("csshw-test-missing-ssh-config")
+            .to_string_lossy()
+            .into_owned();
+        assert_eq!(
+            validate_config(&config),
+            vec![format!(
+                "client.ssh_config_path: `{}` does not exist",
+                config.client.ssh_config_path
+            )]
+        );
+    }
+
+    #[test]
+    fn test_validate_config_reports_missing_placeholder() {
+        let mut config = valid_config();
+        config.client.arguments = vec!["-XY".to_owned(), "alice@web1".to_owned()];
+        config.client.program_overrides = vec![ProgramOverride {
+            host: "sw*".to_owned(),
+            program: "plink".to_owned(),
+            arguments: vec!["-ssh".to_owned()],
+        }];
+        assert_eq!(
+            validate_config(&config),
+            vec![
+                "client.arguments: missing the username_host_placeholder `{{USERNAME_AT_HOST}}`",
+                "client.program_overrides: arguments for `sw*` are missing `{{USERNAME_AT_HOST}}`"
+            ]
+        );
+        config.client.username_host_placeholder = String::new();
+        assert_eq!(
+            validate_config(&config),
+            vec!["client.username_host_placeholder: must not be empty"]
+        );
+    }
+
+    #[test]
+    fn test_validate_config_reports_invalid_daemon_settings() {
+        let mut config = valid_config();
+        config.daemon.height = 0;
+        config.daemon.console_color = 0x100;
+        assert_eq!(
+            validate_config(&config),
+            vec![
+                "daemon.height: must be positive, is 0",
+                "daemon.console_color: must be at most 255, is 256"
+            ]
+        );
+        config.daemon.height = 200;
+        config.daemon.console_color = 0x77;
+        assert_eq!(
+            validate_config(&config),
+            vec!["daemon.console_color: foreground and background color of 119 are the same"]
+        );
+    }
+}
@@ -1,4 +1,5 @@
 pub const PKG_NAME: &str = env!("CARGO_PKG_NAME");
+pub const CONFIG_PATH: &str = concat!(env!("CARGO_PKG_NAME"), "-config.toml");
 // https://learn.microsoft.com/en-us/windows/win32/ipc/pipe-namess
 pub const PIPE_NAME: &str = concat!(r"\\.\pipe\", env!("CARGO_PKG_NAME"), "-named-pipe-for-ipc");
 pub const DEFAULT_SSH_USERNAME_KEY: &str =
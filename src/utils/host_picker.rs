@@ -0,0 +1,110 @@
+use std::io::{self, Write};
+
+/// Lets the user pick hosts from `hosts` in the terminal.
+///
+/// The (filtered) hosts are listed with their index, the user either selects
+/// some of them by index (e.g. `1 3 5-7`) or enters text to narrow down the list.
+/// Returns no hosts if the user aborts by entering nothing.
+pub fn pick_hosts(hosts: &[String]) -> Vec<String> {
+    let mut filtered_hosts: Vec<&String> = hosts.iter().collect();
+    loop {
+        for (index, host) in filtered_hosts.iter().enumerate() {
+            println!("{:>4}  {}", index + 1, host);
+        }
+        print!(
+            "Select host(s) by index (e.g. `1 3 5-7`), type to filter or leave empty to abort: "
+        );
+        let _ = io::stdout().flush();
+        let mut input = String::new();
+        if io::stdin().read_line(&mut input).is_err() {
+            return Vec::new();
+        }
+        let input = input.trim();
+        if input.is_empty() {
+            return Vec::new();
+        }
+        if let Some(indices) = parse_indices(input, filtered_hosts.len()) {
+            return indices
+                .into_iter()
+                .map(|index| return filtered_hosts[index].clone())
+                .collect();
+        }
+        filtered_hosts = hosts
+            .iter()
+            .filter(|host| return matches_filter(input, host))
+            .collect();
+        if filtered_hosts.is_empty() {
+            println!("No host matches `{}`", input);
+            filtered_hosts = hosts.iter().collect();
+        }
+    }
+}
+
+/// Whether all characters of `filter` appear in `host` in the same order,
+/// ignoring the case, e.g. `wb1` matches `web1.example.com`.
+pub fn matches_filter(filter: &str, host: &str) -> bool {
+    let mut host_characters = host.chars().flat_map(char::to_lowercase);
+    return filter
+        .chars()
+        .flat_map(char::to_lowercase)
+        .all(|filter_character| {
+            return host_characters.any(|host_character| return host_character == filter_character);
+        });
+}
+
+/// Parses the whitespace separated 1-based indices and index ranges (e.g. `5-7`)
+/// into 0-based indices, `None` if `input` isn't a valid selection of `len` entries.
+fn parse_indices(input: &str, len: usize) -> Option<Vec<usize>> {
+    let mut indices: Vec<usize> = Vec::new();
+    for selection in input.split_whitespace() {
+        let (start, end) = match selection.split_once('-') {
+            Some((start, end)) => (start.parse::<usize>().ok()?, end.parse::<usize>().ok()?),
+            None => {
+                let index = selection.parse::<usize>().ok()?;
+                (index, index)
+            }
+        };
+        if start == 0 || start > end || end > len {
+            return None;
+        }
+        indices.extend(start - 1..end);
+    }
+    return Some(indices);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_filter() {
+        assert!(matches_filter("web", "web1.example.com"));
+        assert!(matches_filter("wb1", "web1.example.com"));
+        assert!(matches_filter("WEB", "web1.example.com"));
+        assert!(matches_filter("", "web1.example.com"));
+        assert!(!matches_filter("1bw", "web1.example.com"));
+        assert!(!matches_filter("db", "web1.example.com"));
+        // Every character of the filter needs its own character in the host.
+        assert!(!matches_filter("ww", "web1"));
+    }
+
+    #[test]
+    fn test_parse_indices() {
+        assert_eq!(parse_indices("1", 3), Some(vec![0]));
+        assert_eq!(parse_indices("1 3", 3), Some(vec![0, 2]));
+        assert_eq!(parse_indices("  2-3\t1 ", 3), Some(vec![1, 2, 0]));
+        assert_eq!(parse_indices("2-2", 3), Some(vec![1]));
+    }
+
+    #[test]
+    fn test_parse_indices_rejects_invalid_selections() {
+        // Not a selection, used as filter instead.
+        assert_eq!(parse_indices("web", 3), None);
+        assert_eq!(parse_indices("1 web", 3), None);
+        assert_eq!(parse_indices("0", 3), None);
+        assert_eq!(parse_indices("4", 3), None);
+        assert_eq!(parse_indices("3-1", 3), None);
+        assert_eq!(parse_indices("2-4", 3), None);
+        assert_eq!(parse_indices("1-", 3), None);
+    }
+}
@@ -0,0 +1,123 @@
+use std::fs::File;
+use std::io::Write;
+use std::sync::Mutex;
+
+use chrono::SecondsFormat;
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+/// Logger writing one JSON object (`timestamp`, `level`, `module` and `message`)
+/// per line, for ingestion into log aggregators.
+pub struct JsonLogger {
+    level: LevelFilter,
+    file: Mutex<File>,
+}
+
+impl JsonLogger {
+    /// Installs a [`JsonLogger`] writing all records up to `level` to `file`.
+    pub fn init(level: LevelFilter, file: File) -> Result<(), SetLoggerError> {
+        log::set_max_level(level);
+        return log::set_boxed_logger(Box::new(JsonLogger {
+            level,
+            file: Mutex::new(file),
+        }));
+    }
+}
+
+impl Log for JsonLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        return metadata.level() <= self.level;
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format!(
+            "{{\"timestamp\":\"{}\",\"level\":\"{}\",\"module\":\"{}\",\"message\":\"{}\"}}\n",
+            chrono::offset::Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true),
+            record.level(),
+            escape_json(record.module_path().unwrap_or(record.target())),
+            escape_json(&record.args().to_string())
+        );
+        if let Ok(mut file) = self.file.lock() {
+            let _ = file.write_all(line.as_bytes());
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut file) = self.file.lock() {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Escapes `value` to be used within a JSON string.
+fn escape_json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for character in value.chars() {
+        match character {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            character if (character as u32) < 0x20 => {
+                escaped.push_str(&format!("\\u{:04x}", character as u32));
+            }
+            character => escaped.push(character),
+        }
+    }
+    return escaped;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs::{read_to_string, remove_file};
+
+    #[test]
+    fn test_escape_json() {
+        assert_eq!(escape_json("plain text"), "plain text");
+        assert_eq!(escape_json("say \"hi\""), "say \\\"hi\\\"");
+        assert_eq!(escape_json("C:\\logs"), "C:\\\\logs");
+        assert_eq!(escape_json("a\nb\r\tc"), "a\\nb\\r\\tc");
+        assert_eq!(escape_json("\u{0}\u{1b}"), "\\u0000\\u001b");
+        assert_eq!(escape_json("ünïcødé 🦀"), "ünïcødé 🦀");
+    }
+
+    #[test]
+    fn test_log_writes_one_json_object_per_line() {
+        let path =
+            env::temp_dir().join(format!("csshw-test-json-logger-{}.log", std::process::id()));
+        let logger = JsonLogger {
+            level: LevelFilter::Info,
+            file: Mutex::new(File::create(&path).unwrap()),
+        };
+        logger.log(
+            &Record::builder()
+                .level(log::Level::Info)
+                .module_path(Some("csshw::daemon"))
+                .args(format_args!("Connected to \"web1\"\nready"))
+                .build(),
+        );
+        // Records above the level are discarded.
+        logger.log(
+            &Record::builder()
+                .level(log::Level::Debug)
+                .args(format_args!("discarded"))
+                .build(),
+        );
+        logger.flush();
+        let logs = read_to_string(&path).unwrap();
+        remove_file(&path).unwrap();
+
+        let lines: Vec<&str> = logs.lines().collect();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with("{\"timestamp\":\""));
+        assert!(lines[0].ends_with(concat!(
+            r#""level":"INFO","module":"csshw::daemon","#,
+            r#""message":"Connected to \"web1\"\nready"}"#
+        )));
+    }
+}
@@ -1,15 +1,24 @@
 use log::error;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::iter::Peekable;
+use std::str::Chars;
+use std::sync::Mutex;
 use std::{mem, ptr, thread, time};
 
 use windows::core::HSTRING;
-use windows::Win32::Foundation::{COLORREF, HANDLE, HWND, RECT};
-use windows::Win32::Graphics::Dwm::{DwmSetWindowAttribute, DWMWA_BORDER_COLOR};
+use windows::Win32::Foundation::{COLORREF, FALSE, HANDLE, HWND, RECT, TRUE};
+use windows::Win32::Graphics::Dwm::{
+    DwmSetWindowAttribute, DWMWA_BORDER_COLOR, DWMWA_COLOR_DEFAULT,
+};
+use windows::Win32::Graphics::Gdi::{GetDC, GetDeviceCaps, ReleaseDC, LOGPIXELSY};
 use windows::Win32::System::Console::{
-    FillConsoleOutputAttribute, GetConsoleScreenBufferInfo, GetConsoleWindow, GetStdHandle,
-    ReadConsoleInputW, ScrollConsoleScreenBufferW, SetConsoleCursorPosition,
-    SetConsoleTextAttribute, CHAR_INFO, CONSOLE_CHARACTER_ATTRIBUTES, CONSOLE_SCREEN_BUFFER_INFO,
-    COORD, INPUT_RECORD, INPUT_RECORD_0, SMALL_RECT, STD_HANDLE, STD_INPUT_HANDLE,
-    STD_OUTPUT_HANDLE,
+    FillConsoleOutputAttribute, GetConsoleScreenBufferInfo, GetConsoleWindow,
+    GetCurrentConsoleFontEx, GetStdHandle, ReadConsoleInputW, ScrollConsoleScreenBufferW,
+    SetConsoleCursorPosition, SetConsoleTextAttribute, SetCurrentConsoleFontEx, CHAR_INFO,
+    CONSOLE_CHARACTER_ATTRIBUTES, CONSOLE_FONT_INFOEX, CONSOLE_SCREEN_BUFFER_INFO, COORD,
+    INPUT_RECORD, INPUT_RECORD_0, KEY_EVENT_RECORD, KEY_EVENT_RECORD_0, SMALL_RECT, STD_HANDLE,
+    STD_INPUT_HANDLE, STD_OUTPUT_HANDLE,
 };
 use windows::Win32::UI::WindowsAndMessaging::{
     GetWindowRect, GetWindowTextW, MoveWindow, SetWindowTextW,
@@ -17,11 +26,72 @@ use windows::Win32::UI::WindowsAndMessaging::{
 
 use self::constants::MAX_WINDOW_TITLE_LENGTH;
 
+pub mod ansible;
 pub mod config;
 pub mod constants;
 pub mod debug;
+pub mod host_picker;
+pub mod json_logger;
+pub mod ssh_config;
 
 const KEY_EVENT: u16 = 1;
+const MOUSE_EVENT: u16 = 2;
+const POINTS_PER_INCH: i32 = 72;
+
+/// Splits a `[<user>@]<host>[:<port>]` host specification into its parts.
+///
+/// A colon is only treated as port separator if it is the only one, so IPv6
+/// addresses are kept intact, `[<address>]:<port>` adds a port to those.
+pub fn parse_host_specification(specification: &str) -> (Option<&str>, &str, Option<&str>) {
+    let (user, host_and_port) = match specification.rsplit_once('@') {
+        Some((user, host_and_port)) if !user.is_empty() => (Some(user), host_and_port),
+        _ => (None, specification),
+    };
+    let is_port =
+        |port: &str| return !port.is_empty() && port.chars().all(|c| return c.is_ascii_digit());
+    if let Some(bracketed) = host_and_port.strip_prefix('[') {
+        return match bracketed.split_once("]:") {
+            Some((host, port)) if is_port(port) => (user, host, Some(port)),
+            _ => (user, bracketed.trim_end_matches(']'), None),
+        };
+    }
+    return match host_and_port.split_once(':') {
+        Some((host, port)) if is_port(port) => (user, host, Some(port)),
+        _ => (user, host_and_port, None),
+    };
+}
+
+/// Expands all `%NAME%` environment variables in `text`.
+///
+/// Unknown variables are kept as is.
+pub fn expand_environment_variables(text: &str) -> String {
+    let mut expanded = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('%') {
+        expanded.push_str(&rest[..start]);
+        let after_start = &rest[start + 1..];
+        match after_start.find('%') {
+            Some(end) => match std::env::var(&after_start[..end]) {
+                Ok(value) if end > 0 => {
+                    expanded.push_str(&value);
+                    rest = &after_start[end + 1..];
+                }
+                _ => {
+                    // Not a variable, keep the first `%` and continue at the second one
+                    expanded.push('%');
+                    expanded.push_str(&after_start[..end]);
+                    rest = &after_start[end..];
+                }
+            },
+            None => {
+                expanded.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    expanded.push_str(rest);
+    return expanded;
+}
 
 pub fn print_console_rect() {
     loop {
@@ -98,11 +168,95 @@ pub fn clear_screen() {
     }
 }
 
+/// Converts the font size in points to the font height in pixels at the given dots per inch.
+fn get_font_height_in_pixels(points: i16, dpi: i32) -> i16 {
+    return ((points as i32 * dpi + POINTS_PER_INCH / 2) / POINTS_PER_INCH) as i16;
+}
+
+/// Returns the vertical dots per inch of the screen.
+fn get_screen_dpi() -> i32 {
+    let screen_dc = unsafe { GetDC(HWND(0)) };
+    let dpi = unsafe { GetDeviceCaps(screen_dc, LOGPIXELSY) };
+    unsafe {
+        ReleaseDC(HWND(0), screen_dc);
+    }
+    return dpi;
+}
+
+/// Returns the given console font with the given height in pixels.
+///
+/// The width is left to the console, which picks it according to the font face.
+fn get_font_info_with_size(font_info: CONSOLE_FONT_INFOEX, size: i16) -> CONSOLE_FONT_INFOEX {
+    return CONSOLE_FONT_INFOEX {
+        dwFontSize: COORD { X: 0, Y: size },
+        ..font_info
+    };
+}
+
+/// Sets the size in points of the console font, keeping the font face.
+///
+/// The console takes the font height in pixels, so the size is scaled by the
+/// screen's dots per inch.
+/// The console window would grow or shrink with the font, so its position
+/// and size are restored afterwards.
+pub fn set_console_font_size(points: i16) -> windows::core::Result<()> {
+    let console_output_buffer = get_console_output_buffer();
+    let mut font_info = CONSOLE_FONT_INFOEX {
+        cbSize: mem::size_of::<CONSOLE_FONT_INFOEX>() as u32,
+        ..Default::default()
+    };
+    unsafe { GetCurrentConsoleFontEx(console_output_buffer, FALSE, &mut font_info) }?;
+    let font_info = get_font_info_with_size(
+        font_info,
+        get_font_height_in_pixels(points, get_screen_dpi()),
+    );
+    let mut window_rect = RECT::default();
+    unsafe { GetWindowRect(GetConsoleWindow(), &mut window_rect) }?;
+    unsafe { SetCurrentConsoleFontEx(console_output_buffer, FALSE, &font_info) }?;
+    return unsafe {
+        MoveWindow(
+            GetConsoleWindow(),
+            window_rect.left,
+            window_rect.top,
+            window_rect.right - window_rect.left,
+            window_rect.bottom - window_rect.top,
+            true,
+        )
+    };
+}
+
+/// Border colors set via [`set_window_border_color`] by window handle,
+/// windows without an entry have the default border color.
+static WINDOW_BORDER_COLORS: Mutex<BTreeMap<isize, COLORREF>> = Mutex::new(BTreeMap::new());
+
+/// Returns the number of columns and rows of the visible console window.
+pub fn get_console_window_size() -> windows::core::Result<(i16, i16)> {
+    let mut buffer_info = CONSOLE_SCREEN_BUFFER_INFO::default();
+    unsafe { GetConsoleScreenBufferInfo(get_console_output_buffer(), &mut buffer_info) }?;
+    return Ok(get_window_size(buffer_info.srWindow));
+}
+
+/// Returns the number of columns and rows of the given console `window`,
+/// whose coordinates are inclusive.
+fn get_window_size(window: SMALL_RECT) -> (i16, i16) {
+    return (
+        window.Right - window.Left + 1,
+        window.Bottom - window.Top + 1,
+    );
+}
+
 pub fn set_console_border_color(color: COLORREF) {
+    set_window_border_color(unsafe { GetConsoleWindow() }, color);
+}
+
+/// Sets the border color of the given window.
+/// Not supported on Windows 10, where this does nothing.
+pub fn set_window_border_color(handle: HWND, color: COLORREF) {
+    WINDOW_BORDER_COLORS.lock().unwrap().insert(handle.0, color);
     if !is_windows_10() {
         unsafe {
             DwmSetWindowAttribute(
-                GetConsoleWindow(),
+                handle,
                 DWMWA_BORDER_COLOR,
                 &color as *const COLORREF as *const _,
                 mem::size_of::<COLORREF>() as u32,
@@ -112,6 +266,16 @@ pub fn set_console_border_color(color: COLORREF) {
     }
 }
 
+/// Returns the border color last set for the given window.
+pub fn get_window_border_color(handle: HWND) -> COLORREF {
+    return WINDOW_BORDER_COLORS
+        .lock()
+        .unwrap()
+        .get(&handle.0)
+        .copied()
+        .unwrap_or(COLORREF(DWMWA_COLOR_DEFAULT));
+}
+
 pub fn get_console_title() -> String {
     return get_window_title(unsafe { &GetConsoleWindow() });
 }
@@ -166,6 +330,28 @@ fn read_console_input() -> INPUT_RECORD {
     return input_buffer[0];
 }
 
+/// Returns key down and key up input records typing the given text.
+pub fn get_input_records_for_text(text: &str) -> Vec<INPUT_RECORD_0> {
+    let mut input_records: Vec<INPUT_RECORD_0> = Vec::new();
+    for unicode_char in text.encode_utf16() {
+        for key_down in [TRUE, FALSE] {
+            input_records.push(INPUT_RECORD_0 {
+                KeyEvent: KEY_EVENT_RECORD {
+                    bKeyDown: key_down,
+                    wRepeatCount: 1,
+                    wVirtualKeyCode: 0,
+                    wVirtualScanCode: 0,
+                    uChar: KEY_EVENT_RECORD_0 {
+                        UnicodeChar: unicode_char,
+                    },
+                    dwControlKeyState: 0,
+                },
+            });
+        }
+    }
+    return input_records;
+}
+
 pub fn read_keyboard_input() -> INPUT_RECORD_0 {
     loop {
         let input_record = read_console_input();
@@ -180,6 +366,24 @@ pub fn read_keyboard_input() -> INPUT_RECORD_0 {
     }
 }
 
+/// Reads the next key event, or mouse event if `include_mouse_events` is set.
+pub fn read_keyboard_or_mouse_input(include_mouse_events: bool) -> INPUT_RECORD {
+    loop {
+        let input_record = read_console_input();
+        match input_record.EventType {
+            KEY_EVENT => {
+                return input_record;
+            }
+            MOUSE_EVENT if include_mouse_events => {
+                return input_record;
+            }
+            _ => {
+                continue;
+            }
+        }
+    }
+}
+
 pub fn arrange_console(x: i32, y: i32, width: i32, height: i32) {
     // FIXME: sometimes a daemon or client console isn't being arrange correctly
     // when this simply retrying doesn't solve the issue. Maybe it has something to do
@@ -199,3 +403,257 @@ pub fn is_windows_10() -> bool {
     );
     return major <= 10 && build <= 22000;
 }
+
+/// Compares two strings in natural order, i.e. numeric parts are compared
+/// by their value so `host2` is ordered before `host10`.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a_char), Some(b_char)) if a_char.is_ascii_digit() && b_char.is_ascii_digit() => {
+                let a_number = take_digits(&mut a_chars);
+                let b_number = take_digits(&mut b_chars);
+                let a_value = a_number.trim_start_matches('0');
+                let b_value = b_number.trim_start_matches('0');
+                // Longer numbers (without leading zeros) are greater, equally long ones
+                // compare lexicographically, equal values with fewer leading zeros come first.
+                let ordering = a_value
+                    .len()
+                    .cmp(&b_value.len())
+                    .then_with(|| return a_value.cmp(b_value))
+                    .then_with(|| return a_number.len().cmp(&b_number.len()));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(a_char), Some(b_char)) => {
+                if a_char != b_char {
+                    return a_char.cmp(&b_char);
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
+
+fn take_digits(chars: &mut Peekable<Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(digit) = chars.next_if(|char| return char.is_ascii_digit()) {
+        digits.push(digit);
+    }
+    return digits;
+}
+
+/// Case insensitive match of `text` against `pattern`, supporting the `*` and `?` wildcards.
+///
+/// Runs in `O(pattern.len() * text.len())`: on a mismatch only the most recent `*`
+/// is backtracked to, letting it consume one more character of the text.
+pub fn matches_wildcard_pattern(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.as_bytes();
+    let text = text.as_bytes();
+    let mut pattern_index = 0;
+    let mut text_index = 0;
+    // Position of the last `*` in the pattern and of the text it was matched up to.
+    let mut backtrack: Option<(usize, usize)> = None;
+    while text_index < text.len() {
+        match pattern.get(pattern_index) {
+            Some(b'*') => {
+                backtrack = Some((pattern_index, text_index));
+                pattern_index += 1;
+                continue;
+            }
+            Some(b'?') => {
+                pattern_index += 1;
+                text_index += 1;
+                continue;
+            }
+            Some(pattern_character)
+                if pattern_character.eq_ignore_ascii_case(&text[text_index]) =>
+            {
+                pattern_index += 1;
+                text_index += 1;
+                continue;
+            }
+            _ => {}
+        }
+        match backtrack {
+            Some((star_index, star_text_index)) => {
+                backtrack = Some((star_index, star_text_index + 1));
+                pattern_index = star_index + 1;
+                text_index = star_text_index + 1;
+            }
+            None => return false,
+        }
+    }
+    return pattern[pattern_index..]
+        .iter()
+        .all(|character| return *character == b'*');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_font_info_with_size_keeps_font_face() {
+        let mut face_name = [0u16; 32];
+        for (index, character) in "Consolas".encode_utf16().enumerate() {
+            face_name[index] = character;
+        }
+        let font_info = CONSOLE_FONT_INFOEX {
+            cbSize: mem::size_of::<CONSOLE_FONT_INFOEX>() as u32,
+            nFont: 7,
+            dwFontSize: COORD { X: 8, Y: 16 },
+            FontFamily: 54,
+            FontWeight: 400,
+            FaceName: face_name,
+        };
+        let resized_font_info = get_font_info_with_size(font_info, 24);
+        assert_eq!(resized_font_info.dwFontSize, COORD { X: 0, Y: 24 });
+        assert_eq!(resized_font_info.cbSize, font_info.cbSize);
+        assert_eq!(resized_font_info.nFont, font_info.nFont);
+        assert_eq!(resized_font_info.FontFamily, font_info.FontFamily);
+        assert_eq!(resized_font_info.FontWeight, font_info.FontWeight);
+        assert_eq!(resized_font_info.FaceName, face_name);
+    }
+
+    #[test]
+    fn test_get_font_height_in_pixels() {
+        assert_eq!(get_font_height_in_pixels(12, 96), 16);
+        assert_eq!(get_font_height_in_pixels(12, 144), 24);
+        assert_eq!(get_font_height_in_pixels(10, 96), 13);
+        assert_eq!(get_font_height_in_pixels(0, 96), 0);
+    }
+
+    #[test]
+    fn test_get_window_size() {
+        // A window scrolled down the screen buffer.
+        let window = SMALL_RECT {
+            Left: 0,
+            Top: 250,
+            Right: 119,
+            Bottom: 279,
+        };
+        assert_eq!(get_window_size(window), (120, 30));
+        let window = SMALL_RECT {
+            Left: 5,
+            Top: 0,
+            Right: 5,
+            Bottom: 0,
+        };
+        assert_eq!(get_window_size(window), (1, 1));
+    }
+
+    #[test]
+    fn test_matches_wildcard_pattern() {
+        assert!(matches_wildcard_pattern("web1", "WEB1"));
+        assert!(!matches_wildcard_pattern("web1", "web10"));
+        assert!(matches_wildcard_pattern("web*", "web"));
+        assert!(matches_wildcard_pattern(
+            "*.example.com",
+            "web1.example.com"
+        ));
+        assert!(matches_wildcard_pattern("web?", "web1"));
+        assert!(!matches_wildcard_pattern("web?", "web"));
+        assert!(matches_wildcard_pattern("w*b*1", "web-db1"));
+        assert!(!matches_wildcard_pattern("w*b*1", "web-db2"));
+        assert!(matches_wildcard_pattern("*", ""));
+        assert!(matches_wildcard_pattern("web**", "web1"));
+        assert!(!matches_wildcard_pattern("", "web1"));
+        assert!(matches_wildcard_pattern("*1*1", "web1-db11"));
+    }
+
+    #[test]
+    fn test_matches_wildcard_pattern_many_wildcards() {
+        // Backtracking into every `*` would take exponential time here.
+        let text = "a".repeat(100);
+        assert!(!matches_wildcard_pattern(
+            &format!("{}b", "*a".repeat(20)),
+            &text
+        ));
+        assert!(matches_wildcard_pattern(&"*a".repeat(20), &text));
+    }
+
+    #[test]
+    fn test_parse_host_specification() {
+        assert_eq!(parse_host_specification("web1"), (None, "web1", None));
+        assert_eq!(
+            parse_host_specification("alice@web1"),
+            (Some("alice"), "web1", None)
+        );
+        assert_eq!(
+            parse_host_specification("alice@web1:2222"),
+            (Some("alice"), "web1", Some("2222"))
+        );
+        assert_eq!(
+            parse_host_specification("web1.example.com:22"),
+            (None, "web1.example.com", Some("22"))
+        );
+        // The last `@` separates the user, which may contain an `@` itself.
+        assert_eq!(
+            parse_host_specification("alice@corp@web1"),
+            (Some("alice@corp"), "web1", None)
+        );
+    }
+
+    #[test]
+    fn test_parse_host_specification_ignores_invalid_port() {
+        assert_eq!(
+            parse_host_specification("web1:ssh"),
+            (None, "web1:ssh", None)
+        );
+        assert_eq!(parse_host_specification("web1:"), (None, "web1:", None));
+    }
+
+    #[test]
+    fn test_parse_host_specification_keeps_ipv6_addresses_intact() {
+        assert_eq!(parse_host_specification("fe80::1"), (None, "fe80::1", None));
+        assert_eq!(
+            parse_host_specification("[fe80::1]"),
+            (None, "fe80::1", None)
+        );
+        assert_eq!(
+            parse_host_specification("[fe80::1]:2222"),
+            (None, "fe80::1", Some("2222"))
+        );
+        assert_eq!(
+            parse_host_specification("alice@[::1]:22"),
+            (Some("alice"), "::1", Some("22"))
+        );
+    }
+
+    #[test]
+    fn test_natural_cmp_compares_numbers_by_value() {
+        assert_eq!(natural_cmp("host2", "host10"), Ordering::Less);
+        assert_eq!(natural_cmp("host10", "host2"), Ordering::Greater);
+        assert_eq!(natural_cmp("host10", "host10"), Ordering::Equal);
+        assert_eq!(natural_cmp("web1-db2", "web1-db10"), Ordering::Less);
+        assert_eq!(natural_cmp("10.0.0.9", "10.0.0.10"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_natural_cmp_orders_leading_zeros_after_equal_values() {
+        assert_eq!(natural_cmp("host01", "host1"), Ordering::Greater);
+        assert_eq!(natural_cmp("host01", "host2"), Ordering::Less);
+        assert_eq!(natural_cmp("host0", "host00"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_natural_cmp_orders_prefixes_first() {
+        assert_eq!(natural_cmp("host", "host1"), Ordering::Less);
+        assert_eq!(natural_cmp("", "host"), Ordering::Less);
+        assert_eq!(natural_cmp("hosta", "host1"), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_sorting_hosts_naturally() {
+        let mut hosts = vec!["host10", "host2", "db1", "host1", "host02"];
+        hosts.sort_by(|a, b| return natural_cmp(a, b));
+        assert_eq!(hosts, vec!["db1", "host1", "host2", "host02", "host10"]);
+    }
+}
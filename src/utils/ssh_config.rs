@@ -0,0 +1,218 @@
+use std::fs::read_to_string;
+use std::path::Path;
+
+use crate::utils::matches_wildcard_pattern;
+
+/// Returns the SSH arguments (`-J <jump hosts>` or `-o ProxyCommand=<command>`)
+/// for the `ProxyJump` or `ProxyCommand` the SSH config at `path` defines for `host`.
+///
+/// Returns no arguments if the config can't be read or doesn't define a proxy for `host`.
+pub fn get_proxy_arguments(path: &Path, host: &str) -> Vec<String> {
+    return match read_to_string(path) {
+        Ok(ssh_config) => parse_proxy_arguments(&ssh_config, host),
+        Err(_) => Vec::new(),
+    };
+}
+
+/// Returns the hosts the SSH config at `path` defines `Host` blocks for, in order
+/// of appearance and without duplicates.
+///
+/// Patterns containing wildcards and negated patterns don't name a single host
+/// and are omitted, no hosts are returned if the config can't be read.
+pub fn get_configured_hosts(path: &Path) -> Vec<String> {
+    return match read_to_string(path) {
+        Ok(ssh_config) => parse_configured_hosts(&ssh_config),
+        Err(_) => Vec::new(),
+    };
+}
+
+fn parse_configured_hosts(ssh_config: &str) -> Vec<String> {
+    let mut hosts: Vec<String> = Vec::new();
+    for line in ssh_config.lines() {
+        let (keyword, patterns) = match parse_line(line) {
+            Some(keyword_value) => keyword_value,
+            None => continue,
+        };
+        if !keyword.eq_ignore_ascii_case("host") {
+            continue;
+        }
+        for pattern in patterns.split_whitespace() {
+            if pattern.contains(['*', '?', '!']) || hosts.iter().any(|host| return host == pattern)
+            {
+                continue;
+            }
+            hosts.push(pattern.to_owned());
+        }
+    }
+    return hosts;
+}
+
+/// Splits a line of the SSH config into its keyword and value,
+/// returns `None` for blank lines and comments.
+fn parse_line(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let (keyword, value) =
+        line.split_once(|character: char| return character.is_whitespace() || character == '=')?;
+    let value = value
+        .trim_start_matches(|character: char| {
+            return character.is_whitespace() || character == '=';
+        })
+        .trim();
+    return Some((keyword, value));
+}
+
+/// Like SSH, the first `ProxyJump` or `ProxyCommand` obtained for `host` is used,
+/// they are mutually exclusive. `Match` blocks are not supported and skipped.
+fn parse_proxy_arguments(ssh_config: &str, host: &str) -> Vec<String> {
+    // Parameters before the first `Host` apply to all hosts.
+    let mut host_matches = true;
+    for line in ssh_config.lines() {
+        let (keyword, value) = match parse_line(line) {
+            Some(keyword_value) => keyword_value,
+            None => continue,
+        };
+        match keyword.to_lowercase().as_str() {
+            "host" => host_matches = matches_host_patterns(value, host),
+            "match" => host_matches = false,
+            "proxyjump" | "proxycommand" if host_matches && value.eq_ignore_ascii_case("none") => {
+                return Vec::new();
+            }
+            "proxyjump" if host_matches => {
+                return vec!["-J".to_owned(), value.to_owned()];
+            }
+            "proxycommand" if host_matches => {
+                return vec!["-o".to_owned(), format!("ProxyCommand={}", value)];
+            }
+            _ => {}
+        }
+    }
+    return Vec::new();
+}
+
+/// Whether `host` matches any of the whitespace separated `Host` patterns
+/// and none of the negated (`!`) ones.
+fn matches_host_patterns(patterns: &str, host: &str) -> bool {
+    let mut matches = false;
+    for pattern in patterns.split_whitespace() {
+        match pattern.strip_prefix('!') {
+            Some(negated_pattern) => {
+                if matches_wildcard_pattern(negated_pattern, host) {
+                    return false;
+                }
+            }
+            None => matches |= matches_wildcard_pattern(pattern, host),
+        }
+    }
+    return matches;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SSH_CONFIG: &str = "
+# Jump hosts
+Host bastion
+    HostName bastion.example.com
+
+Host *.internal !legacy.internal
+    ProxyJump alice@bastion:2222
+
+Host legacy.internal
+    ProxyCommand ssh -W %h:%p bastion
+
+Host direct.internal
+    ProxyJump none
+";
+
+    #[test]
+    fn test_parse_proxy_jump() {
+        assert_eq!(
+            parse_proxy_arguments(SSH_CONFIG, "web1.internal"),
+            vec!["-J", "alice@bastion:2222"]
+        );
+        assert_eq!(
+            parse_proxy_arguments(SSH_CONFIG, "WEB1.Internal"),
+            vec!["-J", "alice@bastion:2222"]
+        );
+        assert!(parse_proxy_arguments(SSH_CONFIG, "bastion").is_empty());
+    }
+
+    #[test]
+    fn test_parse_proxy_command() {
+        assert_eq!(
+            parse_proxy_arguments(SSH_CONFIG, "legacy.internal"),
+            vec!["-o", "ProxyCommand=ssh -W %h:%p bastion"]
+        );
+    }
+
+    #[test]
+    fn test_parse_proxy_arguments_first_obtained_value_is_used() {
+        // `direct.internal` also matches `*.internal`, which comes first.
+        assert_eq!(
+            parse_proxy_arguments(SSH_CONFIG, "direct.internal"),
+            vec!["-J", "alice@bastion:2222"]
+        );
+        assert!(parse_proxy_arguments(
+            "Host web1\n  ProxyJump none\nHost *\n  ProxyJump bastion",
+            "web1"
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_parse_proxy_arguments_syntax() {
+        // Parameters before the first `Host` apply to all hosts.
+        assert_eq!(
+            parse_proxy_arguments("ProxyJump=bastion\nHost web1", "web2"),
+            vec!["-J", "bastion"]
+        );
+        assert_eq!(
+            parse_proxy_arguments("Host web1\n\tproxyjump = bastion", "web1"),
+            vec!["-J", "bastion"]
+        );
+        // `Match` blocks are skipped.
+        assert!(parse_proxy_arguments("Match host web1\n  ProxyJump bastion", "web1").is_empty());
+    }
+
+    #[test]
+    fn test_matches_host_patterns() {
+        assert!(matches_host_patterns("web1 web2", "web2"));
+        assert!(matches_host_patterns("web?", "web2"));
+        assert!(!matches_host_patterns("web?", "web10"));
+        assert!(!matches_host_patterns("web* !web2", "web2"));
+        // Negated patterns alone don't match anything.
+        assert!(!matches_host_patterns("!web2", "web1"));
+    }
+
+    #[test]
+    fn test_parse_configured_hosts() {
+        assert_eq!(
+            parse_configured_hosts(SSH_CONFIG),
+            vec!["bastion", "legacy.internal", "direct.internal"]
+        );
+        assert_eq!(
+            parse_configured_hosts("Host web1 web2\nHost=db1\nhost web1\n  Match host db2"),
+            vec!["web1", "web2", "db1"]
+        );
+    }
+
+    #[test]
+    fn test_get_proxy_arguments_reads_the_ssh_config() {
+        let path =
+            std::env::temp_dir().join(format!("csshw-test-ssh-config-{}", std::process::id()));
+        std::fs::write(&path, SSH_CONFIG).unwrap();
+        let proxy_arguments = get_proxy_arguments(&path, "web1.internal");
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(proxy_arguments, vec!["-J", "alice@bastion:2222"]);
+    }
+
+    #[test]
+    fn test_get_proxy_arguments_without_ssh_config() {
+        let path = std::env::temp_dir().join("csshw-test-missing-ssh-config");
+        assert!(get_proxy_arguments(&path, "web1.internal").is_empty());
+    }
+}
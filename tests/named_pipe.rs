@@ -0,0 +1,176 @@
+#![deny(clippy::implicit_return)]
+#![allow(clippy::needless_return)]
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use csshw::daemon::named_pipe_server_routine;
+use csshw::serde::serialization::Serialize;
+use csshw::serde::{KEEP_ALIVE_PACKET, SERIALIZED_INPUT_RECORD_0_LENGTH};
+use csshw::utils::constants::PIPE_NAME;
+use csshw::utils::get_input_records_for_text;
+use tokio::net::windows::named_pipe::{ClientOptions, NamedPipeClient, PipeMode, ServerOptions};
+use tokio::sync::broadcast::{self, Receiver};
+use tokio::task::JoinHandle;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Starts the daemon's named pipe server routine forwarding the records received
+/// by `receiver` on a pipe unique to the test and returns a stub client connected to it.
+async fn start_named_pipe_server_routine(
+    test_name: &str,
+    mut receiver: Receiver<[u8; SERIALIZED_INPUT_RECORD_0_LENGTH]>,
+    keep_alive_interval: Duration,
+) -> (NamedPipeClient, JoinHandle<()>) {
+    let pipe_name = format!("{}-{}-{}", PIPE_NAME, std::process::id(), test_name);
+    let server = ServerOptions::new()
+        .access_outbound(true)
+        .pipe_mode(PipeMode::Message)
+        .create(&pipe_name)
+        .unwrap();
+    let client = ClientOptions::new().open(&pipe_name).unwrap();
+    server.connect().await.unwrap();
+    let routine = tokio::spawn(async move {
+        named_pipe_server_routine(
+            &server,
+            &mut receiver,
+            Arc::new(Mutex::new(BTreeMap::new())),
+            std::process::id(),
+            keep_alive_interval,
+            None,
+        )
+        .await;
+    });
+    return (client, routine);
+}
+
+/// Returns the serialized input records typing the given text.
+fn serialize_text(text: &str) -> Vec<[u8; SERIALIZED_INPUT_RECORD_0_LENGTH]> {
+    return get_input_records_for_text(text)
+        .iter()
+        .map(|input_record| {
+            return input_record.serialize().into_vec().try_into().unwrap();
+        })
+        .collect();
+}
+
+/// Reads from the client until `len` bytes other than keep-alive packets were received.
+async fn read_input_records(client: &NamedPipeClient, len: usize) -> Vec<u8> {
+    let mut received: Vec<u8> = Vec::new();
+    let mut buf = [0; SERIALIZED_INPUT_RECORD_0_LENGTH * 10];
+    while received.len() < len {
+        client.readable().await.unwrap();
+        match client.try_read(&mut buf) {
+            Ok(n) => received.extend(&buf[..n]),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => continue,
+            Err(err) => panic!("Failed to read from the named pipe: {}", err),
+        }
+        received = received
+            .chunks(SERIALIZED_INPUT_RECORD_0_LENGTH)
+            .filter(|chunk| return *chunk != KEEP_ALIVE_PACKET)
+            .flatten()
+            .copied()
+            .collect();
+    }
+    return received;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_broadcasted_input_records_reach_the_client_in_order() {
+    let (sender, receiver) = broadcast::channel(1024);
+    let (client, routine) =
+        start_named_pipe_server_routine("in-order", receiver, Duration::from_secs(60)).await;
+    let serialized_input_records = serialize_text("The quick brown fox jumps over the lazy dog\r");
+    for serialized_input_record in &serialized_input_records {
+        sender.send(*serialized_input_record).unwrap();
+    }
+
+    let received = tokio::time::timeout(
+        TIMEOUT,
+        read_input_records(
+            &client,
+            serialized_input_records.len() * SERIALIZED_INPUT_RECORD_0_LENGTH,
+        ),
+    )
+    .await
+    .expect("Timed out waiting for the input records");
+
+    assert_eq!(received, serialized_input_records.concat());
+    routine.abort();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_idle_client_receives_keep_alive_packets() {
+    let (_sender, receiver) = broadcast::channel(1024);
+    let (client, routine) =
+        start_named_pipe_server_routine("keep-alive", receiver, Duration::from_millis(10)).await;
+
+    let mut buf = [0; SERIALIZED_INPUT_RECORD_0_LENGTH];
+    let n = tokio::time::timeout(TIMEOUT, async {
+        loop {
+            client.readable().await.unwrap();
+            match client.try_read(&mut buf) {
+                Ok(n) => return n,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(err) => panic!("Failed to read from the named pipe: {}", err),
+            }
+        }
+    })
+    .await
+    .expect("Timed out waiting for a keep-alive packet");
+
+    assert_eq!(buf[..n], KEEP_ALIVE_PACKET);
+    routine.abort();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_routine_stops_once_the_client_disconnected() {
+    let (_sender, receiver) = broadcast::channel(1024);
+    let (client, routine) =
+        start_named_pipe_server_routine("disconnect", receiver, Duration::from_millis(10)).await;
+
+    drop(client);
+
+    tokio::time::timeout(TIMEOUT, routine)
+        .await
+        .expect("Routine kept running after the client disconnected")
+        .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_lagging_client_stays_attached() {
+    let (sender, receiver) = broadcast::channel(4);
+    // Overflow the channel before the routine starts receiving.
+    let missed_input_records = serialize_text("abcde");
+    for serialized_input_record in &missed_input_records {
+        sender.send(*serialized_input_record).unwrap();
+    }
+    let (client, routine) =
+        start_named_pipe_server_routine("lagging", receiver, Duration::from_secs(60)).await;
+
+    // Only the records still held by the channel are forwarded.
+    let received = tokio::time::timeout(
+        TIMEOUT,
+        read_input_records(&client, 4 * SERIALIZED_INPUT_RECORD_0_LENGTH),
+    )
+    .await
+    .expect("Timed out waiting for the input records");
+    assert_eq!(received, missed_input_records[6..].concat());
+
+    let later_input_records = serialize_text("fg");
+    for serialized_input_record in &later_input_records {
+        sender.send(*serialized_input_record).unwrap();
+    }
+    let received = tokio::time::timeout(
+        TIMEOUT,
+        read_input_records(
+            &client,
+            later_input_records.len() * SERIALIZED_INPUT_RECORD_0_LENGTH,
+        ),
+    )
+    .await
+    .expect("Timed out waiting for the input records");
+    assert_eq!(received, later_input_records.concat());
+    routine.abort();
+}